@@ -0,0 +1,191 @@
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Requests sent by a client before reading their replies
+const PIPELINE: usize = 16;
+
+/// Keys the clients write to, spread over all the shards of the keyspace
+const KEYS: usize = 10_000;
+
+/// Pipelined requests sent by the clients
+struct Workload {
+    name: &'static str,
+    /// Builds a request on two keys
+    request: fn(&str, &str) -> String,
+    /// Length of the reply to each request
+    reply_len: usize,
+}
+
+/// Single key writes, which the cores run on their own shards
+const SET: Workload = Workload {
+    name: "set_pipelined",
+    request: |key, _| {
+        format!(
+            "*3\r\n$3\r\nSET\r\n${}\r\n{}\r\n$5\r\nvalue\r\n",
+            key.len(),
+            key
+        )
+    },
+    reply_len: b"+OK\r\n".len(),
+};
+
+/// Reads of two keys, which usually belong to different cores that lend their
+/// shards to the connection
+const EXISTS: Workload = Workload {
+    name: "exists_two_keys_pipelined",
+    request: |a, b| {
+        format!(
+            "*3\r\n$6\r\nEXISTS\r\n${}\r\n{}\r\n${}\r\n{}\r\n",
+            a.len(),
+            a,
+            b.len(),
+            b
+        )
+    },
+    reply_len: b":0\r\n".len(),
+};
+
+/// Server process killed once the benchmark is done.
+struct Server {
+    process: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(args: &[&str]) -> Self {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_redis-clone"))
+            .args(["--port", &port.to_string()])
+            .args(["--dir", &std::env::temp_dir().to_string_lossy()])
+            .args(["--dbfilename", "redis-clone-bench.rdb"])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("failed to start the server");
+
+        // wait for the server to listen
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Self { process, port }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+    }
+}
+
+/// Sends `requests` pipelined requests of `workload` on keys starting at `seed`
+/// and waits for the replies.
+async fn run_client(stream: &mut TcpStream, workload: &Workload, requests: u64, seed: usize) {
+    let mut replies = vec![0; PIPELINE * workload.reply_len];
+    let mut sent = 0;
+
+    while sent < requests {
+        let mut batch = vec![];
+
+        for i in 0..PIPELINE {
+            let index = seed + sent as usize + i;
+            let key = format!("key:{}", index * 7919 % KEYS);
+            let other = format!("key:{}", (index + 1) * 7919 % KEYS);
+
+            batch.extend((workload.request)(&key, &other).as_bytes());
+        }
+
+        stream.write_all(&batch).await.unwrap();
+        stream.read_exact(&mut replies).await.unwrap();
+
+        sent += PIPELINE as u64;
+    }
+}
+
+/// Measures the throughput of `workload` on the server started with `args` as
+/// the number of clients grows.
+fn bench_clients(c: &mut Criterion, workload: &'static Workload, suffix: &str, args: &[&str]) {
+    let server = Server::start(args);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group(format!("{}{}", workload.name, suffix));
+    group.throughput(Throughput::Elements(1));
+
+    for clients in [1, 4, 16, 64] {
+        // connections wait here between the samples
+        let mut idle = runtime.block_on(async {
+            let mut streams = vec![];
+
+            for _ in 0..clients {
+                let stream = TcpStream::connect(("127.0.0.1", server.port))
+                    .await
+                    .unwrap();
+                stream.set_nodelay(true).unwrap();
+                streams.push(stream);
+            }
+
+            streams
+        });
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(clients),
+            &clients,
+            |b, &clients| {
+                b.iter_custom(|iters| {
+                    runtime.block_on(async {
+                        let streams = std::mem::take(&mut idle);
+                        let requests = (iters / clients).max(1);
+
+                        let start = Instant::now();
+
+                        // every client takes its stream and hands it back for the next sample
+                        let tasks: Vec<_> = streams
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, mut stream)| {
+                                let seed = i * KEYS / clients as usize;
+
+                                tokio::spawn(async move {
+                                    run_client(&mut stream, workload, requests, seed).await;
+                                    stream
+                                })
+                            })
+                            .collect();
+
+                        let mut returned = vec![];
+
+                        for task in tasks {
+                            returned.push(task.await.unwrap());
+                        }
+
+                        let elapsed = start.elapsed();
+                        idle = returned;
+
+                        elapsed
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn scaling_with_clients(c: &mut Criterion) {
+    for workload in [&SET, &EXISTS] {
+        bench_clients(c, workload, "", &[]);
+        bench_clients(c, workload, "_thread_per_core", &["--thread-per-core"]);
+    }
+}
+
+criterion_group!(benches, scaling_with_clients);
+criterion_main!(benches);
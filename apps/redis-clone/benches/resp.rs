@@ -0,0 +1,67 @@
+use bytes::BytesMut;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+#[path = "../src/resp/mod.rs"]
+#[allow(dead_code, unused_imports)]
+mod resp;
+
+use resp::{Decoder, ProtocolLimits};
+
+/// Encodes `SET key <value>` with a value of `len` bytes.
+fn set_request(len: usize) -> Vec<u8> {
+    let mut request = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n".to_vec();
+    request.extend(format!("${}\r\n", len).as_bytes());
+    request.resize(request.len() + len, b'v');
+    request.extend(b"\r\n");
+    request
+}
+
+fn decode_large_values(c: &mut Criterion) {
+    let request = set_request(1024 * 1024);
+
+    let mut group = c.benchmark_group("decode_set_1mb");
+    group.throughput(Throughput::Bytes(request.len() as u64));
+
+    // the chunk size simulates how much of the request each socket read returns
+    for chunk in [request.len(), 64 * 1024, 4 * 1024] {
+        group.bench_with_input(BenchmarkId::from_parameter(chunk), &chunk, |b, &chunk| {
+            b.iter(|| {
+                let mut decoder = Decoder::new(ProtocolLimits::default());
+                let mut buf = BytesMut::with_capacity(chunk);
+
+                for data in request.chunks(chunk) {
+                    buf.extend_from_slice(data);
+
+                    if let Ok(value) = decoder.decode(&mut buf) {
+                        return value;
+                    }
+                }
+
+                unreachable!("the request is complete")
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn decode_small_values(c: &mut Criterion) {
+    let request = set_request(16).repeat(1000);
+
+    let mut group = c.benchmark_group("decode_set_16b_pipeline");
+    group.throughput(Throughput::Bytes(request.len() as u64));
+
+    group.bench_function("1000", |b| {
+        b.iter(|| {
+            let mut decoder = Decoder::new(ProtocolLimits::default());
+            let mut buf = BytesMut::from(&request[..]);
+
+            while decoder.decode(&mut buf).is_ok() {}
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode_large_values, decode_small_values);
+criterion_main!(benches);
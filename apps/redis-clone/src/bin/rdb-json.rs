@@ -0,0 +1,91 @@
+//! Exports the keys of an RDB file as JSON Lines and imports them back, for
+//! migrations and fixtures. The format is described in `src/rdb/json.rs`.
+//!
+//! ```bash
+//! cargo run --bin rdb-json -- export dump.rdb > dump.jsonl
+//! cargo run --bin rdb-json -- import dump.jsonl --output dump.rdb
+//! ```
+
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+
+use redis_clone::rdb::json;
+use redis_clone::rdb::writer::RDBWriter;
+use redis_clone::rdb::RDBParser;
+
+/// Convert RDB files to and from JSON Lines
+#[derive(clap::Parser, Debug)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Write the keys of an RDB file as JSON Lines, expired keys included
+    Export {
+        file: PathBuf,
+        /// Defaults to the standard output
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write an RDB file from keys as JSON Lines
+    Import {
+        file: PathBuf,
+        #[clap(long, default_value = "dump.rdb")]
+        output: PathBuf,
+    },
+}
+
+async fn export(file: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let rdb = std::fs::read(&file).with_context(|| format!("reading {}", file.display()))?;
+
+    let output: Box<dyn Write> = match output {
+        Some(output) => Box::new(File::create(output)?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut output = BufWriter::new(output);
+
+    let mut parser = RDBParser::new(Cursor::new(rdb));
+    parser.read_header().await?;
+
+    while let Some(entry) = parser.next_entry().await? {
+        writeln!(output, "{}", json::to_json(&entry))?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+fn import(file: PathBuf, output: PathBuf) -> anyhow::Result<()> {
+    let jsonl =
+        std::fs::read_to_string(&file).with_context(|| format!("reading {}", file.display()))?;
+
+    let mut writer = RDBWriter::new();
+
+    for (i, line) in jsonl.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = json::parse(line).with_context(|| format!("line {}", i + 1))?;
+        writer.write_entry(&entry);
+    }
+
+    std::fs::write(&output, writer.finish())
+        .with_context(|| format!("writing {}", output.display()))?;
+
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    match Cli::parse().command {
+        Command::Export { file, output } => export(file, output).await,
+        Command::Import { file, output } => import(file, output),
+    }
+}
@@ -0,0 +1,276 @@
+//! Checks an RDB file without loading it into a server, in the spirit of
+//! `redis-check-rdb`: validates its structure and checksum, describes every key
+//! and sums the keys up by type.
+//!
+//! ```bash
+//! cargo run --bin redis-check-rdb -- dump.rdb
+//! cargo run --bin redis-check-rdb -- dump.rdb --json | jq .summary
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use bytes::Bytes;
+use clap::Parser;
+use serde_json::{json, Value};
+
+use redis_clone::rdb::{RDBParser, RDBParsingError, RdbEntry, RdbValue};
+
+/// Check an RDB file and describe its keys
+#[derive(clap::Parser, Debug)]
+struct Cli {
+    /// Path of the RDB file
+    file: PathBuf,
+    /// Print a single JSON document instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+/// Description of a key read from the file, without its value
+struct Key {
+    db: usize,
+    /// Key with the bytes that aren't printable ASCII escaped
+    name: String,
+    value_type: &'static str,
+    encoding: &'static str,
+    /// Number of elements, 1 for strings
+    len: usize,
+    /// Bytes taken by the key and its value in the file
+    size: u64,
+    /// Bytes of the key and of the elements once decoded, without the overhead
+    /// of the structures holding them
+    memory: usize,
+    expires_at: Option<u64>,
+}
+
+impl Key {
+    fn new(entry: &RdbEntry) -> Self {
+        let (value_type, len, data) = match &entry.value {
+            RdbValue::String(data) => ("string", 1, data.len()),
+            RdbValue::List(elements) => ("list", elements.len(), total_len(elements)),
+            RdbValue::Set(members) => ("set", members.len(), total_len(members)),
+            RdbValue::SortedSet(members) => (
+                "zset",
+                members.len(),
+                members.iter().map(|(m, _)| m.len() + 8).sum(),
+            ),
+            RdbValue::Hash(pairs) => (
+                "hash",
+                pairs.len(),
+                pairs.iter().map(|(f, v)| f.len() + v.len()).sum(),
+            ),
+            RdbValue::Stream { entries, .. } => (
+                "stream",
+                entries.len(),
+                entries
+                    .iter()
+                    .flat_map(|(_, fields)| fields)
+                    .map(|(f, v)| 16 + f.len() + v.len())
+                    .sum(),
+            ),
+        };
+
+        Self {
+            db: entry.db,
+            name: entry.key.escape_ascii().to_string(),
+            value_type,
+            encoding: entry.encoding,
+            len,
+            size: entry.size,
+            memory: entry.key.len() + data,
+            expires_at: entry.expires_at,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "db": self.db,
+            "key": self.name,
+            "type": self.value_type,
+            "encoding": self.encoding,
+            "len": self.len,
+            "size": self.size,
+            "memory": self.memory,
+            "expires_at": self.expires_at,
+        })
+    }
+}
+
+fn total_len(elements: &[Bytes]) -> usize {
+    elements.iter().map(|e| e.len()).sum()
+}
+
+/// Keys, bytes in the file and memory of the keys of a type
+#[derive(Debug, Default)]
+struct Totals {
+    keys: usize,
+    size: u64,
+    memory: usize,
+}
+
+impl Totals {
+    fn add(&mut self, key: &Key) {
+        self.keys += 1;
+        self.size += key.size;
+        self.memory += key.memory;
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "keys": self.keys, "size": self.size, "memory": self.memory })
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    version: Option<u32>,
+    keys: Vec<Key>,
+    /// The keys after the first error can't be read
+    error: Option<RDBParsingError>,
+}
+
+impl Report {
+    async fn check(rdb: Vec<u8>) -> Self {
+        let mut report = Report::default();
+
+        if let Err(e) = report.read(rdb).await {
+            report.error = Some(e);
+        }
+
+        report
+    }
+
+    async fn read(&mut self, rdb: Vec<u8>) -> Result<(), RDBParsingError> {
+        let mut parser = RDBParser::new(Cursor::new(rdb));
+        self.version = Some(parser.read_header().await?);
+
+        while let Some(entry) = parser.next_entry().await? {
+            self.keys.push(Key::new(&entry));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the totals by type and of every key.
+    fn summary(&self) -> (BTreeMap<&'static str, Totals>, Totals) {
+        let mut types: BTreeMap<_, Totals> = BTreeMap::new();
+        let mut total = Totals::default();
+
+        for key in &self.keys {
+            types.entry(key.value_type).or_default().add(key);
+            total.add(key);
+        }
+
+        (types, total)
+    }
+
+    fn print(&self) {
+        if let Some(version) = self.version {
+            println!("RDB version {}", version);
+        }
+
+        for key in &self.keys {
+            let expires_at = key
+                .expires_at
+                .map_or("-".to_string(), |expires_at| expires_at.to_string());
+
+            println!(
+                "db={} key=\"{}\" type={} encoding={} len={} size={} memory={} expires_at={}",
+                key.db,
+                key.name,
+                key.value_type,
+                key.encoding,
+                key.len,
+                key.size,
+                key.memory,
+                expires_at
+            );
+        }
+
+        let (types, total) = self.summary();
+
+        println!("--- summary ---");
+        for (value_type, totals) in types.iter().chain([(&"total", &total)]) {
+            println!(
+                "{:<8} keys={} size={} memory={}",
+                value_type, totals.keys, totals.size, totals.memory
+            );
+        }
+
+        match &self.error {
+            Some(e) => println!("RDB is invalid: {}", e),
+            None => println!("RDB looks OK"),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let (types, total) = self.summary();
+        let types: serde_json::Map<_, _> = types
+            .iter()
+            .map(|(value_type, totals)| (value_type.to_string(), totals.to_json()))
+            .collect();
+
+        json!({
+            "version": self.version,
+            "keys": self.keys.iter().map(Key::to_json).collect::<Vec<_>>(),
+            "summary": { "types": types, "total": total.to_json() },
+            "error": self.error.as_ref().map(|e| e.to_string()),
+        })
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let rdb = match std::fs::read(&cli.file) {
+        Ok(rdb) => rdb,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", cli.file.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let report = Report::check(rdb).await;
+
+    if cli.json {
+        println!("{}", report.to_json());
+    } else {
+        report.print();
+    }
+
+    match report.error {
+        Some(_) => ExitCode::FAILURE,
+        None => ExitCode::SUCCESS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_report_stops_at_first_error() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0x00, 0x02, b'a', 0xFF, 0x01, b'0']);
+        rdb.extend([0x02, 0x01, b's', 0x02, 0x01, b'x', 0x01, b'y']);
+        rdb.extend([0x00, 0x01, b'b', 0x05, b'v']);
+
+        let report = Report::check(rdb).await;
+        let (types, total) = report.summary();
+
+        assert_eq!(report.version, Some(11));
+        assert_eq!(report.keys[0].name, "a\\xff");
+        assert_eq!((types["set"].keys, types["set"].memory), (1, 3));
+        assert_eq!((total.keys, total.size), (2, 14));
+        assert!(matches!(
+            report.error,
+            Some(RDBParsingError::InvalidRDBFile(_))
+        ));
+
+        let json = report.to_json();
+        assert_eq!(json["keys"][1]["encoding"], "hashtable");
+        assert_eq!(json["summary"]["types"]["string"]["keys"], 1);
+    }
+}
@@ -1,33 +1,55 @@
+use bytes::Bytes;
+
 use crate::{next_arg, resp::RespValue};
 
 use super::CommandTrait;
 
 pub enum Config {
     Get(String),
+    Set(String, String),
 }
 
 impl CommandTrait for Config {
     async fn execute(&self, db: &crate::db::Db) -> Option<RespValue> {
-        let config = db.config().await;
+        let response = match self {
+            Config::Get(param) => Self::get(param, db),
+            Config::Set(param, value) => match db.set_config(param, value) {
+                Ok(()) => RespValue::SimpleString("OK".to_string()),
+                Err(e) => RespValue::SimpleError(format!("ERR {}", e)),
+            },
+        };
+
+        Some(response)
+    }
+}
+
+impl Config {
+    fn get(param: &str, db: &crate::db::Db) -> RespValue {
+        let config = db.config();
 
         let persistence = config.persistence();
 
-        let value: Option<RespValue> = match self {
-            Config::Get(param) => match param.as_str() {
-                "dir" => Some(persistence.dir().to_str().unwrap().into()),
-                "dbfilename" => Some(persistence.dbfilename().into()),
-                _ => None,
+        let value = match param {
+            "dir" => persistence.dir().to_str().unwrap().to_string(),
+            "dbfilename" => persistence.dbfilename().to_string(),
+            "hz" => config.hz().to_string(),
+            "notify-keyspace-events" => config.notify_keyspace_events().to_string(),
+            "proto-max-bulk-len" => config.protocol_limits().max_bulk_len.to_string(),
+            "client-query-buffer-limit" => config.protocol_limits().query_buffer_limit.to_string(),
+            "maxmemory" => config.maxmemory().to_string(),
+            "maxmemory-policy" => config.maxmemory_policy().to_string(),
+            "maxmemory-samples" => config.maxmemory_samples().to_string(),
+            "databases" => config.databases().to_string(),
+            _ => match config.encoding_limits().get(param) {
+                Some(limit) => limit,
+                None => return RespValue::SimpleError("ERR parameter not supported".into()),
             },
         };
 
-        if value.is_none() {
-            return Some(RespValue::SimpleError("ERR parameter not supported".into()));
-        }
-
-        Some(RespValue::Array(vec![
-            RespValue::BulkString("dir".into()),
-            value.unwrap().as_bulk().unwrap(),
-        ]))
+        RespValue::Map(vec![(
+            RespValue::BulkString(Bytes::copy_from_slice(param.as_bytes())),
+            RespValue::BulkString(value.into()),
+        )])
     }
 }
 
@@ -46,6 +68,11 @@ impl TryFrom<Vec<RespValue>> for Config {
                 let param = next_arg!(args)?;
                 Ok(Self::Get(param))
             }
+            "set" => {
+                let param: String = next_arg!(args)?;
+                let value = next_arg!(args)?;
+                Ok(Self::Set(param.to_lowercase(), value))
+            }
             _ => Err(anyhow::anyhow!("Not Implemented")),
         }
     }
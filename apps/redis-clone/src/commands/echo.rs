@@ -1,22 +1,22 @@
+use bytes::Bytes;
+
 use crate::{next_arg, resp::RespValue};
 
 use super::CommandTrait;
 
 pub struct Echo {
-    message: String,
+    message: Bytes,
 }
 
 impl Echo {
-    pub fn new(message: String) -> Self {
+    pub fn new(message: Bytes) -> Self {
         Self { message }
     }
 }
 
 impl CommandTrait for Echo {
     async fn execute(&self, _: &crate::db::Db) -> Option<RespValue> {
-        Some(crate::resp::RespValue::BulkString(
-            self.message.as_bytes().to_vec(),
-        ))
+        Some(crate::resp::RespValue::BulkString(self.message.clone()))
     }
 
     // fn from_resp(resp: crate::resp::RespValue) -> Result<Self, anyhow::Error> {
@@ -32,6 +32,6 @@ impl TryFrom<Vec<crate::resp::RespValue>> for Echo {
 
         let message = next_arg!(args)?;
 
-        return Ok(Self::new(message));
+        Ok(Self::new(message))
     }
 }
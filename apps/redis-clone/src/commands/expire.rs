@@ -0,0 +1,147 @@
+use std::sync::OnceLock;
+
+use bytes::Bytes;
+
+use crate::db::{Db, ExpireCondition};
+use crate::next_arg;
+use crate::resp::RespValue;
+use crate::utils::unix_millis;
+
+use super::CommandTrait;
+
+/// Expiration given to a command, in milliseconds
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ExpireTime {
+    /// Absolute UNIX time
+    At(i64),
+    /// Time from when the command runs, which for a command queued by `MULTI`
+    /// is when `EXEC` runs rather than when it was queued
+    In(i64),
+}
+
+impl ExpireTime {
+    /// Returns the absolute UNIX time, `None` if it overflows.
+    pub(crate) fn resolve(self) -> Option<i64> {
+        match self {
+            ExpireTime::At(at) => Some(at),
+            ExpireTime::In(time) => time.checked_add(unix_millis() as i64),
+        }
+    }
+}
+
+/// `EXPIRE`, `PEXPIRE`, `EXPIREAT` and `PEXPIREAT` commands
+pub struct Expire {
+    pub(crate) key: Bytes,
+    time: ExpireTime,
+    condition: ExpireCondition,
+    /// Name of the command, for errors
+    command: String,
+    /// Absolute time the command resolved its expiration to, once executed
+    at: OnceLock<i64>,
+}
+
+impl Expire {
+    /// Every command of the family is propagated as `PEXPIREAT` with the time it
+    /// resolved to, so that replicas expire the key at the same moment
+    /// regardless of replication lag.
+    pub(crate) fn propagation(&self) -> Option<RespValue> {
+        let at = self.at.get()?;
+
+        let mut args = vec![
+            RespValue::BulkString(Bytes::from_static(b"PEXPIREAT")),
+            RespValue::BulkString(self.key.clone()),
+            RespValue::BulkString(at.to_string().into()),
+        ];
+
+        let flags = [
+            (self.condition.nx, "NX"),
+            (self.condition.xx, "XX"),
+            (self.condition.gt, "GT"),
+            (self.condition.lt, "LT"),
+        ];
+
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            args.push(RespValue::BulkString(Bytes::copy_from_slice(
+                flag.as_bytes(),
+            )));
+        }
+
+        Some(RespValue::Array(args))
+    }
+}
+
+impl CommandTrait for Expire {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let Some(at) = self.time.resolve() else {
+            return Some(RespValue::SimpleError(format!(
+                "ERR invalid expire time in '{}' command",
+                self.command
+            )));
+        };
+
+        let at = *self.at.get_or_init(|| at);
+        let updated = db.expire(&self.key, at, self.condition).await;
+
+        Some(RespValue::Integer(updated as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Expire {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+        let command = command.to_lowercase();
+
+        let key = next_arg!(args)?;
+        let time: i64 = next_arg!(args)?;
+
+        let time = match command.as_str() {
+            "expire" => time.checked_mul(1000).map(ExpireTime::In),
+            "pexpire" => Some(ExpireTime::In(time)),
+            "expireat" => time.checked_mul(1000).map(ExpireTime::At),
+            "pexpireat" => Some(ExpireTime::At(time)),
+            _ => return Err(anyhow::anyhow!("Invalid command")),
+        }
+        .ok_or(anyhow::anyhow!(
+            "invalid expire time in '{}' command",
+            command
+        ))?;
+
+        let mut condition = ExpireCondition::default();
+
+        for flag in args {
+            let flag = String::try_from(flag)?;
+
+            match flag.to_lowercase().as_str() {
+                "nx" => condition.nx = true,
+                "xx" => condition.xx = true,
+                "gt" => condition.gt = true,
+                "lt" => condition.lt = true,
+                _ => return Err(anyhow::anyhow!("Unsupported option {}", flag)),
+            }
+        }
+
+        if condition.nx && (condition.xx || condition.gt || condition.lt) {
+            return Err(anyhow::anyhow!(
+                "NX and XX, GT or LT options at the same time are not compatible"
+            ));
+        }
+
+        if condition.gt && condition.lt {
+            return Err(anyhow::anyhow!(
+                "GT and LT options at the same time are not compatible"
+            ));
+        }
+
+        Ok(Self {
+            key,
+            time,
+            condition,
+            command,
+            at: OnceLock::new(),
+        })
+    }
+}
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::db::Db;
 use crate::next_arg;
 use crate::resp::RespValue;
@@ -5,11 +7,11 @@ use crate::resp::RespValue;
 use super::CommandTrait;
 
 pub struct Get {
-    key: String,
+    pub(crate) key: Bytes,
 }
 
 impl Get {
-    pub fn new(key: String) -> Self {
+    pub fn new(key: Bytes) -> Self {
         Self { key }
     }
 }
@@ -18,8 +20,9 @@ impl CommandTrait for Get {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
         let value = db.get(&self.key).await;
         let response = match value {
-            Some(value) => RespValue::BulkString(value.to_vec()),
-            None => RespValue::Null,
+            Ok(Some(value)) => RespValue::BulkString(value),
+            Ok(None) => RespValue::Null,
+            Err(e) => RespValue::SimpleError(e.to_string()),
         };
         Some(response)
     }
@@ -35,6 +38,6 @@ impl TryFrom<Vec<RespValue>> for Get {
 
         let key = next_arg!(args)?;
 
-        return Ok(Self::new(key));
+        Ok(Self::new(key))
     }
 }
@@ -0,0 +1,157 @@
+use bytes::Bytes;
+
+use crate::conf::ReplicationRole;
+use crate::next_arg;
+use crate::resp::{Protocol, RespValue};
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`, executed by
+/// the connection since it switches the protocol of the connection
+pub struct Hello {
+    protover: Option<i64>,
+    auth: Option<(String, String)>,
+    pub(crate) setname: Option<String>,
+}
+
+impl Hello {
+    /// Returns the protocol the connection switches to, or the error to reply with
+    /// if the version is not supported or the credentials are wrong.
+    pub(crate) fn negotiate(&self, current: Protocol) -> Result<Protocol, RespValue> {
+        let protocol = match self.protover {
+            None => current,
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => {
+                return Err(RespValue::SimpleError(
+                    "NOPROTO unsupported protocol version".to_string(),
+                ))
+            }
+        };
+
+        // there are no ACL users, only the default user without a password
+        if let Some((username, _)) = &self.auth {
+            if username != "default" {
+                return Err(RespValue::SimpleError(
+                    "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                ));
+            }
+        }
+
+        Ok(protocol)
+    }
+
+    /// Returns the server properties, a map in RESP3 and a flat array in RESP2.
+    pub(crate) fn reply(
+        &self,
+        db: &crate::db::Db,
+        client_id: u64,
+        protocol: Protocol,
+    ) -> RespValue {
+        let config = db.config();
+
+        let role = match config.replication().role {
+            ReplicationRole::Master => "master",
+            ReplicationRole::Slave { .. } => "replica",
+        };
+
+        let proto = match protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+
+        let bulk = |s: &str| RespValue::BulkString(Bytes::copy_from_slice(s.as_bytes()));
+
+        RespValue::Map(vec![
+            (bulk("server"), bulk("redis")),
+            (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+            (bulk("proto"), RespValue::Integer(proto)),
+            (bulk("id"), RespValue::Integer(client_id as i64)),
+            (bulk("mode"), bulk("standalone")),
+            (bulk("role"), bulk(role)),
+            (bulk("modules"), RespValue::Array(vec![])),
+        ])
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Hello {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let protover = match args.next() {
+            Some(protover) => Some(String::try_from(protover)?.parse::<i64>().map_err(|_| {
+                anyhow::anyhow!("Protocol version is not an integer or out of range")
+            })?),
+            None => None,
+        };
+
+        let mut auth = None;
+        let mut setname = None;
+
+        while let Some(option) = args.next() {
+            let option = String::try_from(option)?;
+
+            match option.to_lowercase().as_str() {
+                "auth" => {
+                    let username = next_arg!(args)?;
+                    let password = next_arg!(args)?;
+                    auth = Some((username, password));
+                }
+                "setname" => {
+                    let name: String = next_arg!(args)?;
+
+                    if name.contains(|c: char| c == ' ' || c.is_control()) {
+                        return Err(anyhow::anyhow!(
+                            "Client names cannot contain spaces, newlines or special characters."
+                        ));
+                    }
+
+                    setname = Some(name);
+                }
+                _ => return Err(anyhow::anyhow!("Syntax error in HELLO option '{}'", option)),
+            }
+        }
+
+        Ok(Self {
+            protover,
+            auth,
+            setname,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(args: &[&str]) -> Result<Hello, anyhow::Error> {
+        let args = std::iter::once("HELLO")
+            .chain(args.iter().copied())
+            .map(|arg| RespValue::BulkString(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect::<Vec<_>>();
+
+        Hello::try_from(args)
+    }
+
+    #[test]
+    fn test_negotiate_protocol() {
+        let command = hello(&[]).unwrap();
+        assert_eq!(command.negotiate(Protocol::Resp3), Ok(Protocol::Resp3));
+
+        let command = hello(&["3", "AUTH", "default", "secret", "SETNAME", "app"]).unwrap();
+        assert_eq!(command.negotiate(Protocol::Resp2), Ok(Protocol::Resp3));
+        assert_eq!(command.setname.as_deref(), Some("app"));
+
+        assert!(hello(&["4"]).unwrap().negotiate(Protocol::Resp2).is_err());
+        assert!(hello(&["3", "AUTH", "admin", "secret"])
+            .unwrap()
+            .negotiate(Protocol::Resp2)
+            .is_err());
+
+        assert!(hello(&["three"]).is_err());
+        assert!(hello(&["3", "SETNAME", "my app"]).is_err());
+        assert!(hello(&["3", "AUTH", "default"]).is_err());
+    }
+}
@@ -2,30 +2,83 @@ use crate::resp::RespValue;
 
 use super::CommandTrait;
 
-pub struct Info;
+pub struct Info {
+    section: Option<String>,
+}
 
 impl Info {
-    pub fn new(_: Option<String>) -> Self {
-        Self {}
+    pub fn new(section: Option<String>) -> Self {
+        Self { section }
+    }
+
+    fn includes(&self, section: &str) -> bool {
+        match self.section.as_deref() {
+            None => true,
+            Some(s) => matches!(s, "all" | "default" | "everything") || s == section,
+        }
     }
 }
 
 impl CommandTrait for Info {
     async fn execute(&self, db: &crate::db::Db) -> Option<RespValue> {
-        let config = db.config().await;
+        let config = db.config();
+
+        let mut sections = vec![];
+
+        if self.includes("replication") {
+            sections.push(config.replication().to_string());
+        }
+
+        if self.includes("memory") {
+            sections.push(format!(
+                "# Memory\r\nused_memory:{}\r\nmaxmemory:{}\r\nmaxmemory_policy:{}\r\n",
+                db.used_memory(),
+                config.maxmemory(),
+                config.maxmemory_policy()
+            ));
+        }
+
+        if self.includes("stats") {
+            let stats = db.stats();
 
-        let replication = config.replication().to_string();
+            sections.push(format!(
+                "# Stats\r\nexpired_keys:{}\r\nevicted_keys:{}\r\n",
+                stats.expired_keys, stats.evicted_keys
+            ));
+        }
 
-        let response = crate::resp::RespValue::from(replication.as_str());
+        if self.includes("keyspace") {
+            let mut keyspace = "# Keyspace\r\n".to_string();
 
-        Some(response)
+            for info in db.keyspace_info().await {
+                keyspace.push_str(&format!(
+                    "db{}:keys={},expires={},avg_ttl={}\r\n",
+                    info.db, info.keys, info.expires, info.avg_ttl
+                ));
+            }
+
+            sections.push(keyspace);
+        }
+
+        let info = sections.join("\r\n");
+
+        Some(RespValue::VerbatimString(
+            "txt".to_string(),
+            info.into_bytes(),
+        ))
     }
 }
 
 impl TryFrom<Vec<crate::resp::RespValue>> for Info {
     type Error = anyhow::Error;
 
-    fn try_from(_args: Vec<crate::resp::RespValue>) -> Result<Self, Self::Error> {
-        return Ok(Self::new(None));
+    fn try_from(args: Vec<crate::resp::RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let section = args.next().map(String::try_from).transpose()?;
+
+        Ok(Self::new(section.map(|section| section.to_lowercase())))
     }
 }
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::db::Db;
 use crate::next_arg;
 use crate::resp::RespValue;
@@ -5,24 +7,20 @@ use crate::resp::RespValue;
 use super::CommandTrait;
 
 pub struct Keys {
-    pattern: String,
+    pattern: Bytes,
 }
 
 impl CommandTrait for Keys {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
-        if self.pattern == "*" {
-            let keys = db.keys().await;
-            let response = RespValue::Array(
-                keys.iter()
-                    .map(|key| RespValue::BulkString(key.as_bytes().to_vec()))
-                    .collect(),
-            );
-            return Some(response);
-        }
-
-        Some(RespValue::SimpleError(
-            ("Pattern not supported yet: ".to_string() + &self.pattern).into(),
-        ))
+        let keys = db.keys(&self.pattern).await;
+
+        let response = RespValue::Array(
+            keys.iter()
+                .map(|key| RespValue::BulkString(key.clone()))
+                .collect(),
+        );
+
+        Some(response)
     }
 }
 
@@ -36,6 +34,6 @@ impl TryFrom<Vec<RespValue>> for Keys {
 
         let pattern = next_arg!(args)?;
 
-        return Ok(Self { pattern });
+        Ok(Self { pattern })
     }
 }
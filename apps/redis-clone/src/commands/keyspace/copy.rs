@@ -0,0 +1,71 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+pub struct Copy {
+    pub(crate) source: Bytes,
+    pub(crate) destination: Bytes,
+    replace: bool,
+    /// Database of the destination, the selected one by default
+    db: Option<usize>,
+}
+
+impl CommandTrait for Copy {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let index = self.db.unwrap_or(db.index());
+
+        let response = match db
+            .copy_to_db(&self.source, &self.destination, index, self.replace)
+            .await
+        {
+            Ok(copied) => RespValue::Integer(copied as i64),
+            Err(e) => RespValue::SimpleError(e.to_string()),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Copy {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let source = next_arg!(args)?;
+        let destination = next_arg!(args)?;
+
+        let mut replace = false;
+        let mut db = None;
+
+        while let Some(option) = args.next() {
+            let option = String::try_from(option)?;
+
+            match option.to_lowercase().as_str() {
+                "replace" => replace = true,
+                "db" => {
+                    let index: String = next_arg!(args)?;
+                    let index = index
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("value is not an integer or out of range"))?;
+
+                    db = Some(index);
+                }
+                _ => return Err(anyhow::anyhow!("syntax error")),
+            }
+        }
+
+        Ok(Self {
+            source,
+            destination,
+            replace,
+            db,
+        })
+    }
+}
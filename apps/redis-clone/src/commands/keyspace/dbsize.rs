@@ -0,0 +1,20 @@
+use crate::db::Db;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+pub struct DbSize;
+
+impl CommandTrait for DbSize {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        Some(RespValue::Integer(db.dbsize().await as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for DbSize {
+    type Error = anyhow::Error;
+
+    fn try_from(_args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        Ok(Self)
+    }
+}
@@ -0,0 +1,42 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `DEL` and `UNLINK` commands. `UNLINK` frees large values in the background.
+pub struct Del {
+    pub(crate) keys: Vec<Bytes>,
+    lazy: bool,
+}
+
+impl CommandTrait for Del {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let count = db.del(&self.keys, self.lazy).await;
+
+        Some(RespValue::Integer(count as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Del {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let keys = args.map(Bytes::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("Invalid arguments, missing"));
+        }
+
+        Ok(Self {
+            keys,
+            lazy: command.eq_ignore_ascii_case("unlink"),
+        })
+    }
+}
@@ -0,0 +1,38 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `EXISTS` command, replies with the number of existing keys without counting
+/// them as accessed.
+pub struct Exists {
+    pub(crate) keys: Vec<Bytes>,
+}
+
+impl CommandTrait for Exists {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let count = db.exists(&self.keys).await;
+
+        Some(RespValue::Integer(count as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Exists {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let keys = args.map(Bytes::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("Invalid arguments, missing"));
+        }
+
+        Ok(Self { keys })
+    }
+}
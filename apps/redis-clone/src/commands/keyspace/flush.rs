@@ -0,0 +1,58 @@
+use std::sync::OnceLock;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `FLUSHDB` and `FLUSHALL` commands. With `ASYNC` the values are freed in the
+/// background.
+pub struct Flush {
+    /// Set for `FLUSHALL`, which deletes the keys of every database
+    all: bool,
+    lazy: bool,
+    /// Number of deleted keys, once executed
+    deleted: OnceLock<usize>,
+}
+
+impl Flush {
+    /// Whether the command deleted any key, as flushing empty databases
+    /// doesn't need to be propagated.
+    pub(crate) fn changed(&self) -> bool {
+        self.deleted.get().is_some_and(|deleted| *deleted > 0)
+    }
+}
+
+impl CommandTrait for Flush {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let deleted = db.flush(self.all, self.lazy).await;
+        self.deleted.get_or_init(|| deleted);
+
+        Some(RespValue::SimpleString("OK".to_string()))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Flush {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+        let all = command.eq_ignore_ascii_case("flushall");
+
+        let lazy = match args.next().map(String::try_from).transpose()? {
+            Some(mode) if mode.eq_ignore_ascii_case("async") => true,
+            Some(mode) if mode.eq_ignore_ascii_case("sync") => false,
+            Some(_) => return Err(anyhow::anyhow!("syntax error")),
+            None => false,
+        };
+
+        Ok(Self {
+            all,
+            lazy,
+            deleted: OnceLock::new(),
+        })
+    }
+}
@@ -0,0 +1,11 @@
+pub(super) mod copy;
+pub(super) mod dbsize;
+pub(super) mod del;
+pub(super) mod exists;
+pub(super) mod flush;
+pub(super) mod r#move;
+pub(super) mod randomkey;
+pub(super) mod rename;
+pub(super) mod scan;
+pub(super) mod swapdb;
+pub(super) mod touch;
@@ -0,0 +1,43 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `MOVE` command, moves a key to another database
+pub struct Move {
+    pub(crate) key: Bytes,
+    db: usize,
+}
+
+impl CommandTrait for Move {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = match db.move_key(&self.key, self.db).await {
+            Ok(moved) => RespValue::Integer(moved as i64),
+            Err(e) => RespValue::SimpleError(e.to_string()),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Move {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let key = next_arg!(args)?;
+
+        let db: String = next_arg!(args)?;
+        let db = db
+            .parse()
+            .map_err(|_| anyhow::anyhow!("value is not an integer or out of range"))?;
+
+        Ok(Self { key, db })
+    }
+}
@@ -0,0 +1,25 @@
+use crate::db::Db;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+pub struct RandomKey;
+
+impl CommandTrait for RandomKey {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = match db.random_key().await {
+            Some(key) => RespValue::BulkString(key),
+            None => RespValue::Null,
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for RandomKey {
+    type Error = anyhow::Error;
+
+    fn try_from(_args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        Ok(Self)
+    }
+}
@@ -0,0 +1,45 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `RENAME` and `RENAMENX` commands
+pub struct Rename {
+    pub(crate) key: Bytes,
+    pub(crate) new_key: Bytes,
+    nx: bool,
+}
+
+impl CommandTrait for Rename {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = match db.rename(&self.key, &self.new_key, self.nx).await {
+            Ok(renamed) if self.nx => RespValue::Integer(renamed as i64),
+            Ok(_) => RespValue::SimpleString("OK".to_string()),
+            Err(e) => RespValue::SimpleError(e.to_string()),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Rename {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let key = next_arg!(args)?;
+        let new_key = next_arg!(args)?;
+
+        Ok(Self {
+            key,
+            new_key,
+            nx: command.eq_ignore_ascii_case("renamenx"),
+        })
+    }
+}
@@ -0,0 +1,122 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// Number of elements visited per call when `COUNT` is not given
+const DEFAULT_COUNT: usize = 10;
+
+/// `SCAN`, `HSCAN`, `SSCAN` and `ZSCAN` commands
+pub struct Scan {
+    /// Key and type of the collection iterated by `HSCAN`, `SSCAN` and `ZSCAN`
+    collection: Option<(Bytes, &'static str)>,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+    value_type: Option<String>,
+}
+
+impl Scan {
+    /// Key of the collection iterated by `HSCAN`, `SSCAN` and `ZSCAN`, `None`
+    /// for `SCAN`.
+    pub(crate) fn collection(&self) -> Option<&Bytes> {
+        self.collection.as_ref().map(|(key, _)| key)
+    }
+}
+
+impl CommandTrait for Scan {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let (cursor, elements) = match &self.collection {
+            None => {
+                db.scan(
+                    self.cursor,
+                    self.count,
+                    self.pattern.as_deref(),
+                    self.value_type.as_deref(),
+                )
+                .await
+            }
+            Some((key, value_type)) => {
+                let scanned = db
+                    .scan_collection(
+                        key,
+                        value_type,
+                        self.cursor,
+                        self.count,
+                        self.pattern.as_deref(),
+                    )
+                    .await;
+
+                match scanned {
+                    Ok(scanned) => scanned,
+                    Err(e) => return Some(RespValue::SimpleError(e.to_string())),
+                }
+            }
+        };
+
+        Some(RespValue::Array(vec![
+            RespValue::BulkString(cursor.to_string().into()),
+            RespValue::Array(elements.into_iter().map(RespValue::BulkString).collect()),
+        ]))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Scan {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let collection = match command.to_lowercase().as_str() {
+            "scan" => None,
+            "hscan" => Some((next_arg!(args)?, "hash")),
+            "sscan" => Some((next_arg!(args)?, "set")),
+            "zscan" => Some((next_arg!(args)?, "zset")),
+            _ => return Err(anyhow::anyhow!("Invalid command")),
+        };
+
+        let cursor: String = next_arg!(args)?;
+        let cursor = cursor
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid cursor"))?;
+
+        let mut pattern = None;
+        let mut count = DEFAULT_COUNT;
+        let mut value_type = None;
+
+        while let Some(option) = args.next() {
+            let option = String::try_from(option)?;
+
+            match option.to_lowercase().as_str() {
+                "match" => pattern = Some(next_arg!(args)?),
+                "count" => {
+                    let n: u64 = next_arg!(args)?;
+
+                    if n < 1 {
+                        return Err(anyhow::anyhow!("syntax error"));
+                    }
+
+                    count = n as usize;
+                }
+                "type" if collection.is_none() => {
+                    let t: String = next_arg!(args)?;
+                    value_type = Some(t.to_lowercase());
+                }
+                _ => return Err(anyhow::anyhow!("syntax error")),
+            }
+        }
+
+        Ok(Self {
+            collection,
+            cursor,
+            pattern,
+            count,
+            value_type,
+        })
+    }
+}
@@ -0,0 +1,44 @@
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `SWAPDB` command, swaps the keys of two databases
+pub struct SwapDb {
+    a: usize,
+    b: usize,
+}
+
+impl CommandTrait for SwapDb {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = match db.swap(self.a, self.b).await {
+            Ok(()) => RespValue::SimpleString("OK".to_string()),
+            Err(e) => RespValue::SimpleError(e.to_string()),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for SwapDb {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let a: String = next_arg!(args)?;
+        let a = a
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid first DB index"))?;
+
+        let b: String = next_arg!(args)?;
+        let b = b
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid second DB index"))?;
+
+        Ok(Self { a, b })
+    }
+}
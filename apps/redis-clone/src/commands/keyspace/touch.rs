@@ -0,0 +1,38 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `TOUCH` command, replies with the number of existing keys and counts them as
+/// accessed for the eviction policies.
+pub struct Touch {
+    pub(crate) keys: Vec<Bytes>,
+}
+
+impl CommandTrait for Touch {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let count = db.touch(&self.keys).await;
+
+        Some(RespValue::Integer(count as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Touch {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let keys = args.map(Bytes::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("Invalid arguments, missing"));
+        }
+
+        Ok(Self { keys })
+    }
+}
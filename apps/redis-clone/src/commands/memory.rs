@@ -0,0 +1,164 @@
+use bytes::Bytes;
+
+use crate::db::{Db, MemoryStats};
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::CommandTrait;
+
+/// Number of stream entries measured by `MEMORY USAGE` when `SAMPLES` is not given
+const DEFAULT_SAMPLES: usize = 5;
+
+/// Below this many bytes `MEMORY DOCTOR` doesn't look for issues
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+
+/// `MEMORY` subcommands
+pub enum Memory {
+    /// Key and the number of sampled stream entries, 0 for all of them
+    Usage(Bytes, usize),
+    Stats,
+    Doctor,
+}
+
+impl CommandTrait for Memory {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = match self {
+            Memory::Usage(key, samples) => match db.memory_usage(key, *samples).await {
+                Some(usage) => RespValue::Integer(usage as i64),
+                None => RespValue::Null,
+            },
+            Memory::Stats => Self::stats(db.memory_stats().await),
+            Memory::Doctor => RespValue::VerbatimString(
+                "txt".into(),
+                Self::doctor(db.memory_stats().await).into(),
+            ),
+        };
+
+        Some(response)
+    }
+}
+
+impl Memory {
+    fn stats(stats: MemoryStats) -> RespValue {
+        let field = |name: &'static str, value: usize| {
+            (
+                RespValue::BulkString(Bytes::from_static(name.as_bytes())),
+                RespValue::Integer(value as i64),
+            )
+        };
+
+        let bytes_per_key = stats
+            .dataset
+            .checked_div(stats.keys_count)
+            .unwrap_or_default();
+
+        let dataset_percentage = match stats.total_allocated {
+            0 => 0.0,
+            total => stats.dataset as f64 * 100.0 / total as f64,
+        };
+
+        RespValue::Map(vec![
+            field("peak.allocated", stats.peak_allocated),
+            field("total.allocated", stats.total_allocated),
+            field("overhead.total", stats.overhead),
+            field("keys.count", stats.keys_count),
+            field("keys.bytes-per-key", bytes_per_key),
+            field("expires.count", stats.expires_count),
+            field("dataset.bytes", stats.dataset),
+            (
+                RespValue::BulkString(Bytes::from_static(b"dataset.percentage")),
+                RespValue::Double(dataset_percentage),
+            ),
+        ])
+    }
+
+    fn doctor(stats: MemoryStats) -> String {
+        if stats.total_allocated < DOCTOR_MIN_MEMORY {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                    detector can't be used in these conditions. Please, leave for your mission \
+                    on Earth and fill it with some data. The new Sam and I will be back to our \
+                    programming as soon as I finished rebooting."
+                .into();
+        }
+
+        // the memory of deleted keys is returned to the allocator but rarely to
+        // the operating system
+        if stats.peak_allocated > stats.total_allocated * 3 / 2 {
+            return format!(
+                "Sam, I detected a few issues in this Redis instance memory implants:\n\n\
+                 * Peak memory: In the past this instance used more than 150% the memory that \
+                 is currently using. The allocator is normally not able to release memory \
+                 after a peak, so you can expect to see a big fragmentation ratio. The peak \
+                 was {} bytes, the keys now use {} bytes.\n\n\
+                 I'm here to keep you safe, Sam. I want to help you.\n",
+                stats.peak_allocated, stats.total_allocated
+            );
+        }
+
+        "Hi Sam, I can't find any memory issue in your instance. I can only account for what \
+         occurs on this base."
+            .into()
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Memory {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let subcommand: String = next_arg!(args)?;
+
+        match subcommand.to_lowercase().as_str() {
+            "usage" => {
+                let key = next_arg!(args)?;
+                let mut samples = DEFAULT_SAMPLES;
+
+                while let Some(option) = args.next() {
+                    let option = String::try_from(option)?;
+
+                    match option.to_lowercase().as_str() {
+                        "samples" => {
+                            let n: u64 = next_arg!(args)?;
+                            samples = n as usize;
+                        }
+                        _ => return Err(anyhow::anyhow!("syntax error")),
+                    }
+                }
+
+                Ok(Self::Usage(key, samples))
+            }
+            "stats" => Ok(Self::Stats),
+            "doctor" => Ok(Self::Doctor),
+            _ => Err(anyhow::anyhow!(
+                "unknown subcommand '{}'. Try MEMORY HELP.",
+                subcommand
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(peak_allocated: usize, total_allocated: usize) -> MemoryStats {
+        MemoryStats {
+            peak_allocated,
+            total_allocated,
+            keys_count: 0,
+            expires_count: 0,
+            overhead: 0,
+            dataset: total_allocated,
+        }
+    }
+
+    #[test]
+    fn test_doctor() {
+        assert!(Memory::doctor(stats(1024, 1024)).contains("very little memory"));
+        assert!(Memory::doctor(stats(20 << 20, 10 << 20)).contains("Peak memory"));
+        assert!(Memory::doctor(stats(10 << 20, 10 << 20)).contains("can't find any memory issue"));
+    }
+}
@@ -1,25 +1,47 @@
+use bytes::Bytes;
+
 use crate::resp::RespValue;
 
 use self::{
-    config::Config, echo::Echo, get::Get, info::Info, keys::Keys, ping::Ping, r#type::Type,
-    set::Set, wait::Wait,
+    config::Config, echo::Echo, expire::Expire, get::Get, info::Info, keys::Keys, memory::Memory,
+    object::Object, persist::Persist, ping::Ping, r#type::Type, save::Save, select::Select,
+    set::Set, ttl::Ttl, wait::Wait, watch::Watch,
 };
 
 mod config;
 mod echo;
+mod expire;
 mod get;
+mod hello;
 mod info;
 mod keys;
+mod keyspace;
+mod memory;
+mod object;
+mod persist;
 mod ping;
 mod psync;
+mod pubsub;
 mod replconf;
+mod save;
+mod select;
 mod set;
 mod streams;
+mod ttl;
 mod r#type;
 mod wait;
+mod watch;
 
+use keyspace::{
+    copy::Copy, dbsize::DbSize, del::Del, exists::Exists, flush::Flush, r#move::Move,
+    randomkey::RandomKey, rename::Rename, scan::Scan, swapdb::SwapDb, touch::Touch,
+};
+use pubsub::{
+    introspection::PubSub, publish::Publish, subscribe::Subscribe, unsubscribe::Unsubscribe,
+};
 use streams::{xadd::XAdd, xrange::XRange, xread::XRead};
 
+pub use hello::Hello;
 pub use psync::Psync;
 pub use replconf::Replconf;
 
@@ -39,6 +61,39 @@ pub enum Command {
     Replconf(Replconf),
     Psync(Psync),
     Wait(Wait),
+    Hello(Hello),
+    Object(Object),
+    Memory(Memory),
+    Save(Save),
+
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+
+    Del(Del),
+    Exists(Exists),
+    Touch(Touch),
+    Rename(Rename),
+    Copy(Copy),
+    RandomKey(RandomKey),
+    DbSize(DbSize),
+    Flush(Flush),
+    Scan(Scan),
+    Select(Select),
+    Move(Move),
+    SwapDb(SwapDb),
+
+    Multi,
+    Exec,
+    Discard,
+    Watch(Watch),
+    Unwatch,
+
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Publish(Publish),
+    PubSub(PubSub),
+    Quit,
 
     XAdd(XAdd),
     XRange(XRange),
@@ -48,12 +103,92 @@ pub enum Command {
 }
 
 impl Command {
-    pub(crate) fn is_propagated(&self) -> bool {
+    /// Returns the command to propagate to replicas, if the command is a write
+    /// that changed the dataset. `request` is the command as it was received
+    /// from the client and `reply` what it replied.
+    pub(crate) fn propagation(
+        &self,
+        request: &RespValue,
+        reply: Option<&RespValue>,
+    ) -> Option<RespValue> {
+        // commands that may change a single key reply 1 when they did, and 0
+        // when the key was missing or a condition prevented the change
+        let changed = matches!(reply, Some(RespValue::Integer(1)));
+
         match self {
-            Command::Set(_) => true,
-            _ => false,
+            Command::Set(cmd) => Some(cmd.propagation()),
+            Command::Expire(cmd) if changed => cmd.propagation(),
+            Command::Persist(_) if changed => Some(request.clone()),
+            Command::Del(_) if matches!(reply, Some(RespValue::Integer(1..))) => {
+                Some(request.clone())
+            }
+            // renaming a key to itself changes nothing, `RENAMENX` replies 0 when
+            // the new key exists
+            Command::Rename(cmd)
+                if cmd.key != cmd.new_key
+                    && (changed || matches!(reply, Some(RespValue::SimpleString(_)))) =>
+            {
+                Some(request.clone())
+            }
+            Command::Copy(_) if changed => Some(request.clone()),
+            Command::Flush(cmd) if cmd.changed() => Some(request.clone()),
+            Command::Move(_) if changed => Some(request.clone()),
+            Command::SwapDb(_) => Some(request.clone()),
+            Command::Publish(_) => Some(request.clone()),
+            _ => None,
         }
     }
+
+    /// Returns whether the command may use more memory. Such commands first evict
+    /// keys once `maxmemory` is reached, and fail if none can be evicted.
+    pub(crate) fn grows_memory(&self) -> bool {
+        matches!(self, Command::Set(_) | Command::Copy(_) | Command::XAdd(_))
+    }
+
+    /// Returns the keys the command reads or writes, `None` if it may use any
+    /// key of the keyspace.
+    pub(crate) fn keys(&self) -> Option<Vec<&Bytes>> {
+        let keys = match self {
+            Command::Get(cmd) => vec![&cmd.key],
+            Command::Set(cmd) => vec![&cmd.key],
+            Command::Type(cmd) => vec![&cmd.key],
+            Command::Object(cmd) => vec![cmd.key()],
+            Command::Memory(Memory::Usage(key, _)) => vec![key],
+            Command::Expire(cmd) => vec![&cmd.key],
+            Command::Ttl(cmd) => vec![&cmd.key],
+            Command::Persist(cmd) => vec![&cmd.key],
+            Command::Del(cmd) => cmd.keys.iter().collect(),
+            Command::Exists(cmd) => cmd.keys.iter().collect(),
+            Command::Touch(cmd) => cmd.keys.iter().collect(),
+            Command::Rename(cmd) => vec![&cmd.key, &cmd.new_key],
+            Command::Copy(cmd) => vec![&cmd.source, &cmd.destination],
+            Command::Move(cmd) => vec![&cmd.key],
+            Command::Scan(cmd) => vec![cmd.collection()?],
+            Command::Watch(cmd) => cmd.keys.iter().collect(),
+            Command::XAdd(cmd) => vec![&cmd.stream_key],
+            Command::XRange(cmd) => vec![&cmd.stream_key],
+            Command::XRead(cmd) => cmd.entries.iter().map(|(key, _)| key).collect(),
+            Command::Keys(_)
+            | Command::Info(_)
+            | Command::Memory(_)
+            | Command::Save(_)
+            | Command::RandomKey(_)
+            | Command::DbSize(_)
+            | Command::Flush(_)
+            | Command::SwapDb(_) => return None,
+            _ => vec![],
+        };
+
+        Some(keys)
+    }
+
+    /// Returns whether the command may be used by a connection in subscribed mode.
+    pub(crate) fn allowed_when_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Ping(_) | Command::Quit
+        )
+    }
 }
 
 impl TryFrom<RespValue> for Command {
@@ -61,13 +196,10 @@ impl TryFrom<RespValue> for Command {
 
     fn try_from(resp: crate::resp::RespValue) -> Result<Self, anyhow::Error> {
         if let crate::resp::RespValue::Array(args) = resp {
-            let command = args
-                .iter()
-                .next()
-                .ok_or(anyhow::anyhow!("Invalid command"))?;
+            let command = args.first().ok_or(anyhow::anyhow!("Invalid command"))?;
 
             if let crate::resp::RespValue::BulkString(command) = command {
-                let command = String::from_utf8_lossy(&command).to_lowercase();
+                let command = String::from_utf8_lossy(command).to_lowercase();
 
                 let command_handler = match command.as_str() {
                     "get" => Command::Get(Get::try_from(args)?),
@@ -78,16 +210,57 @@ impl TryFrom<RespValue> for Command {
                     "replconf" => Command::Replconf(Replconf::try_from(args)?),
                     "psync" => Command::Psync(Psync::try_from(args)?),
                     "wait" => Command::Wait(Wait::try_from(args)?),
+                    "hello" => Command::Hello(Hello::try_from(args)?),
                     "config" => Command::Config(Config::try_from(args)?),
                     "type" => Command::Type(Type::try_from(args)?),
                     "keys" => Command::Keys(Keys::try_from(args)?),
+                    "object" => Command::Object(Object::try_from(args)?),
+                    "memory" => Command::Memory(Memory::try_from(args)?),
+                    "save" | "bgsave" => Command::Save(Save::try_from(args)?),
+
+                    "expire" | "pexpire" | "expireat" | "pexpireat" => {
+                        Command::Expire(Expire::try_from(args)?)
+                    }
+                    "ttl" | "pttl" | "expiretime" | "pexpiretime" => {
+                        Command::Ttl(Ttl::try_from(args)?)
+                    }
+                    "persist" => Command::Persist(Persist::try_from(args)?),
+
+                    "del" | "unlink" => Command::Del(Del::try_from(args)?),
+                    "exists" => Command::Exists(Exists::try_from(args)?),
+                    "touch" => Command::Touch(Touch::try_from(args)?),
+                    "rename" | "renamenx" => Command::Rename(Rename::try_from(args)?),
+                    "copy" => Command::Copy(Copy::try_from(args)?),
+                    "randomkey" => Command::RandomKey(RandomKey::try_from(args)?),
+                    "dbsize" => Command::DbSize(DbSize::try_from(args)?),
+                    "flushdb" | "flushall" => Command::Flush(Flush::try_from(args)?),
+                    "scan" | "hscan" | "sscan" | "zscan" => Command::Scan(Scan::try_from(args)?),
+                    "select" => Command::Select(Select::try_from(args)?),
+                    "move" => Command::Move(Move::try_from(args)?),
+                    "swapdb" => Command::SwapDb(SwapDb::try_from(args)?),
+
+                    "multi" => Command::Multi,
+                    "exec" => Command::Exec,
+                    "discard" => Command::Discard,
+                    "watch" => Command::Watch(Watch::try_from(args)?),
+                    "unwatch" => Command::Unwatch,
+
+                    "subscribe" | "psubscribe" | "ssubscribe" => {
+                        Command::Subscribe(Subscribe::try_from(args)?)
+                    }
+                    "unsubscribe" | "punsubscribe" | "sunsubscribe" => {
+                        Command::Unsubscribe(Unsubscribe::try_from(args)?)
+                    }
+                    "publish" | "spublish" => Command::Publish(Publish::try_from(args)?),
+                    "pubsub" => Command::PubSub(PubSub::try_from(args)?),
+                    "quit" => Command::Quit,
 
                     "xadd" => Command::XAdd(XAdd::try_from(args)?),
                     "xrange" => Command::XRange(XRange::try_from(args)?),
                     "xread" => Command::XRead(XRead::try_from(args)?),
 
                     "command" => Command::CliEntry,
-                    _ => return Err(anyhow::anyhow!("Invalid command")),
+                    _ => return Err(anyhow::anyhow!("unknown command '{}'", command)),
                 };
 
                 return Ok(command_handler);
@@ -110,7 +283,41 @@ impl CommandTrait for Command {
             Command::Config(cmd) => cmd.execute(db).await,
             Command::Type(cmd) => cmd.execute(db).await,
             Command::Keys(cmd) => cmd.execute(db).await,
+            Command::Object(cmd) => cmd.execute(db).await,
+            Command::Memory(cmd) => cmd.execute(db).await,
+            Command::Save(cmd) => cmd.execute(db).await,
             Command::Wait(_) => None,
+            // executed by the connection since it switches the protocol
+            Command::Hello(_) => None,
+
+            Command::Expire(cmd) => cmd.execute(db).await,
+            Command::Ttl(cmd) => cmd.execute(db).await,
+            Command::Persist(cmd) => cmd.execute(db).await,
+
+            Command::Del(cmd) => cmd.execute(db).await,
+            Command::Exists(cmd) => cmd.execute(db).await,
+            Command::Touch(cmd) => cmd.execute(db).await,
+            Command::Rename(cmd) => cmd.execute(db).await,
+            Command::Copy(cmd) => cmd.execute(db).await,
+            Command::RandomKey(cmd) => cmd.execute(db).await,
+            Command::DbSize(cmd) => cmd.execute(db).await,
+            Command::Flush(cmd) => cmd.execute(db).await,
+            Command::Scan(cmd) => cmd.execute(db).await,
+            Command::Move(cmd) => cmd.execute(db).await,
+            Command::SwapDb(cmd) => cmd.execute(db).await,
+
+            // executed by the connection's transaction state
+            Command::Select(_)
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Watch(_)
+            | Command::Unwatch => None,
+
+            Command::Publish(cmd) => cmd.execute(db).await,
+            Command::PubSub(cmd) => cmd.execute(db).await,
+            // executed by the connection's subscriptions
+            Command::Subscribe(_) | Command::Unsubscribe(_) | Command::Quit => None,
 
             Command::XAdd(cmd) => cmd.execute(db).await,
             Command::XRange(cmd) => cmd.execute(db).await,
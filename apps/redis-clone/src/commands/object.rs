@@ -0,0 +1,101 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::CommandTrait;
+
+/// `OBJECT` subcommands, which inspect a key without counting as an access
+pub enum Object {
+    Encoding(Bytes),
+    IdleTime(Bytes),
+    Freq(Bytes),
+    RefCount(Bytes),
+}
+
+impl Object {
+    pub(crate) fn key(&self) -> &Bytes {
+        match self {
+            Object::Encoding(key)
+            | Object::IdleTime(key)
+            | Object::Freq(key)
+            | Object::RefCount(key) => key,
+        }
+    }
+}
+
+impl CommandTrait for Object {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let lfu = db.config().maxmemory_policy().lfu();
+
+        match self {
+            Object::IdleTime(_) if lfu => {
+                return Some(RespValue::SimpleError(
+                    "ERR An LFU maxmemory policy is selected, idle time not tracked. \
+                     Please note that when switching between policies at runtime LRU and \
+                     LFU data will take some time to adjust."
+                        .into(),
+                ))
+            }
+            Object::Freq(_) if !lfu => {
+                return Some(RespValue::SimpleError(
+                    "ERR An LFU maxmemory policy is not selected, access frequency not \
+                     tracked. Please note that when switching between policies at runtime \
+                     LRU and LFU data will take some time to adjust."
+                        .into(),
+                ))
+            }
+            _ => {}
+        }
+
+        let Some(info) = db.object(self.key()).await else {
+            return Some(RespValue::Null);
+        };
+
+        let response = match self {
+            Object::Encoding(_) => {
+                RespValue::BulkString(Bytes::from_static(info.encoding.as_bytes()))
+            }
+            Object::IdleTime(_) => RespValue::Integer(info.idle as i64),
+            Object::Freq(_) => RespValue::Integer(info.freq as i64),
+            // values are never shared between keys
+            Object::RefCount(_) => RespValue::Integer(1),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Object {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let subcommand: String = next_arg!(args)?;
+
+        let constructor = match subcommand.to_lowercase().as_str() {
+            "encoding" => Self::Encoding,
+            "idletime" => Self::IdleTime,
+            "freq" => Self::Freq,
+            "refcount" => Self::RefCount,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "unknown subcommand '{}'. Try OBJECT HELP.",
+                    subcommand
+                ))
+            }
+        };
+
+        let key = next_arg!(args)?;
+
+        if args.next().is_some() {
+            return Err(anyhow::anyhow!("syntax error"));
+        }
+
+        Ok(constructor(key))
+    }
+}
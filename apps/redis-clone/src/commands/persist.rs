@@ -0,0 +1,33 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::CommandTrait;
+
+pub struct Persist {
+    pub(crate) key: Bytes,
+}
+
+impl CommandTrait for Persist {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let removed = db.persist(&self.key).await;
+
+        Some(RespValue::Integer(removed as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Persist {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let key = next_arg!(args)?;
+
+        Ok(Self { key })
+    }
+}
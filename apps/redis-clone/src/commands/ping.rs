@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::resp::RespValue;
 
 use super::CommandTrait;
@@ -10,12 +12,16 @@ impl Ping {
     pub fn new(message: Option<String>) -> Self {
         Self { message }
     }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
 }
 
 impl CommandTrait for Ping {
     async fn execute(&self, _: &crate::db::Db) -> Option<RespValue> {
         let response = match &self.message {
-            Some(message) => RespValue::BulkString(message.as_bytes().to_vec()),
+            Some(message) => RespValue::BulkString(Bytes::copy_from_slice(message.as_bytes())),
             None => RespValue::SimpleString("PONG".to_string()),
         };
 
@@ -32,11 +38,11 @@ impl TryFrom<Vec<RespValue>> for Ping {
         let _command = args.next();
 
         let message = match args.next() {
-            Some(RespValue::BulkString(message)) => Some(String::from_utf8(message)?),
+            Some(RespValue::BulkString(message)) => Some(String::from_utf8(message.to_vec())?),
             Some(_) => return Err(anyhow::anyhow!("Invalid argument")),
             None => None,
         };
 
-        return Ok(Self::new(message));
+        Ok(Self::new(message))
     }
 }
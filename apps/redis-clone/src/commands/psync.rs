@@ -16,8 +16,8 @@ impl Psync {
 impl CommandTrait for Psync {
     async fn execute(&self, db: &crate::db::Db) -> Option<RespValue> {
         match (self.id.as_str(), self.offset) {
-            (id, None) if id == "?" => {
-                let id = db.config().await.replication().master_replid.clone();
+            ("?", None) => {
+                let id = db.config().replication().master_replid.clone();
 
                 let response = crate::resp::RespValue::SimpleString(
                     format!("FULLRESYNC {} 0", id).to_string(),
@@ -45,6 +45,6 @@ impl TryFrom<Vec<crate::resp::RespValue>> for Psync {
             return Ok(Self::new(id, Some(offset as u64)));
         }
 
-        return Ok(Self::new(id, None));
+        Ok(Self::new(id, None))
     }
 }
@@ -0,0 +1,81 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::pubsub::Kind;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `PUBSUB` introspection subcommands
+pub enum PubSub {
+    /// `CHANNELS` and `SHARDCHANNELS`
+    Channels(Kind, Option<String>),
+    /// `NUMSUB` and `SHARDNUMSUB`
+    NumSub(Kind, Vec<String>),
+    NumPat,
+}
+
+impl CommandTrait for PubSub {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let pubsub = db.pubsub();
+
+        let response = match self {
+            PubSub::Channels(kind, pattern) => RespValue::Array(
+                pubsub
+                    .channels(*kind, pattern.as_deref())
+                    .into_iter()
+                    .map(|channel| RespValue::BulkString(channel.into()))
+                    .collect(),
+            ),
+            PubSub::NumSub(kind, channels) => RespValue::Array(
+                channels
+                    .iter()
+                    .flat_map(|channel| {
+                        [
+                            RespValue::BulkString(Bytes::copy_from_slice(channel.as_bytes())),
+                            RespValue::Integer(pubsub.numsub(*kind, channel) as i64),
+                        ]
+                    })
+                    .collect(),
+            ),
+            PubSub::NumPat => RespValue::Integer(pubsub.numpat() as i64),
+        };
+
+        Some(response)
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for PubSub {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let subcommand: String = next_arg!(args)?;
+
+        let kind = if subcommand.to_lowercase().starts_with("shard") {
+            Kind::Shard
+        } else {
+            Kind::Channel
+        };
+
+        match subcommand.to_lowercase().as_str() {
+            "channels" | "shardchannels" => Ok(Self::Channels(
+                kind,
+                args.next().map(String::try_from).transpose()?,
+            )),
+            "numsub" | "shardnumsub" => Ok(Self::NumSub(
+                kind,
+                args.map(String::try_from).collect::<Result<Vec<_>, _>>()?,
+            )),
+            "numpat" => Ok(Self::NumPat),
+            _ => Err(anyhow::anyhow!(
+                "unknown subcommand '{}'. Try PUBSUB HELP.",
+                subcommand
+            )),
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub(super) mod introspection;
+pub(super) mod publish;
+pub(super) mod subscribe;
+pub(super) mod unsubscribe;
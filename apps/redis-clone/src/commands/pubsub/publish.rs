@@ -0,0 +1,46 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::super::CommandTrait;
+
+/// `PUBLISH` and `SPUBLISH` commands. Reply with the number of clients that
+/// received the message.
+pub struct Publish {
+    channel: String,
+    message: Bytes,
+    shard: bool,
+}
+
+impl CommandTrait for Publish {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let received = if self.shard {
+            db.pubsub().spublish(&self.channel, &self.message)
+        } else {
+            db.pubsub().publish(&self.channel, &self.message)
+        };
+
+        Some(RespValue::Integer(received as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Publish {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let channel = next_arg!(args)?;
+        let message = next_arg!(args)?;
+
+        Ok(Self {
+            channel,
+            message,
+            shard: command.eq_ignore_ascii_case("spublish"),
+        })
+    }
+}
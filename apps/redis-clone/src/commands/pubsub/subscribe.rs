@@ -0,0 +1,35 @@
+use crate::next_arg;
+use crate::pubsub::Kind;
+use crate::resp::RespValue;
+
+/// `SUBSCRIBE`, `PSUBSCRIBE` and `SSUBSCRIBE` commands, executed by the connection's
+/// [`crate::pubsub::Subscriptions`]
+pub struct Subscribe {
+    pub(crate) channels: Vec<String>,
+    pub(crate) kind: Kind,
+}
+
+impl TryFrom<Vec<RespValue>> for Subscribe {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let channels = args.map(String::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        if channels.is_empty() {
+            return Err(anyhow::anyhow!("Invalid arguments, missing"));
+        }
+
+        Ok(Self {
+            channels,
+            kind: match command.to_lowercase().as_str() {
+                "psubscribe" => Kind::Pattern,
+                "ssubscribe" => Kind::Shard,
+                _ => Kind::Channel,
+            },
+        })
+    }
+}
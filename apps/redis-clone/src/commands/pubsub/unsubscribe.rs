@@ -0,0 +1,31 @@
+use crate::next_arg;
+use crate::pubsub::Kind;
+use crate::resp::RespValue;
+
+/// `UNSUBSCRIBE`, `PUNSUBSCRIBE` and `SUNSUBSCRIBE` commands, executed by the connection's
+/// [`crate::pubsub::Subscriptions`]. Without channels all of them are unsubscribed.
+pub struct Unsubscribe {
+    pub(crate) channels: Vec<String>,
+    pub(crate) kind: Kind,
+}
+
+impl TryFrom<Vec<RespValue>> for Unsubscribe {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let channels = args.map(String::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            channels,
+            kind: match command.to_lowercase().as_str() {
+                "punsubscribe" => Kind::Pattern,
+                "sunsubscribe" => Kind::Shard,
+                _ => Kind::Channel,
+            },
+        })
+    }
+}
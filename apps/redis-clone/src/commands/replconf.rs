@@ -2,6 +2,7 @@ use crate::{next_arg, resp::RespValue};
 
 use super::CommandTrait;
 
+#[allow(dead_code)]
 pub enum Replconf {
     ListeningPort(u16),
     Capa(String),
@@ -39,20 +40,18 @@ impl TryFrom<Vec<RespValue>> for Replconf {
         match subcommand.to_lowercase().as_str() {
             "listening-port" => {
                 let port: u64 = next_arg!(args)?;
-                return Ok(Self::new_port(port as u16));
+                Ok(Self::new_port(port as u16))
             }
             "capa" => {
                 let capa = next_arg!(args)?;
-                return Ok(Self::new_capa(capa));
-            }
-            "getack" => {
-                return Ok(Self::Getack);
+                Ok(Self::new_capa(capa))
             }
+            "getack" => Ok(Self::Getack),
             "ack" => {
                 let offset = next_arg!(args)?;
-                return Ok(Self::Ack(offset));
+                Ok(Self::Ack(offset))
             }
-            _ => return Err(anyhow::anyhow!("Invalid arguments")),
+            _ => Err(anyhow::anyhow!("Invalid arguments")),
         }
     }
 }
@@ -0,0 +1,48 @@
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+
+use super::CommandTrait;
+
+/// `SAVE` and `BGSAVE` commands, write the keys of every database to the RDB
+/// file of `dir` and `dbfilename`
+pub struct Save {
+    /// Set for `BGSAVE`, which replies before the file is written
+    background: bool,
+}
+
+impl CommandTrait for Save {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let response = if self.background {
+            db.background_save()
+                .await
+                .map(|_| RespValue::SimpleString("Background saving started".to_string()))
+        } else {
+            db.save()
+                .await
+                .map(|_| RespValue::SimpleString("OK".to_string()))
+        };
+
+        Some(response.unwrap_or_else(|e| RespValue::SimpleError(e.to_string())))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Save {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+        let background = command.eq_ignore_ascii_case("bgsave");
+
+        // `BGSAVE SCHEDULE` saves right away as no rewrite can be in progress
+        match args.next().map(String::try_from).transpose()? {
+            Some(option) if background && option.eq_ignore_ascii_case("schedule") => {}
+            Some(_) => return Err(anyhow::anyhow!("syntax error")),
+            None => {}
+        }
+
+        Ok(Self { background })
+    }
+}
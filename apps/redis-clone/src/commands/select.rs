@@ -0,0 +1,25 @@
+use crate::next_arg;
+use crate::resp::RespValue;
+
+/// `SELECT` command, executed by the connection's [`crate::transaction::Transaction`]
+/// since it changes the database of the connection
+pub struct Select {
+    pub(crate) index: usize,
+}
+
+impl TryFrom<Vec<RespValue>> for Select {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command = args.next();
+
+        let index: String = next_arg!(args)?;
+        let index = index
+            .parse()
+            .map_err(|_| anyhow::anyhow!("value is not an integer or out of range"))?;
+
+        Ok(Self { index })
+    }
+}
@@ -1,27 +1,54 @@
+use std::sync::OnceLock;
+
 use bytes::Bytes;
-use tokio::time::Duration;
 
 use crate::db::Db;
 use crate::next_arg;
 use crate::resp::RespValue;
 
+use super::expire::ExpireTime;
 use super::CommandTrait;
 
 pub struct Set {
-    key: String,
+    pub(crate) key: Bytes,
     value: Bytes,
-    expire: Option<Duration>,
+    expiration: Option<ExpireTime>,
+    /// Absolute expiration time as UNIX time in milliseconds, once executed
+    expires_at: OnceLock<u64>,
 }
 
 impl Set {
-    pub fn new(key: String, value: Bytes, expire: Option<Duration>) -> Self {
-        Self { key, value, expire }
+    /// Relative expirations are propagated as absolute `PXAT` so that replicas
+    /// expire the key at the same moment regardless of replication lag.
+    pub(crate) fn propagation(&self) -> RespValue {
+        let mut args = vec![
+            RespValue::BulkString(Bytes::from_static(b"SET")),
+            RespValue::BulkString(self.key.clone()),
+            RespValue::BulkString(self.value.clone()),
+        ];
+
+        if let Some(expires_at) = self.expires_at.get() {
+            args.push(RespValue::BulkString(Bytes::from_static(b"PXAT")));
+            args.push(RespValue::BulkString(expires_at.to_string().into()));
+        }
+
+        RespValue::Array(args)
     }
 }
 
 impl CommandTrait for Set {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
-        db.set(self.key.clone(), self.value.clone(), self.expire)
+        let expires_at = match self.expiration.map(ExpireTime::resolve) {
+            Some(Some(expires_at)) => Some(*self.expires_at.get_or_init(|| expires_at as u64)),
+            Some(None) => {
+                return Some(RespValue::SimpleError(
+                    "ERR invalid expire time in 'set' command".into(),
+                ))
+            }
+            None => None,
+        };
+
+        db.set(self.key.clone(), self.value.clone(), expires_at)
             .await;
 
         Some(RespValue::SimpleString("OK".to_string()))
@@ -39,16 +66,33 @@ impl TryFrom<Vec<RespValue>> for Set {
         let key = next_arg!(args)?;
         let value = next_arg!(args)?;
 
-        let expire = next_arg!(args,
+        let expiration: Option<Option<ExpireTime>> = next_arg!(args,
             optional,
             "ex" => {
-                |v| Duration::from_secs(v)
+                |v: i64| v.checked_mul(1000).filter(|_| v > 0).map(ExpireTime::In)
             },
             "px" => {
-                |v| Duration::from_millis(v)
+                |v: i64| Some(v).filter(|v| *v > 0).map(ExpireTime::In)
+            },
+            "exat" => {
+                |v: i64| v.checked_mul(1000).filter(|_| v > 0).map(ExpireTime::At)
+            },
+            "pxat" => {
+                |v: i64| Some(v).filter(|v| *v > 0).map(ExpireTime::At)
             }
         );
 
-        return Ok(Self::new(key, value, expire));
+        let expiration = match expiration {
+            Some(Some(expiration)) => Some(expiration),
+            Some(None) => return Err(anyhow::anyhow!("invalid expire time in 'set' command")),
+            None => None,
+        };
+
+        Ok(Self {
+            key,
+            value,
+            expiration,
+            expires_at: OnceLock::new(),
+        })
     }
 }
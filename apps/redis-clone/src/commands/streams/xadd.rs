@@ -7,10 +7,9 @@ use crate::resp::RespValue;
 use super::super::CommandTrait;
 
 pub struct XAdd {
-    stream_key: String,
+    pub(crate) stream_key: Bytes,
     id: String,
-    key: String,
-    value: Bytes,
+    fields: Vec<(Bytes, Bytes)>,
 }
 
 impl CommandTrait for XAdd {
@@ -22,20 +21,12 @@ impl CommandTrait for XAdd {
         }
 
         let id = db
-            .xadd(
-                &self.stream_key.as_str(),
-                Some(self.id.clone()),
-                self.key.clone(),
-                self.value.clone(),
-            )
+            .xadd(&self.stream_key, Some(self.id.clone()), self.fields.clone())
             .await;
 
         match id {
             Ok(id) => Some(RespValue::SimpleString(id)),
-            Err(_) => Some(RespValue::SimpleError(
-                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
-                    .into(),
-            )),
+            Err(e) => Some(RespValue::SimpleError(e.to_string())),
         }
     }
 }
@@ -50,14 +41,16 @@ impl TryFrom<Vec<RespValue>> for XAdd {
 
         let stream_key = next_arg!(args)?;
         let id = next_arg!(args)?;
-        let key = next_arg!(args)?;
-        let value = next_arg!(args)?;
 
-        return Ok(Self {
+        let mut fields = vec![(next_arg!(args)?, next_arg!(args)?)];
+        while let Ok(field) = next_arg!(args) {
+            fields.push((field, next_arg!(args)?));
+        }
+
+        Ok(Self {
             stream_key,
             id,
-            key,
-            value,
-        });
+            fields,
+        })
     }
 }
@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::db::Db;
 use crate::next_arg;
 use crate::resp::RespValue;
@@ -5,7 +7,7 @@ use crate::resp::RespValue;
 use super::super::CommandTrait;
 
 pub struct XRange {
-    stream_key: String,
+    pub(crate) stream_key: Bytes,
     start_id: String,
     end_id: String,
 }
@@ -14,25 +16,37 @@ impl CommandTrait for XRange {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
         let range = db
             .xrange(
-                &self.stream_key.as_str(),
-                &self.start_id.as_str(),
-                &self.end_id.as_str(),
+                &self.stream_key,
+                self.start_id.as_str(),
+                self.end_id.as_str(),
                 None,
             )
-            .await
-            .unwrap_or(vec![]);
+            .await;
+
+        let range = match range {
+            Ok(range) => range,
+            Err(e) => return Some(RespValue::SimpleError(e.to_string())),
+        };
 
         Some(RespValue::Array(
             range
                 .iter()
                 .map(|(id, fields)| {
                     RespValue::Array(
-                        vec![RespValue::BulkString(id.as_bytes().to_vec())]
+                        vec![RespValue::BulkString(Bytes::copy_from_slice(id.as_bytes()))]
                             .into_iter()
-                            .chain(vec![RespValue::Array(vec![
-                                RespValue::BulkString(fields.key().as_bytes().to_vec()),
-                                RespValue::BulkString(fields.data().to_vec()),
-                            ])])
+                            .chain(vec![RespValue::Array(
+                                fields
+                                    .fields()
+                                    .iter()
+                                    .flat_map(|(field, value)| {
+                                        [
+                                            RespValue::BulkString(field.clone()),
+                                            RespValue::BulkString(value.clone()),
+                                        ]
+                                    })
+                                    .collect(),
+                            )])
                             .collect(),
                     )
                 })
@@ -53,10 +67,10 @@ impl TryFrom<Vec<RespValue>> for XRange {
         let start_id = next_arg!(args)?;
         let end_id = next_arg!(args)?;
 
-        return Ok(Self {
+        Ok(Self {
             stream_key,
             start_id,
             end_id,
-        });
+        })
     }
 }
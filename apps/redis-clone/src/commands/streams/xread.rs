@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::db::{Db, StreamEntry};
 use crate::next_arg;
 use crate::resp::RespValue;
@@ -5,18 +7,18 @@ use crate::resp::RespValue;
 use super::super::CommandTrait;
 
 pub struct XRead {
-    entries: Vec<(String, String)>,
+    pub(crate) entries: Vec<(Bytes, String)>,
 }
 
 impl CommandTrait for XRead {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
-        let mut entries: Vec<(String, StreamEntry)> = vec![];
+        let mut entries: Vec<(Bytes, StreamEntry)> = vec![];
 
         for (stream_key, id) in self.entries.iter() {
-            let entry = db.xread(stream_key.clone(), id.clone()).await;
-
-            if let Some(entry) = entry {
-                entries.push((stream_key.clone(), entry));
+            match db.xread(stream_key, id).await {
+                Ok(Some(entry)) => entries.push((stream_key.clone(), entry)),
+                Ok(None) => {}
+                Err(e) => return Some(RespValue::SimpleError(e.to_string())),
             }
         }
 
@@ -25,12 +27,20 @@ impl CommandTrait for XRead {
                 .iter()
                 .map(|(id, fields)| {
                     RespValue::Array(
-                        vec![RespValue::BulkString(id.as_bytes().to_vec())]
+                        vec![RespValue::BulkString(id.clone())]
                             .into_iter()
-                            .chain(vec![RespValue::Array(vec![
-                                RespValue::BulkString(fields.key().as_bytes().to_vec()),
-                                RespValue::BulkString(fields.data().to_vec()),
-                            ])])
+                            .chain(vec![RespValue::Array(
+                                fields
+                                    .fields()
+                                    .iter()
+                                    .flat_map(|(field, value)| {
+                                        [
+                                            RespValue::BulkString(field.clone()),
+                                            RespValue::BulkString(value.clone()),
+                                        ]
+                                    })
+                                    .collect(),
+                            )])
                             .collect(),
                     )
                 })
@@ -48,12 +58,12 @@ impl TryFrom<Vec<RespValue>> for XRead {
         let _command = args.next();
 
         let mut next_arg: String = next_arg!(args)?;
-        while String::from("streams") != next_arg.to_ascii_lowercase() {
+        while !"streams".eq_ignore_ascii_case(&next_arg) {
             // TODO: handle XRead Options
             next_arg = next_arg!(args)?;
         }
 
-        let mut entries: Vec<String> = vec![];
+        let mut entries: Vec<Bytes> = vec![];
 
         while let Ok(v) = next_arg!(args) {
             entries.push(v);
@@ -62,10 +72,11 @@ impl TryFrom<Vec<RespValue>> for XRead {
         let n = entries.len();
         // convert entries to a tuple of (stream_key, id) pairs
         // e.g. ["stream1", "stream2", "id1", "id2"] -> [("stream1", "id1"), ("stream2", "id2")]
-        let entries: Vec<(String, String)> = entries.iter().take(n / 2).enumerate().fold(
+        let entries: Vec<(Bytes, String)> = entries.iter().take(n / 2).enumerate().fold(
             vec![],
-            |mut acc: Vec<(String, String)>, (i, v)| {
-                acc.push((v.to_string(), entries[n / 2 + i].to_string()));
+            |mut acc: Vec<(Bytes, String)>, (i, v)| {
+                let id = String::from_utf8_lossy(&entries[n / 2 + i]).into_owned();
+                acc.push((v.clone(), id));
                 acc
             },
         );
@@ -0,0 +1,62 @@
+use bytes::Bytes;
+
+use crate::db::Db;
+use crate::next_arg;
+use crate::resp::RespValue;
+use crate::utils::unix_millis;
+
+use super::CommandTrait;
+
+/// `TTL`, `PTTL`, `EXPIRETIME` and `PEXPIRETIME` commands. Reply with `-2` if the
+/// key does not exist and `-1` if it has no expiration.
+pub struct Ttl {
+    pub(crate) key: Bytes,
+    millis: bool,
+    absolute: bool,
+}
+
+impl CommandTrait for Ttl {
+    async fn execute(&self, db: &Db) -> Option<RespValue> {
+        let expires_at = match db.expires_at(&self.key).await {
+            None => return Some(RespValue::Integer(-2)),
+            Some(None) => return Some(RespValue::Integer(-1)),
+            Some(Some(expires_at)) => expires_at,
+        };
+
+        let response = match (self.absolute, self.millis) {
+            (true, true) => expires_at,
+            (true, false) => expires_at / 1000,
+            (false, true) => expires_at.saturating_sub(unix_millis()),
+            // round to the closest second as Redis does
+            (false, false) => (expires_at.saturating_sub(unix_millis()) + 500) / 1000,
+        };
+
+        Some(RespValue::Integer(response as i64))
+    }
+}
+
+impl TryFrom<Vec<RespValue>> for Ttl {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let command: String = next_arg!(args)?;
+
+        let (millis, absolute) = match command.to_lowercase().as_str() {
+            "ttl" => (false, false),
+            "pttl" => (true, false),
+            "expiretime" => (false, true),
+            "pexpiretime" => (true, true),
+            _ => return Err(anyhow::anyhow!("Invalid command")),
+        };
+
+        let key = next_arg!(args)?;
+
+        Ok(Self {
+            key,
+            millis,
+            absolute,
+        })
+    }
+}
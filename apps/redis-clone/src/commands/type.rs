@@ -1,3 +1,5 @@
+use bytes::Bytes;
+
 use crate::db::Db;
 use crate::next_arg;
 use crate::resp::RespValue;
@@ -5,12 +7,14 @@ use crate::resp::RespValue;
 use super::CommandTrait;
 
 pub struct Type {
-    key: String,
+    pub(crate) key: Bytes,
 }
 
 impl CommandTrait for Type {
     async fn execute(&self, db: &Db) -> Option<RespValue> {
-        Some(RespValue::SimpleString(db.value_type(&self.key).await))
+        Some(RespValue::SimpleString(
+            db.value_type(&self.key).await.to_string(),
+        ))
     }
 }
 
@@ -24,6 +28,6 @@ impl TryFrom<Vec<RespValue>> for Type {
 
         let key = next_arg!(args)?;
 
-        return Ok(Self { key });
+        Ok(Self { key })
     }
 }
@@ -0,0 +1,27 @@
+use bytes::Bytes;
+
+use crate::next_arg;
+use crate::resp::RespValue;
+
+/// `WATCH` command, executed by the connection's [`crate::transaction::Transaction`]
+pub struct Watch {
+    pub(crate) keys: Vec<Bytes>,
+}
+
+impl TryFrom<Vec<RespValue>> for Watch {
+    type Error = anyhow::Error;
+
+    fn try_from(args: Vec<RespValue>) -> Result<Self, Self::Error> {
+        let mut args = args.into_iter();
+
+        let _command: String = next_arg!(args)?;
+
+        let keys = args.map(Bytes::try_from).collect::<Result<Vec<_>, _>>()?;
+
+        if keys.is_empty() {
+            return Err(anyhow::anyhow!("Invalid arguments, missing"));
+        }
+
+        Ok(Self { keys })
+    }
+}
@@ -0,0 +1,111 @@
+/// Sizes up to which collections are given their compact encoding when they are
+/// created, from an RDB file or a fixture. No command writes to collections
+/// yet, so nothing converts a compact collection to a full structure once it
+/// grows; changed limits apply to the collections loaded afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EncodingLimits {
+    pub(crate) hash_max_listpack_entries: usize,
+    pub(crate) hash_max_listpack_value: usize,
+    /// Sets of integers only use an intset up to this many entries
+    pub(crate) set_max_intset_entries: usize,
+    pub(crate) set_max_listpack_entries: usize,
+    pub(crate) set_max_listpack_value: usize,
+    pub(crate) zset_max_listpack_entries: usize,
+    pub(crate) zset_max_listpack_value: usize,
+    /// Number of entries of a list node if positive, otherwise the node size
+    /// from `-1` for 4 kb to `-5` for 64 kb
+    pub(crate) list_max_listpack_size: i64,
+}
+
+impl Default for EncodingLimits {
+    fn default() -> Self {
+        Self {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+        }
+    }
+}
+
+impl EncodingLimits {
+    /// Returns the limit named `param`, also accepting the older `ziplist` names.
+    fn limit_mut(&mut self, param: &str) -> Option<&mut usize> {
+        let limit = match param.replace("ziplist", "listpack").as_str() {
+            "hash-max-listpack-entries" => &mut self.hash_max_listpack_entries,
+            "hash-max-listpack-value" => &mut self.hash_max_listpack_value,
+            "set-max-intset-entries" => &mut self.set_max_intset_entries,
+            "set-max-listpack-entries" => &mut self.set_max_listpack_entries,
+            "set-max-listpack-value" => &mut self.set_max_listpack_value,
+            "zset-max-listpack-entries" => &mut self.zset_max_listpack_entries,
+            "zset-max-listpack-value" => &mut self.zset_max_listpack_value,
+            _ => return None,
+        };
+
+        Some(limit)
+    }
+
+    fn is_list_size(param: &str) -> bool {
+        param.replace("ziplist", "listpack") == "list-max-listpack-size"
+    }
+
+    /// Returns the value of a limit for `CONFIG GET`, if `param` is one.
+    pub(crate) fn get(&self, param: &str) -> Option<String> {
+        if Self::is_list_size(param) {
+            return Some(self.list_max_listpack_size.to_string());
+        }
+
+        let mut limits = *self;
+        limits.limit_mut(param).map(|limit| limit.to_string())
+    }
+
+    /// Changes a limit and returns whether `param` is one.
+    pub(crate) fn set(&mut self, param: &str, value: &str) -> Result<bool, anyhow::Error> {
+        let invalid = || anyhow::anyhow!("Invalid argument '{}' for CONFIG SET '{}'", value, param);
+
+        if Self::is_list_size(param) {
+            self.list_max_listpack_size = match value.parse() {
+                Ok(size @ (-5..=-1 | 1..)) => size,
+                _ => return Err(invalid()),
+            };
+
+            return Ok(true);
+        }
+
+        let Some(limit) = self.limit_mut(param) else {
+            return Ok(false);
+        };
+
+        *limit = value.parse().map_err(|_| invalid())?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set_limits() {
+        let mut limits = EncodingLimits::default();
+
+        assert!(limits.set("hash-max-listpack-entries", "16").unwrap());
+        assert!(limits.set("zset-max-ziplist-value", "32").unwrap());
+        assert!(limits.set("list-max-ziplist-size", "-3").unwrap());
+        assert!(!limits.set("maxmemory", "1").unwrap());
+
+        assert!(limits.set("list-max-listpack-size", "-6").is_err());
+        assert!(limits.set("list-max-listpack-size", "0").is_err());
+        assert!(limits.set("set-max-intset-entries", "-1").is_err());
+
+        assert_eq!(limits.hash_max_listpack_entries, 16);
+        assert_eq!(limits.get("zset-max-listpack-value").unwrap(), "32");
+        assert_eq!(limits.get("list-max-listpack-size").unwrap(), "-3");
+        assert_eq!(limits.get("hz"), None);
+    }
+}
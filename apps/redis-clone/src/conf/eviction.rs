@@ -0,0 +1,90 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Keys evicted once `maxmemory` is reached, set with `maxmemory-policy`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum MaxmemoryPolicy {
+    /// Writes fail with `-OOM` instead
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    /// Keys with the nearest expiration first
+    VolatileTtl,
+}
+
+impl MaxmemoryPolicy {
+    const NAMES: [(&'static str, Self); 8] = [
+        ("noeviction", Self::NoEviction),
+        ("allkeys-lru", Self::AllKeysLru),
+        ("allkeys-lfu", Self::AllKeysLfu),
+        ("allkeys-random", Self::AllKeysRandom),
+        ("volatile-lru", Self::VolatileLru),
+        ("volatile-lfu", Self::VolatileLfu),
+        ("volatile-random", Self::VolatileRandom),
+        ("volatile-ttl", Self::VolatileTtl),
+    ];
+
+    /// Returns whether only keys with an expiration are evicted.
+    pub(crate) fn volatile(&self) -> bool {
+        matches!(
+            self,
+            Self::VolatileLru | Self::VolatileLfu | Self::VolatileRandom | Self::VolatileTtl
+        )
+    }
+
+    /// Returns whether keys are evicted by access frequency.
+    pub(crate) fn lfu(&self) -> bool {
+        matches!(self, Self::AllKeysLfu | Self::VolatileLfu)
+    }
+}
+
+impl FromStr for MaxmemoryPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, policy)| *policy)
+            .ok_or_else(|| anyhow::anyhow!("invalid maxmemory policy '{}'", s))
+    }
+}
+
+impl Display for MaxmemoryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, _) = Self::NAMES
+            .iter()
+            .find(|(_, policy)| policy == self)
+            .expect("every policy has a name");
+
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_policy() {
+        for (name, policy) in MaxmemoryPolicy::NAMES {
+            assert_eq!(name.parse::<MaxmemoryPolicy>().unwrap(), policy);
+            assert_eq!(policy.to_string(), name);
+        }
+
+        assert_eq!(
+            "AllKeys-LRU".parse::<MaxmemoryPolicy>().unwrap(),
+            MaxmemoryPolicy::AllKeysLru
+        );
+        assert!(MaxmemoryPolicy::VolatileTtl.volatile());
+        assert!(!MaxmemoryPolicy::AllKeysLfu.volatile());
+        assert!("lru".parse::<MaxmemoryPolicy>().is_err());
+    }
+}
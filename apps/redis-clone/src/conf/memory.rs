@@ -0,0 +1,66 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Amount of memory in bytes, parsed from values with units such as `512mb` or
+/// `1gb` like in `redis.conf`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MemorySize(pub(crate) usize);
+
+impl FromStr for MemorySize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        let multiplier: usize = match unit {
+            "" | "b" => 1,
+            "k" => 1000,
+            "kb" => 1024,
+            "m" => 1000 * 1000,
+            "mb" => 1024 * 1024,
+            "g" => 1000 * 1000 * 1000,
+            "gb" => 1024 * 1024 * 1024,
+            _ => return Err(anyhow::anyhow!("invalid memory unit '{}'", unit)),
+        };
+
+        number
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .map(Self)
+            .ok_or_else(|| anyhow::anyhow!("invalid memory size '{}'", s))
+    }
+}
+
+impl Display for MemorySize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!("1024".parse::<MemorySize>().unwrap(), MemorySize(1024));
+        assert_eq!("2k".parse::<MemorySize>().unwrap(), MemorySize(2000));
+        assert_eq!(
+            "512MB".parse::<MemorySize>().unwrap(),
+            MemorySize(512 << 20)
+        );
+        assert_eq!(
+            "1gb".parse::<MemorySize>().unwrap().to_string(),
+            "1073741824"
+        );
+
+        assert!("".parse::<MemorySize>().is_err());
+        assert!("mb".parse::<MemorySize>().is_err());
+        assert!("10tb".parse::<MemorySize>().is_err());
+        assert!("-1".parse::<MemorySize>().is_err());
+    }
+}
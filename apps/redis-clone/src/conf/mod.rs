@@ -6,13 +6,121 @@ use std::{
 
 use crate::Cli;
 
+pub(crate) use self::encoding::EncodingLimits;
+pub(crate) use self::eviction::MaxmemoryPolicy;
+pub(crate) use self::memory::MemorySize;
+pub(crate) use self::notify::KeyspaceEvents;
+
+use crate::resp::ProtocolLimits;
+
+mod encoding;
+mod eviction;
+mod memory;
+mod notify;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     persistence: PersistenceConfig,
     replication: ReplicationConfig,
+    /// Frequency of background tasks such as the active expire cycle
+    hz: u32,
+    notify_keyspace_events: KeyspaceEvents,
+    /// Limits of client requests, applied to new connections
+    protocol_limits: ProtocolLimits,
+    /// Memory the keys may use before they are evicted, `0` for no limit
+    maxmemory: usize,
+    maxmemory_policy: MaxmemoryPolicy,
+    /// Number of keys compared to pick the key to evict
+    maxmemory_samples: usize,
+    encoding_limits: EncodingLimits,
+    /// Number of logical databases, selected with `SELECT`
+    databases: usize,
+    /// Number of core threads owning the keyspace, `None` unless running a
+    /// thread per core
+    cores: Option<usize>,
 }
 
 impl Config {
+    pub(crate) fn hz(&self) -> u32 {
+        self.hz
+    }
+
+    pub(crate) fn notify_keyspace_events(&self) -> KeyspaceEvents {
+        self.notify_keyspace_events
+    }
+
+    pub(crate) fn protocol_limits(&self) -> ProtocolLimits {
+        self.protocol_limits
+    }
+
+    pub(crate) fn maxmemory(&self) -> usize {
+        self.maxmemory
+    }
+
+    pub(crate) fn maxmemory_policy(&self) -> MaxmemoryPolicy {
+        self.maxmemory_policy
+    }
+
+    pub(crate) fn maxmemory_samples(&self) -> usize {
+        self.maxmemory_samples
+    }
+
+    pub(crate) fn encoding_limits(&self) -> EncodingLimits {
+        self.encoding_limits
+    }
+
+    pub(crate) fn databases(&self) -> usize {
+        self.databases
+    }
+
+    pub(crate) fn cores(&self) -> Option<usize> {
+        self.cores
+    }
+
+    /// Changes a parameter at runtime, as done by `CONFIG SET`.
+    pub(crate) fn set(&mut self, param: &str, value: &str) -> Result<(), anyhow::Error> {
+        let invalid = || anyhow::anyhow!("Invalid argument '{}' for CONFIG SET '{}'", value, param);
+
+        if self.encoding_limits.set(param, value)? {
+            return Ok(());
+        }
+
+        match param {
+            "notify-keyspace-events" => {
+                self.notify_keyspace_events = value.parse().map_err(|_| invalid())?;
+            }
+            "proto-max-bulk-len" => {
+                let MemorySize(len) = value.parse().map_err(|_| invalid())?;
+                self.protocol_limits.max_bulk_len = len;
+            }
+            "client-query-buffer-limit" => {
+                let MemorySize(limit) = value.parse().map_err(|_| invalid())?;
+                self.protocol_limits.query_buffer_limit = limit;
+            }
+            "maxmemory" => {
+                let MemorySize(maxmemory) = value.parse().map_err(|_| invalid())?;
+                self.maxmemory = maxmemory;
+            }
+            "maxmemory-policy" => {
+                self.maxmemory_policy = value.parse().map_err(|_| invalid())?;
+            }
+            "maxmemory-samples" => {
+                self.maxmemory_samples = match value.parse() {
+                    Ok(samples @ 1..=64) => samples,
+                    _ => return Err(invalid()),
+                };
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown option or number of arguments for CONFIG SET - '{}'",
+                    param
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn replication(&self) -> ReplicationConfig {
         self.replication.clone()
     }
@@ -42,6 +150,21 @@ impl From<Cli> for Config {
                 master_replid: crate::utils::random_string(40),
                 master_repl_offset: 0,
             },
+            hz: cli.hz.clamp(1, 500),
+            notify_keyspace_events: cli.notify_keyspace_events,
+            protocol_limits: ProtocolLimits {
+                max_bulk_len: cli.proto_max_bulk_len.0,
+                query_buffer_limit: cli.client_query_buffer_limit.0,
+            },
+            maxmemory: cli.maxmemory.0,
+            maxmemory_policy: cli.maxmemory_policy,
+            maxmemory_samples: cli.maxmemory_samples.clamp(1, 64),
+            encoding_limits: EncodingLimits::default(),
+            databases: cli.databases.max(1),
+            cores: cli.thread_per_core.then(|| {
+                let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+                cli.cores.unwrap_or(cpus).max(1)
+            }),
         }
     }
 }
@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Classes of keyspace events published to pub/sub, configured with the
+/// `notify-keyspace-events` flag letters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct KeyspaceEvents(u16);
+
+impl KeyspaceEvents {
+    /// `K`, publish to `__keyspace@<db>__:<key>`
+    pub(crate) const KEYSPACE: Self = Self(1 << 0);
+    /// `E`, publish to `__keyevent@<db>__:<event>`
+    pub(crate) const KEYEVENT: Self = Self(1 << 1);
+    /// `g`, generic commands such as `DEL`, `EXPIRE` and `RENAME`
+    pub(crate) const GENERIC: Self = Self(1 << 2);
+    /// `$`, string commands
+    pub(crate) const STRING: Self = Self(1 << 3);
+    /// `l`, list commands
+    pub(crate) const LIST: Self = Self(1 << 4);
+    /// `s`, set commands
+    pub(crate) const SET: Self = Self(1 << 5);
+    /// `h`, hash commands
+    pub(crate) const HASH: Self = Self(1 << 6);
+    /// `z`, sorted set commands
+    pub(crate) const ZSET: Self = Self(1 << 7);
+    /// `x`, keys deleted because they expired
+    pub(crate) const EXPIRED: Self = Self(1 << 8);
+    /// `e`, keys deleted because of `maxmemory`
+    pub(crate) const EVICTED: Self = Self(1 << 9);
+    /// `t`, stream commands
+    pub(crate) const STREAM: Self = Self(1 << 10);
+    /// `m`, key misses, not included in `A`
+    pub(crate) const KEY_MISS: Self = Self(1 << 11);
+    /// `d`, module key types
+    pub(crate) const MODULE: Self = Self(1 << 12);
+    /// `n`, new keys, not included in `A`
+    pub(crate) const NEW: Self = Self(1 << 13);
+
+    /// `A`, alias for `g$lshzxetd`
+    const ALL: Self = Self(
+        Self::GENERIC.0
+            | Self::STRING.0
+            | Self::LIST.0
+            | Self::SET.0
+            | Self::HASH.0
+            | Self::ZSET.0
+            | Self::EXPIRED.0
+            | Self::EVICTED.0
+            | Self::STREAM.0
+            | Self::MODULE.0,
+    );
+
+    const LETTERS: [(char, Self); 10] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('d', Self::MODULE),
+    ];
+
+    pub(crate) fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns whether events of `class` are published to any kind of channel.
+    pub(crate) fn enabled(&self, class: Self) -> bool {
+        self.0 & class.0 != 0 && self.0 & (Self::KEYSPACE.0 | Self::KEYEVENT.0) != 0
+    }
+}
+
+impl FromStr for KeyspaceEvents {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = 0;
+
+        for c in s.chars() {
+            flags |= match c {
+                'A' => Self::ALL.0,
+                'K' => Self::KEYSPACE.0,
+                'E' => Self::KEYEVENT.0,
+                'm' => Self::KEY_MISS.0,
+                'n' => Self::NEW.0,
+                c => match Self::LETTERS.iter().find(|(letter, _)| *letter == c) {
+                    Some((_, class)) => class.0,
+                    None => return Err(anyhow::anyhow!("invalid keyspace events flag '{}'", c)),
+                },
+            };
+        }
+
+        Ok(Self(flags))
+    }
+}
+
+impl Display for KeyspaceEvents {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.contains(Self::ALL) {
+            write!(f, "A")?;
+        } else {
+            for (letter, class) in Self::LETTERS {
+                if self.contains(class) {
+                    write!(f, "{}", letter)?;
+                }
+            }
+        }
+
+        for (letter, class) in [
+            ('K', Self::KEYSPACE),
+            ('E', Self::KEYEVENT),
+            ('m', Self::KEY_MISS),
+            ('n', Self::NEW),
+        ] {
+            if self.contains(class) {
+                write!(f, "{}", letter)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_flags() {
+        let events: KeyspaceEvents = "KEA".parse().unwrap();
+
+        assert!(events.enabled(KeyspaceEvents::EXPIRED));
+        assert!(!events.enabled(KeyspaceEvents::KEY_MISS));
+        assert_eq!(events.to_string(), "AKE");
+
+        let events: KeyspaceEvents = "Ex$gn".parse().unwrap();
+        assert_eq!(events.to_string(), "g$xEn");
+
+        let events: KeyspaceEvents = "g$".parse().unwrap();
+        assert!(!events.enabled(KeyspaceEvents::GENERIC));
+
+        assert!("Kq".parse::<KeyspaceEvents>().is_err());
+        assert_eq!(KeyspaceEvents::default().to_string(), "");
+    }
+}
@@ -1,22 +1,31 @@
-use std::io::Cursor;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use bytes::{Buf, BytesMut};
+use bytes::BytesMut;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
 
-use crate::resp::{RespParseError, RespValue};
+use crate::resp::{Decoder, Protocol, ProtocolLimits, RespParseError, RespValue};
+
+/// Source of the ids reported to clients by `HELLO`
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug)]
 pub struct Connection {
     read_connection: ConnectionRead,
     write_connection: ConnectionWrite,
+
+    client_id: u64,
+    #[allow(dead_code)]
+    name: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct ConnectionRead {
     stream: OwnedReadHalf,
     buffer: BytesMut,
+    decoder: Decoder,
+    limits: ProtocolLimits,
 
     id: String,
 }
@@ -24,6 +33,8 @@ pub struct ConnectionRead {
 #[derive(Debug)]
 pub struct ConnectionWrite {
     stream: BufWriter<OwnedWriteHalf>,
+    /// Protocol the responses are encoded with, switched by `HELLO`
+    protocol: Protocol,
 
     #[allow(dead_code)]
     id: String,
@@ -35,12 +46,22 @@ pub enum ConnectionError {
     ResetByPeer,
     #[error("Failed to read from connection")]
     ReadFailed,
+    #[error("Protocol error: {0}")]
+    Protocol(RespParseError),
+    #[error("Max query buffer length reached")]
+    QueryBufferLimit,
 }
 
 impl Connection {
     pub(crate) fn new(socket: TcpStream) -> Self {
         use nanoid::nanoid;
 
+        // replies are batched by the connection, so Nagle's algorithm would only
+        // delay them waiting for the client to acknowledge the previous batch
+        if let Err(e) = socket.set_nodelay(true) {
+            println!("Failed to disable Nagle's algorithm: {}", e);
+        }
+
         let (read_connection, write_connection) = socket.into_split();
 
         let id = nanoid!(10);
@@ -49,12 +70,17 @@ impl Connection {
             read_connection: ConnectionRead {
                 stream: read_connection,
                 buffer: BytesMut::with_capacity(4096),
+                decoder: Decoder::new(ProtocolLimits::default()),
+                limits: ProtocolLimits::default(),
                 id: id.clone(),
             },
             write_connection: ConnectionWrite {
                 stream: BufWriter::new(write_connection),
+                protocol: Protocol::default(),
                 id: id.clone(),
             },
+            client_id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            name: None,
         }
     }
 
@@ -62,10 +88,20 @@ impl Connection {
         self.read_connection.read().await
     }
 
+    /// Returns the next request already in the buffer without reading from the
+    /// socket, so pipelined requests can be handled before flushing the replies.
+    pub(crate) fn read_buffered(&mut self) -> Result<Option<(RespValue, usize)>, ConnectionError> {
+        self.read_connection.parse_resp()
+    }
+
     pub(crate) async fn write(&mut self, response: &RespValue) -> usize {
         self.write_connection.write(response).await
     }
 
+    pub(crate) async fn send(&mut self, response: &RespValue) -> usize {
+        self.write_connection.send(response).await
+    }
+
     pub(crate) async fn write_bytes(&mut self, response: &[u8]) {
         self.write_connection.write_bytes(response).await
     }
@@ -82,6 +118,28 @@ impl Connection {
     pub(crate) fn id(&self) -> String {
         self.read_connection.id()
     }
+
+    /// Sets the limits of the requests read from the connection.
+    pub(crate) fn set_limits(&mut self, limits: ProtocolLimits) {
+        self.read_connection.decoder.set_limits(limits);
+        self.read_connection.limits = limits;
+    }
+
+    pub(crate) fn client_id(&self) -> u64 {
+        self.client_id
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    pub(crate) fn protocol(&self) -> Protocol {
+        self.write_connection.protocol
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: Protocol) {
+        self.write_connection.protocol = protocol;
+    }
 }
 
 impl ConnectionRead {
@@ -91,6 +149,11 @@ impl ConnectionRead {
                 return Ok((resp, len));
             }
 
+            // an incomplete request must not grow the buffer without bounds
+            if self.buffer.len() + self.decoder.buffered() > self.limits.query_buffer_limit {
+                return Err(ConnectionError::QueryBufferLimit);
+            }
+
             match self.stream.read_buf(&mut self.buffer).await {
                 Ok(0) => {
                     return Err(ConnectionError::ResetByPeer);
@@ -104,18 +167,11 @@ impl ConnectionRead {
     }
 
     fn parse_resp(&mut self) -> Result<Option<(RespValue, usize)>, ConnectionError> {
-        let mut buf: Cursor<&[u8]> = Cursor::new(&self.buffer[..]);
-
-        match RespValue::from_bytes(&mut buf) {
-            Ok(resp) => {
-                let len = buf.position() as usize;
-                self.buffer.advance(len);
-
-                Ok(Some((resp, len)))
-            }
+        match self.decoder.decode(&mut self.buffer) {
+            Ok((resp, len)) => Ok(Some((resp, len))),
             Err(RespParseError::Incomplete) => Ok(None),
             Err(RespParseError::MissingNewline) => Ok(None),
-            Err(_) => Err(ConnectionError::ReadFailed),
+            Err(e) => Err(ConnectionError::Protocol(e)),
         }
     }
 
@@ -125,8 +181,9 @@ impl ConnectionRead {
 }
 
 impl ConnectionWrite {
+    /// Buffers the response, it is sent once the buffer is full or flushed.
     pub(crate) async fn write(&mut self, response: &RespValue) -> usize {
-        let response = response.to_buf();
+        let response = response.to_buf(self.protocol);
 
         let len = response.len();
 
@@ -135,12 +192,14 @@ impl ConnectionWrite {
             .await
             .expect("Failed to write to connection");
 
-        // Flush the stream to ensure the response is sent immediately. This is necessary because
-        // the stream is buffered and the response may not be sent immediately.
-        self.stream
-            .flush()
-            .await
-            .expect("Failed to flush connection");
+        len
+    }
+
+    /// Writes the response and sends it right away, for exchanges that wait for
+    /// a reply instead of batching responses.
+    pub(crate) async fn send(&mut self, response: &RespValue) -> usize {
+        let len = self.write(response).await;
+        self.flush().await;
 
         len
     }
@@ -164,3 +223,43 @@ impl ConnectionWrite {
         self.id.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pipelined_replies_are_flushed_together() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let mut connection = Connection::new(listener.accept().await.unwrap().0);
+
+        client
+            .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n*1\r\n$4")
+            .await
+            .unwrap();
+
+        let (request, _) = connection.read().await.unwrap();
+        connection
+            .write(&RespValue::SimpleString("PONG".into()))
+            .await;
+
+        // the second request is already buffered, the third is incomplete
+        assert_eq!(connection.read_buffered().unwrap(), Some((request, 14)));
+        connection
+            .write(&RespValue::SimpleString("PONG".into()))
+            .await;
+        assert_eq!(connection.read_buffered().unwrap(), None);
+
+        connection.flush().await;
+
+        let mut replies = [0; 14];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(&replies, b"+PONG\r\n+PONG\r\n");
+    }
+}
@@ -0,0 +1,429 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::commands::Command;
+use crate::conf::ReplicationRole;
+use crate::db::{self, Db};
+use crate::resp::RespValue;
+use crate::transaction::{self, Transaction};
+
+/// Work sent to the thread of a core
+enum Job {
+    /// Command whose keys all belong to the core
+    Execute {
+        command: Command,
+        request: RespValue,
+        /// Database selected by the connection
+        db: Db,
+        reply: oneshot::Sender<Option<RespValue>>,
+    },
+    /// Lends the shards of the core to `holder`, which gives them back by
+    /// dropping the sender of `returned`
+    Lend {
+        holder: usize,
+        lent: oneshot::Sender<()>,
+        returned: oneshot::Receiver<()>,
+    },
+}
+
+/// Error of a command needing a core whose thread stopped
+#[derive(Debug, thiserror::Error)]
+#[error("ERR core {0} is not running")]
+pub(crate) struct CoreStopped(usize);
+
+/// Threads that each run a single threaded runtime and own a part of the
+/// keyspace, the shards with `shard % cores == core`, which they use without
+/// locking them. Connections are spread over the cores and send each command
+/// to the cores owning its keys:
+///
+/// - commands whose keys all belong to one core are executed by its thread,
+///   one after another;
+/// - commands on keys of several cores or on the whole keyspace, transactions
+///   and watched keys borrow the shards of the cores they need, in the order of
+///   the cores so that they can't deadlock, and run on the task of the
+///   connection while those cores wait for their shards back.
+#[derive(Clone)]
+pub(crate) struct Cores {
+    jobs: Arc<[mpsc::UnboundedSender<Job>]>,
+    connections: Arc<[mpsc::UnboundedSender<std::net::TcpStream>]>,
+    next: Arc<AtomicUsize>,
+    db: Db,
+}
+
+impl Cores {
+    /// Starts a thread for each core owning the keyspace of `db`, which must be
+    /// created in thread per core mode. `handle` serves the connections assigned
+    /// to a core.
+    pub(crate) fn start<F, Fut>(db: &Db, handle: F) -> Self
+    where
+        F: Fn(TcpStream, Db, Cores) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let count = db.cores().expect("the keyspace is owned by cores");
+
+        let (jobs, job_receivers): (Vec<_>, Vec<_>) =
+            (0..count).map(|_| mpsc::unbounded_channel()).unzip();
+        let (connections, connection_receivers): (Vec<_>, Vec<_>) =
+            (0..count).map(|_| mpsc::unbounded_channel()).unzip();
+
+        let cores = Self {
+            jobs: jobs.into(),
+            connections: connections.into(),
+            next: Arc::new(AtomicUsize::new(0)),
+            db: db.clone(),
+        };
+
+        for (index, (jobs, connections)) in job_receivers
+            .into_iter()
+            .zip(connection_receivers)
+            .enumerate()
+        {
+            let cores = cores.clone();
+            let handle = handle.clone();
+
+            std::thread::Builder::new()
+                .name(format!("core-{}", index))
+                .spawn(move || cores.run(index, jobs, connections, handle))
+                .expect("failed to start core thread");
+        }
+
+        println!("Started {} core threads", count);
+
+        cores
+    }
+
+    fn len(&self) -> usize {
+        self.jobs.len()
+    }
+
+    /// Hands a new connection to the next core.
+    pub(crate) fn assign(&self, stream: TcpStream) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.len();
+
+        match stream.into_std() {
+            Ok(stream) => {
+                let _ = self.connections[index].send(stream);
+            }
+            Err(e) => println!("Failed to assign connection: {}", e),
+        }
+    }
+
+    /// Returns the cores owning `keys` in the order of their index, every core
+    /// if `keys` is `None`.
+    fn owners(&self, keys: Option<Vec<&Bytes>>) -> Vec<usize> {
+        let Some(keys) = keys else {
+            return (0..self.len()).collect();
+        };
+
+        let mut owners: Vec<usize> = keys.iter().map(|key| self.db.core_of(key)).collect();
+
+        owners.sort_unstable();
+        owners.dedup();
+
+        owners
+    }
+
+    /// Runs the thread of core `index` until the server stops: executes the
+    /// commands sent to the core, lends its shards, expires its keys and serves
+    /// the connections assigned to it.
+    fn run<F, Fut>(
+        self,
+        index: usize,
+        mut jobs: mpsc::UnboundedReceiver<Job>,
+        mut connections: mpsc::UnboundedReceiver<std::net::TcpStream>,
+        handle: F,
+    ) where
+        F: Fn(TcpStream, Db, Cores) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start core runtime");
+
+        let config = self.db.config();
+        let master = matches!(config.replication().role, ReplicationRole::Master);
+
+        // the loop is the only task of the thread using its shards, the tasks of
+        // the connections go through its jobs
+        runtime.block_on(db::hold(index, async move {
+            let mut expire = tokio::time::interval(db::active_expire_period(config.hz()));
+            let mut expire_index = 0;
+
+            loop {
+                tokio::select! {
+                    Some(job) = jobs.recv() => match job {
+                        Job::Execute { command, request, db, reply } => {
+                            let resp = transaction::execute_command(&command, &request, &db).await;
+                            let _ = reply.send(resp);
+                        }
+                        Job::Lend { holder, lent, returned } => {
+                            self.db.hand_over(index, holder);
+
+                            if lent.send(()).is_ok() {
+                                let _ = returned.await;
+                            }
+
+                            self.db.hand_over(index, index);
+                        }
+                    },
+                    Some(stream) = connections.recv() => {
+                        match TcpStream::from_std(stream) {
+                            Ok(stream) => {
+                                tokio::spawn(handle(stream, self.db.clone(), self.clone()));
+                            }
+                            Err(e) => println!("Failed to accept connection on core {}: {}", index, e),
+                        }
+                    }
+                    _ = expire.tick(), if master => {
+                        self.db.active_expire_cycle(config.hz(), &mut expire_index).await;
+                    }
+                    else => break,
+                }
+            }
+        }));
+    }
+
+    /// Executes a command of a connection on its selected database `db`, see
+    /// [`Transaction::execute`].
+    pub(crate) async fn execute(
+        &self,
+        command: Command,
+        request: &RespValue,
+        transaction: &mut Transaction,
+        db: &mut Db,
+    ) -> Option<RespValue> {
+        let owners = self.owners(transaction.keys(&command));
+
+        if let [owner] = owners[..] {
+            if transaction.executes_directly(&command) {
+                return self.forward(owner, command, request, db).await;
+            }
+        }
+
+        self.exclusive(&owners, transaction.execute(command, request, db))
+            .await
+            .unwrap_or_else(|e| Some(RespValue::SimpleError(e.to_string())))
+    }
+
+    /// Runs `f` on the current task holding the shards of every core.
+    pub(crate) async fn exclusive_all<F: Future>(&self, f: F) -> Result<F::Output, CoreStopped> {
+        self.exclusive(&self.owners(None), f).await
+    }
+
+    /// Forgets the keys watched by a connection once it is closed.
+    pub(crate) async fn unwatch(&self, transaction: &mut Transaction) {
+        let owners = self.owners(Some(transaction.watched_keys().collect()));
+
+        if let Err(e) = self.exclusive(&owners, transaction.unwatch()).await {
+            println!("Failed to unwatch keys: {}", e);
+        }
+    }
+
+    /// Sends a command to the thread of core `owner` and waits for its reply.
+    async fn forward(
+        &self,
+        owner: usize,
+        command: Command,
+        request: &RespValue,
+        db: &Db,
+    ) -> Option<RespValue> {
+        let (reply, response) = oneshot::channel();
+
+        let job = Job::Execute {
+            command,
+            request: request.clone(),
+            db: db.clone(),
+            reply,
+        };
+
+        if self.jobs[owner].send(job).is_err() {
+            return Some(RespValue::SimpleError(CoreStopped(owner).to_string()));
+        }
+
+        response.await.unwrap_or_else(|_| {
+            Some(RespValue::SimpleError(format!(
+                "ERR core {} dropped the command",
+                owner
+            )))
+        })
+    }
+
+    /// Runs `f` on the current task holding the shards of `cores`, given in the
+    /// order of their index. The cores are borrowed one after another in that
+    /// order, and wait for their shards back until `f` is done.
+    pub(crate) async fn exclusive<F: Future>(
+        &self,
+        cores: &[usize],
+        f: F,
+    ) -> Result<F::Output, CoreStopped> {
+        let holder = self.db.new_holder();
+
+        // dropping a sender gives the shards back to their core
+        let mut loans = Vec::with_capacity(cores.len());
+
+        for &core in cores {
+            let (lent, lent_receiver) = oneshot::channel();
+            let (loan, returned) = oneshot::channel::<()>();
+
+            let job = Job::Lend {
+                holder,
+                lent,
+                returned,
+            };
+
+            if self.jobs[core].send(job).is_err() || lent_receiver.await.is_err() {
+                return Err(CoreStopped(core));
+            }
+
+            loans.push(loan);
+        }
+
+        let output = db::hold(holder, f).await;
+        drop(loans);
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::test_utils::{command, db_with, request};
+
+    fn start() -> (Cores, Db) {
+        let db = db_with(&["--thread-per-core", "--cores", "4"]);
+        let cores = Cores::start(&db, |_, _, _| async {});
+
+        (cores, db)
+    }
+
+    /// Returns a key owned by core `owner`.
+    fn key(db: &Db, owner: usize) -> String {
+        (0..)
+            .map(|i| format!("key{i}"))
+            .find(|key| db.core_of(key.as_bytes()) == owner)
+            .unwrap()
+    }
+
+    async fn send(
+        cores: &Cores,
+        transaction: &mut Transaction,
+        db: &mut Db,
+        args: &[&str],
+    ) -> Option<RespValue> {
+        cores
+            .execute(command(args), &request(args), transaction, db)
+            .await
+    }
+
+    async fn get(cores: &Cores, db: &Db, key: &str) -> Option<Bytes> {
+        cores
+            .exclusive_all(db.get(key.as_bytes()))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn ok() -> Option<RespValue> {
+        Some(RespValue::SimpleString("OK".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_commands_need_the_cores_owning_their_keys() {
+        let (cores, db) = start();
+        let (a, b) = (key(&db, 1), key(&db, 2));
+
+        let owners = |args: &[&str]| cores.owners(Transaction::new().keys(&command(args)));
+
+        assert_eq!(owners(&["GET", &a]), vec![1]);
+        assert_eq!(owners(&["DEL", &b, &a, &b]), vec![1, 2]);
+        assert_eq!(owners(&["PING"]), Vec::<usize>::new());
+        assert_eq!(owners(&["DBSIZE"]), vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_commands_run_on_the_owners_of_their_keys() {
+        let (cores, mut db) = start();
+        let (a, b) = (key(&db, 1), key(&db, 2));
+        let mut transaction = Transaction::new();
+
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["SET", &a, "1"]).await,
+            ok()
+        );
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["SET", &b, "2"]).await,
+            ok()
+        );
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["GET", &a]).await,
+            Some(RespValue::BulkString(Bytes::from("1")))
+        );
+
+        // the keys belong to two cores
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["RENAME", &a, &b]).await,
+            ok()
+        );
+
+        assert_eq!(get(&cores, &db, &a).await, None);
+        assert_eq!(get(&cores, &db, &b).await, Some(Bytes::from("1")));
+    }
+
+    #[tokio::test]
+    async fn test_transactions_span_cores() {
+        let (cores, mut db) = start();
+        let (a, b) = (key(&db, 0), key(&db, 3));
+        let mut transaction = Transaction::new();
+
+        send(&cores, &mut transaction, &mut db, &["MULTI"]).await;
+        send(&cores, &mut transaction, &mut db, &["SET", &a, "1"]).await;
+        send(&cores, &mut transaction, &mut db, &["SET", &b, "2"]).await;
+
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::Array(vec![ok().unwrap(), ok().unwrap()]))
+        );
+        assert_eq!(get(&cores, &db, &a).await, Some(Bytes::from("1")));
+        assert_eq!(get(&cores, &db, &b).await, Some(Bytes::from("2")));
+    }
+
+    #[tokio::test]
+    async fn test_modified_watched_key_aborts_exec_on_another_core() {
+        let (cores, mut db) = start();
+        let (a, b) = (key(&db, 1), key(&db, 2));
+        let mut transaction = Transaction::new();
+
+        send(&cores, &mut transaction, &mut db, &["WATCH", &a]).await;
+        send(&cores, &mut Transaction::new(), &mut db, &["SET", &a, "1"]).await;
+
+        send(&cores, &mut transaction, &mut db, &["MULTI"]).await;
+        send(&cores, &mut transaction, &mut db, &["SET", &b, "2"]).await;
+
+        assert_eq!(
+            send(&cores, &mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::NullArray)
+        );
+        assert_eq!(get(&cores, &db, &b).await, None);
+
+        send(&cores, &mut transaction, &mut db, &["WATCH", &a]).await;
+        cores.unwatch(&mut transaction).await;
+        assert_eq!(transaction.watched_keys().count(), 0);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "used by a task not holding it")]
+    async fn test_shards_are_only_used_by_their_holder() {
+        let (_cores, db) = start();
+
+        let _ = db.get(b"key").await;
+    }
+}
@@ -0,0 +1,568 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use bytes::Bytes;
+
+use super::canonical_int;
+use super::table::Table;
+use crate::conf::EncodingLimits;
+
+/// Approximate memory used by an element of a full structure besides its data,
+/// for the node or table slot holding it
+pub(super) const ELEMENT_OVERHEAD: usize = 16;
+
+/// Elements stored back to back in a single allocation, each prefixed with its
+/// length as a varint. Used by small collections instead of full structures.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Listpack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    fn push(&mut self, element: &[u8]) {
+        let mut len = element.len();
+
+        while len >= 0x80 {
+            self.buf.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+
+        self.buf.push(len as u8);
+        self.buf.extend_from_slice(element);
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let mut buf = &self.buf[..];
+
+        std::iter::from_fn(move || {
+            let mut len = 0;
+            let mut shift = 0;
+
+            loop {
+                let (&byte, rest) = buf.split_first()?;
+                buf = rest;
+                len |= ((byte & 0x7F) as usize) << shift;
+                shift += 7;
+
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+
+            let (element, rest) = buf.split_at(len);
+            buf = rest;
+
+            Some(element)
+        })
+    }
+
+    /// Iterates the elements two by two.
+    fn pairs(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let mut elements = self.iter();
+
+        std::iter::from_fn(move || Some((elements.next()?, elements.next()?)))
+    }
+
+    fn memory(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for Listpack {
+    fn from_iter<I: IntoIterator<Item = T>>(elements: I) -> Self {
+        let mut listpack = Listpack::default();
+
+        for element in elements {
+            listpack.push(element.as_ref());
+        }
+
+        listpack
+    }
+}
+
+/// Elements of a collection scanned by [`Hash::scan`], [`Set::scan`] or
+/// [`SortedSet::scan`], with the field value or score that follows them
+pub(super) type Scanned = Vec<(Bytes, Option<Bytes>)>;
+
+/// Visits the buckets of `table` from `cursor` until `count` entries were
+/// visited or the iteration is complete, and returns the next cursor.
+fn scan_table<V>(
+    table: &Table<V>,
+    mut cursor: u64,
+    count: usize,
+    mut visit: impl FnMut(&Bytes, &V),
+) -> u64 {
+    let mut visited = 0;
+
+    loop {
+        cursor = table.scan(cursor, |key, value| {
+            visited += 1;
+            visit(key, value);
+        });
+
+        if cursor == 0 || visited >= count {
+            return cursor;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Hash {
+    /// Fields and values alternating
+    Listpack(Listpack),
+    Table(Table<Bytes>),
+}
+
+impl Hash {
+    pub(super) fn new(pairs: Vec<(Bytes, Bytes)>, limits: &EncodingLimits) -> Self {
+        let compact = pairs.len() <= limits.hash_max_listpack_entries
+            && pairs.iter().all(|(field, value)| {
+                field.len() <= limits.hash_max_listpack_value
+                    && value.len() <= limits.hash_max_listpack_value
+            });
+
+        if compact {
+            Hash::Listpack(
+                pairs
+                    .iter()
+                    .flat_map(|(field, value)| [field, value])
+                    .collect(),
+            )
+        } else {
+            Hash::Table(pairs.into_iter().collect())
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Hash::Listpack(listpack) => listpack.len() / 2,
+            Hash::Table(table) => table.len(),
+        }
+    }
+
+    pub(super) fn memory(&self) -> usize {
+        match self {
+            Hash::Listpack(listpack) => listpack.memory(),
+            Hash::Table(table) => table
+                .iter()
+                .map(|(field, value)| field.len() + value.len() + ELEMENT_OVERHEAD)
+                .sum(),
+        }
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        match self {
+            Hash::Listpack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        }
+    }
+
+    pub(super) fn pairs(&self) -> Vec<(Bytes, Bytes)> {
+        match self {
+            Hash::Listpack(listpack) => listpack
+                .pairs()
+                .map(|(field, value)| {
+                    (Bytes::copy_from_slice(field), Bytes::copy_from_slice(value))
+                })
+                .collect(),
+            Hash::Table(table) => table
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Fields and values from `cursor`, see [`Table::scan`]. Listpacks are
+    /// returned whole, with cursor `0`.
+    pub(super) fn scan(&self, cursor: u64, count: usize) -> (u64, Scanned) {
+        match self {
+            Hash::Listpack(_) => {
+                let pairs = self.pairs().into_iter();
+                (
+                    0,
+                    pairs.map(|(field, value)| (field, Some(value))).collect(),
+                )
+            }
+            Hash::Table(table) => {
+                let mut pairs = vec![];
+                let cursor = scan_table(table, cursor, count, |field, value| {
+                    pairs.push((field.clone(), Some(value.clone())))
+                });
+                (cursor, pairs)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum List {
+    Listpack(Listpack),
+    /// Linked listpacks, each filled up to `list-max-listpack-size`
+    Quicklist(VecDeque<Listpack>),
+}
+
+impl List {
+    pub(super) fn new(elements: Vec<Bytes>, limits: &EncodingLimits) -> Self {
+        let mut nodes = VecDeque::new();
+        let mut node = Listpack::default();
+
+        for element in elements {
+            let full = match limits.list_max_listpack_size {
+                entries @ 1.. => node.len() >= entries as usize,
+                size => {
+                    let max = 4096 << (-size - 1);
+                    node.len() > 0 && node.memory() + element.len() > max
+                }
+            };
+
+            if full {
+                nodes.push_back(std::mem::take(&mut node));
+            }
+
+            node.push(&element);
+        }
+
+        if nodes.is_empty() {
+            return List::Listpack(node);
+        }
+
+        nodes.push_back(node);
+        List::Quicklist(nodes)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            List::Listpack(listpack) => listpack.len(),
+            List::Quicklist(nodes) => nodes.iter().map(Listpack::len).sum(),
+        }
+    }
+
+    pub(super) fn memory(&self) -> usize {
+        match self {
+            List::Listpack(listpack) => listpack.memory(),
+            List::Quicklist(nodes) => nodes
+                .iter()
+                .map(|node| node.memory() + ELEMENT_OVERHEAD)
+                .sum(),
+        }
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        match self {
+            List::Listpack(_) => "listpack",
+            List::Quicklist(_) => "quicklist",
+        }
+    }
+
+    pub(super) fn elements(&self) -> Vec<Bytes> {
+        match self {
+            List::Listpack(listpack) => listpack.iter().map(Bytes::copy_from_slice).collect(),
+            List::Quicklist(nodes) => nodes
+                .iter()
+                .flat_map(Listpack::iter)
+                .map(Bytes::copy_from_slice)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Set {
+    /// Sorted integers, for sets that only hold integers
+    Intset(Vec<i64>),
+    Listpack(Listpack),
+    Table(Table<()>),
+}
+
+impl Set {
+    pub(super) fn new(members: Vec<Bytes>, limits: &EncodingLimits) -> Self {
+        if members.len() <= limits.set_max_intset_entries {
+            let ints: Option<Vec<i64>> = members.iter().map(|m| canonical_int(m)).collect();
+
+            if let Some(mut ints) = ints {
+                ints.sort_unstable();
+                ints.dedup();
+                return Set::Intset(ints);
+            }
+        }
+
+        let compact = members.len() <= limits.set_max_listpack_entries
+            && members
+                .iter()
+                .all(|member| member.len() <= limits.set_max_listpack_value);
+
+        if compact {
+            let members: HashSet<Bytes> = members.into_iter().collect();
+            Set::Listpack(members.iter().collect())
+        } else {
+            Set::Table(members.into_iter().map(|member| (member, ())).collect())
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Set::Intset(ints) => ints.len(),
+            Set::Listpack(listpack) => listpack.len(),
+            Set::Table(table) => table.len(),
+        }
+    }
+
+    pub(super) fn memory(&self) -> usize {
+        match self {
+            Set::Intset(ints) => ints.len() * std::mem::size_of::<i64>(),
+            Set::Listpack(listpack) => listpack.memory(),
+            Set::Table(table) => table
+                .iter()
+                .map(|(member, _)| member.len() + ELEMENT_OVERHEAD)
+                .sum(),
+        }
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        match self {
+            Set::Intset(_) => "intset",
+            Set::Listpack(_) => "listpack",
+            Set::Table(_) => "hashtable",
+        }
+    }
+
+    pub(super) fn members(&self) -> Vec<Bytes> {
+        match self {
+            Set::Intset(ints) => ints.iter().map(|n| Bytes::from(n.to_string())).collect(),
+            Set::Listpack(listpack) => listpack.iter().map(Bytes::copy_from_slice).collect(),
+            Set::Table(table) => table.iter().map(|(member, _)| member.clone()).collect(),
+        }
+    }
+
+    /// Members from `cursor`, see [`Table::scan`]. Intsets and listpacks are
+    /// returned whole, with cursor `0`.
+    pub(super) fn scan(&self, cursor: u64, count: usize) -> (u64, Scanned) {
+        match self {
+            Set::Intset(_) | Set::Listpack(_) => {
+                (0, self.members().into_iter().map(|m| (m, None)).collect())
+            }
+            Set::Table(table) => {
+                let mut members = vec![];
+                let cursor = scan_table(table, cursor, count, |member, _| {
+                    members.push((member.clone(), None))
+                });
+                (cursor, members)
+            }
+        }
+    }
+}
+
+/// Score of a sorted set member, ordered with [`f64::total_cmp`]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum SortedSet {
+    /// Members and scores alternating, ordered by score then member
+    Listpack(Listpack),
+    Skiplist {
+        scores: Table<f64>,
+        ordered: BTreeSet<(Score, Bytes)>,
+    },
+}
+
+impl SortedSet {
+    pub(super) fn new(members: Vec<(Bytes, f64)>, limits: &EncodingLimits) -> Self {
+        let compact = members.len() <= limits.zset_max_listpack_entries
+            && members
+                .iter()
+                .all(|(member, _)| member.len() <= limits.zset_max_listpack_value);
+
+        // a member added twice keeps its last score
+        let scores: Table<f64> = members.into_iter().collect();
+        let ordered: BTreeSet<(Score, Bytes)> = scores
+            .iter()
+            .map(|(member, score)| (Score(*score), member.clone()))
+            .collect();
+
+        if compact {
+            let listpack = ordered
+                .iter()
+                .flat_map(|(score, member)| [member.clone(), Bytes::from(score.0.to_string())])
+                .collect();
+
+            SortedSet::Listpack(listpack)
+        } else {
+            SortedSet::Skiplist { scores, ordered }
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            SortedSet::Listpack(listpack) => listpack.len() / 2,
+            SortedSet::Skiplist { scores, .. } => scores.len(),
+        }
+    }
+
+    pub(super) fn memory(&self) -> usize {
+        match self {
+            SortedSet::Listpack(listpack) => listpack.memory(),
+            // each member is in the table and in the ordered set
+            SortedSet::Skiplist { ordered, .. } => ordered
+                .iter()
+                .map(|(_, member)| 2 * (member.len() + ELEMENT_OVERHEAD))
+                .sum(),
+        }
+    }
+
+    pub(super) fn encoding(&self) -> &'static str {
+        match self {
+            SortedSet::Listpack(_) => "listpack",
+            SortedSet::Skiplist { .. } => "skiplist",
+        }
+    }
+
+    /// Members with their scores, ordered by score then member.
+    pub(super) fn members(&self) -> Vec<(Bytes, f64)> {
+        match self {
+            // scores are formatted with `f64::to_string`, which parses back
+            SortedSet::Listpack(listpack) => listpack
+                .pairs()
+                .map(|(member, score)| {
+                    let score = std::str::from_utf8(score).ok().and_then(|s| s.parse().ok());
+                    (Bytes::copy_from_slice(member), score.unwrap_or(f64::NAN))
+                })
+                .collect(),
+            SortedSet::Skiplist { ordered, .. } => ordered
+                .iter()
+                .map(|(score, member)| (member.clone(), score.0))
+                .collect(),
+        }
+    }
+
+    /// Members and scores from `cursor`, see [`Table::scan`]. Listpacks are
+    /// returned whole, with cursor `0`.
+    pub(super) fn scan(&self, cursor: u64, count: usize) -> (u64, Scanned) {
+        let score = |score: f64| Some(Bytes::from(score.to_string()));
+
+        match self {
+            SortedSet::Listpack(_) => {
+                let members = self.members().into_iter();
+                (0, members.map(|(m, s)| (m, score(s))).collect())
+            }
+            SortedSet::Skiplist { scores, .. } => {
+                let mut members = vec![];
+                let cursor = scan_table(scores, cursor, count, |member, s| {
+                    members.push((member.clone(), score(*s)))
+                });
+                (cursor, members)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(elements: &[&str]) -> Vec<Bytes> {
+        elements
+            .iter()
+            .map(|e| Bytes::from(e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_encodings_follow_limits() {
+        let limits = EncodingLimits {
+            hash_max_listpack_entries: 2,
+            set_max_intset_entries: 2,
+            set_max_listpack_entries: 3,
+            zset_max_listpack_value: 4,
+            list_max_listpack_size: 2,
+            ..Default::default()
+        };
+
+        let set = Set::new(bytes(&["3", "1", "2"]), &limits);
+        assert_eq!((set.encoding(), set.len()), ("listpack", 3));
+        let set = Set::new(bytes(&["3", "-1"]), &limits);
+        assert!(matches!(set, Set::Intset(ref ints) if ints == &[-1, 3]));
+        let set = Set::new(bytes(&["a", "b", "c", "d"]), &limits);
+        assert_eq!(set.encoding(), "hashtable");
+
+        let pair = |f: &str| (Bytes::from(f.to_string()), Bytes::from("v"));
+        let hash = Hash::new(vec![pair("a"), pair("b")], &limits);
+        assert_eq!((hash.encoding(), hash.len()), ("listpack", 2));
+        let hash = Hash::new(vec![pair("a"), pair("b"), pair("c")], &limits);
+        assert_eq!((hash.encoding(), hash.len()), ("hashtable", 3));
+
+        let zset = SortedSet::new(vec![(Bytes::from("a"), 1.0)], &limits);
+        assert_eq!(zset.encoding(), "listpack");
+        let zset = SortedSet::new(vec![(Bytes::from("long member"), 1.0)], &limits);
+        assert_eq!(zset.encoding(), "skiplist");
+
+        let list = List::new(bytes(&["a", "b"]), &limits);
+        assert_eq!((list.encoding(), list.len()), ("listpack", 2));
+        let list = List::new(bytes(&["a", "b", "c", "d", "e"]), &limits);
+        assert!(matches!(list, List::Quicklist(ref nodes) if nodes.len() == 3));
+        assert_eq!(list.len(), 5);
+    }
+
+    #[test]
+    fn test_elements_are_read_back_in_every_encoding() {
+        let limits = EncodingLimits {
+            list_max_listpack_size: 2,
+            ..Default::default()
+        };
+
+        // longer than a single byte length prefix
+        let long = "x".repeat(200);
+        let elements = bytes(&["a", &long, "", "b", "c"]);
+
+        for list in [
+            List::new(elements.clone(), &EncodingLimits::default()),
+            List::new(elements.clone(), &limits),
+        ] {
+            assert_eq!(list.elements(), elements);
+        }
+
+        let pairs = vec![(Bytes::from("f"), Bytes::from(long.clone()))];
+        assert_eq!(Hash::new(pairs.clone(), &limits).pairs(), pairs);
+
+        let mut members = Set::new(bytes(&["b", "a"]), &limits).members();
+        members.sort();
+        assert_eq!(members, bytes(&["a", "b"]));
+        assert_eq!(
+            Set::new(bytes(&["2", "-1"]), &limits).members(),
+            bytes(&["-1", "2"])
+        );
+
+        let members = vec![
+            (Bytes::from("a"), f64::NEG_INFINITY),
+            (Bytes::from("b"), 0.1),
+            (Bytes::from("c"), 1e300),
+        ];
+        assert_eq!(SortedSet::new(members.clone(), &limits).members(), members);
+    }
+}
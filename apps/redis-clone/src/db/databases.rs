@@ -0,0 +1,310 @@
+use bytes::Bytes;
+
+use super::{Db, DbError, Shard};
+use crate::conf::KeyspaceEvents;
+use crate::utils::unix_millis;
+
+/// Keys of a database reported by the `keyspace` section of `INFO`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DatabaseInfo {
+    pub(crate) db: usize,
+    pub(crate) keys: usize,
+    pub(crate) expires: usize,
+    /// Average time to live of the keys with an expiration, in milliseconds
+    pub(crate) avg_ttl: u64,
+}
+
+impl Shard {
+    /// Marks the transactions watching a key of this shard as dirty if the key
+    /// exists here or in `other`, since their contents are about to be swapped.
+    fn signal_swapped_keys(&self, other: &Shard) {
+        for key in self.watched.keys() {
+            if self.contains(key) || other.contains(key) {
+                self.signal_modified_key(key);
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Moves a key of any type with its expiration to database `to`. Returns
+    /// `false` if the key doesn't exist or `to` already has it.
+    pub(crate) async fn move_key(&self, key: &[u8], to: usize) -> Result<bool, DbError> {
+        let target = self.select(to)?;
+
+        if to == self.index {
+            return Err(DbError::SameObject);
+        }
+
+        // databases are locked in the order of their index, like the shards of
+        // a single database
+        let (mut source, mut destination) = if self.index < to {
+            let source = self.shard(key).await;
+            (source, target.shard(key).await)
+        } else {
+            let destination = target.shard(key).await;
+            (self.shard(key).await, destination)
+        };
+
+        if source.lookup(key, &self.shared).is_none() {
+            return Ok(false);
+        }
+
+        destination.expire_if_needed(key, &self.shared);
+
+        if destination.contains(key) {
+            return Ok(false);
+        }
+
+        let entry = source.take(key, &self.shared).expect("key exists");
+        self.notify_keyspace_event(KeyspaceEvents::GENERIC, "move_from", key);
+
+        destination.insert(Bytes::copy_from_slice(key), entry, &self.shared);
+        target.notify_keyspace_event(KeyspaceEvents::GENERIC, "move_to", key);
+
+        Ok(true)
+    }
+
+    /// Copies a key of any type with its expiration to the key `to` of database
+    /// `db`. Returns `false` if the key doesn't exist, or if `to` exists there
+    /// and `replace` isn't set.
+    pub(crate) async fn copy_to_db(
+        &self,
+        from: &[u8],
+        to: &[u8],
+        db: usize,
+        replace: bool,
+    ) -> Result<bool, DbError> {
+        let target = self.select(db)?;
+
+        if db == self.index {
+            if from == to {
+                return Err(DbError::SameObject);
+            }
+
+            return Ok(self.copy(from, to, replace).await);
+        }
+
+        // locked in the order of the databases, like `move_key`
+        let (mut source, mut destination) = if self.index < db {
+            let source = self.shard(from).await;
+            (source, target.shard(to).await)
+        } else {
+            let destination = target.shard(to).await;
+            (self.shard(from).await, destination)
+        };
+
+        let Some(entry) = source.lookup(from, &self.shared).cloned() else {
+            return Ok(false);
+        };
+
+        destination.expire_if_needed(to, &self.shared);
+
+        if !replace && destination.contains(to) {
+            return Ok(false);
+        }
+
+        destination.insert(Bytes::copy_from_slice(to), entry, &self.shared);
+        target.notify_keyspace_event(KeyspaceEvents::GENERIC, "copy_to", to);
+
+        Ok(true)
+    }
+
+    /// Swaps the keys of two databases, so that clients connected to one of them
+    /// see the keys of the other one.
+    pub(crate) async fn swap(&self, a: usize, b: usize) -> Result<(), DbError> {
+        let (first, second) = (self.select(a.min(b))?, self.select(a.max(b))?);
+
+        if a == b {
+            return Ok(());
+        }
+
+        let mut firsts = Vec::with_capacity(first.shards().len());
+        let mut seconds = Vec::with_capacity(second.shards().len());
+
+        for shard in first.shards() {
+            firsts.push(shard.lock().await);
+        }
+
+        for shard in second.shards() {
+            seconds.push(shard.lock().await);
+        }
+
+        for (first, second) in firsts.iter_mut().zip(seconds.iter_mut()) {
+            first.signal_swapped_keys(second);
+            second.signal_swapped_keys(first);
+
+            // the watched keys stay with the database the clients selected
+            std::mem::swap(&mut first.keyspace, &mut second.keyspace);
+            std::mem::swap(&mut first.volatile, &mut second.volatile);
+            std::mem::swap(&mut first.memory, &mut second.memory);
+
+            first.expire_cursor = None;
+            second.expire_cursor = None;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of keys and keys with an expiration of the databases
+    /// that have keys.
+    pub(crate) async fn keyspace_info(&self) -> Vec<DatabaseInfo> {
+        let mut databases = vec![];
+
+        for (db, shards) in self.shared.databases.iter().enumerate() {
+            let mut info = DatabaseInfo {
+                db,
+                keys: 0,
+                expires: 0,
+                avg_ttl: 0,
+            };
+            let mut total_ttl = 0;
+
+            for shard in shards.iter() {
+                let shard = shard.lock().await;
+                let now = unix_millis();
+
+                info.keys += shard.keyspace.len();
+
+                for key in shard.volatile.iter() {
+                    if let Some(Some(expires_at)) = shard.expiration(key) {
+                        info.expires += 1;
+                        total_ttl += expires_at.saturating_sub(now);
+                    }
+                }
+            }
+
+            if info.keys > 0 {
+                info.avg_ttl = total_ttl.checked_div(info.expires as u64).unwrap_or(0);
+                databases.push(info);
+            }
+        }
+
+        databases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::test_utils::db_with;
+
+    fn db() -> Db {
+        db_with(&["--databases", "4"])
+    }
+
+    #[tokio::test]
+    async fn test_copy_to_db() {
+        let db = db();
+        let other = db.select(3).unwrap();
+
+        db.set(
+            "key".into(),
+            Bytes::from("value"),
+            Some(unix_millis() + 60_000),
+        )
+        .await;
+        other.set("taken".into(), Bytes::from("other"), None).await;
+
+        assert!(matches!(
+            db.copy_to_db(b"key", b"key", 0, false).await,
+            Err(DbError::SameObject)
+        ));
+        assert!(matches!(
+            db.copy_to_db(b"key", b"key", 4, false).await,
+            Err(DbError::InvalidDbIndex)
+        ));
+
+        assert!(db.copy_to_db(b"key", b"key", 3, false).await.unwrap());
+        assert!(!db.copy_to_db(b"missing", b"key", 3, true).await.unwrap());
+        assert_eq!(other.get(b"key").await.unwrap(), Some(Bytes::from("value")));
+        assert!(other.expires_at(b"key").await.unwrap().is_some());
+        assert_eq!(db.get(b"key").await.unwrap(), Some(Bytes::from("value")));
+
+        // copies from a higher database lock the databases in the same order
+        assert!(!other.copy_to_db(b"taken", b"key", 0, false).await.unwrap());
+        assert!(other.copy_to_db(b"taken", b"key", 0, true).await.unwrap());
+        assert_eq!(db.get(b"key").await.unwrap(), Some(Bytes::from("other")));
+        assert_eq!(db.expires_at(b"key").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_select_and_move() {
+        let db = db();
+        let other = db.select(2).unwrap();
+
+        assert!(db.select(4).is_err());
+
+        db.set(
+            "key".into(),
+            Bytes::from("value"),
+            Some(unix_millis() + 60_000),
+        )
+        .await;
+        assert_eq!(other.get(b"key").await.unwrap(), None);
+
+        assert!(matches!(
+            db.move_key(b"key", 0).await,
+            Err(DbError::SameObject)
+        ));
+        assert!(matches!(
+            db.move_key(b"key", 4).await,
+            Err(DbError::InvalidDbIndex)
+        ));
+
+        assert!(db.move_key(b"key", 2).await.unwrap());
+        assert!(!db.move_key(b"key", 2).await.unwrap());
+        assert_eq!(db.dbsize().await, 0);
+        assert_eq!(other.get(b"key").await.unwrap(), Some(Bytes::from("value")));
+        assert!(other.expires_at(b"key").await.unwrap().is_some());
+
+        // the target keeps its own value
+        db.set("key".into(), Bytes::from("other"), None).await;
+        assert!(!other.move_key(b"key", 0).await.unwrap());
+        assert_eq!(db.get(b"key").await.unwrap(), Some(Bytes::from("other")));
+
+        let keyspace = db.keyspace_info().await;
+        assert_eq!(keyspace.len(), 2);
+        assert_eq!(
+            (keyspace[0].db, keyspace[0].keys, keyspace[0].expires),
+            (0, 1, 0)
+        );
+        assert_eq!(
+            (keyspace[1].db, keyspace[1].keys, keyspace[1].expires),
+            (2, 1, 1)
+        );
+        assert!(keyspace[1].avg_ttl > 50_000);
+    }
+
+    #[tokio::test]
+    async fn test_swap_and_flush() {
+        let db = db();
+        let other = db.select(1).unwrap();
+        let dirty = Arc::new(AtomicBool::new(false));
+
+        db.set("a".into(), Bytes::from("0"), None).await;
+        other.set("b".into(), Bytes::from("1"), None).await;
+        other.watch(b"a", &dirty).await;
+
+        db.swap(1, 0).await.unwrap();
+        assert!(matches!(db.swap(0, 4).await, Err(DbError::InvalidDbIndex)));
+
+        assert!(dirty.load(Ordering::SeqCst));
+        assert_eq!(db.get(b"b").await.unwrap(), Some(Bytes::from("1")));
+        assert_eq!(other.get(b"a").await.unwrap(), Some(Bytes::from("0")));
+
+        let memory = db.used_memory();
+        other.flush(false, false).await;
+        assert_eq!(db.dbsize().await, 1);
+        assert!(db.used_memory() < memory);
+
+        db.flush(true, false).await;
+        assert_eq!(db.dbsize().await, 0);
+        assert_eq!(db.used_memory(), 0);
+    }
+}
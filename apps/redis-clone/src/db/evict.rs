@@ -0,0 +1,344 @@
+use std::ops::Bound;
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use radix_trie::TrieCommon;
+use rand::{thread_rng, Rng};
+
+use super::{Db, DbError, Entry, Shard, Shared, StreamEntry, Value};
+use crate::conf::{KeyspaceEvents, MaxmemoryPolicy};
+use crate::resp::RespValue;
+use crate::utils::unix_millis;
+
+/// Approximate memory used by a key besides its name and value, for the table
+/// slot, the entry and the allocations holding them
+pub(super) const ENTRY_OVERHEAD: usize = 64;
+/// Approximate memory used by a stream entry besides its id, field and value
+pub(super) const STREAM_ENTRY_OVERHEAD: usize = 48;
+
+/// LFU counter of new keys, so they aren't evicted before they had a chance to
+/// be accessed again
+const LFU_INIT_VAL: u8 = 5;
+/// The higher the factor, the more accesses it takes to increment the counter
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// The LFU counter is decremented once per this many milliseconds without access
+const LFU_DECAY_TIME: u64 = 60_000;
+
+/// Access statistics of a key, used by the LRU and LFU eviction policies
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Access {
+    /// Last access as UNIX time in milliseconds
+    clock: u64,
+    /// Logarithmic access frequency, decayed over time
+    counter: u8,
+}
+
+impl Access {
+    pub(super) fn new() -> Self {
+        Self {
+            clock: unix_millis(),
+            counter: LFU_INIT_VAL,
+        }
+    }
+
+    /// Records an access to the key.
+    pub(super) fn touch(&mut self) {
+        let now = unix_millis();
+
+        let counter = self.frequency(now);
+
+        // the more accesses the key had, the less likely another one counts
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        let increment = thread_rng().gen::<f64>() < 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+
+        self.counter = if increment {
+            counter.saturating_add(1)
+        } else {
+            counter
+        };
+        self.clock = now;
+    }
+
+    /// Milliseconds since the last access.
+    pub(super) fn idle(&self, now: u64) -> u64 {
+        now.saturating_sub(self.clock)
+    }
+
+    /// The LFU counter, decremented for the time since the last access.
+    pub(super) fn frequency(&self, now: u64) -> u8 {
+        let periods = self.idle(now) / LFU_DECAY_TIME;
+
+        self.counter
+            .saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+}
+
+impl Value {
+    /// Approximate memory used by the value.
+    pub(super) fn memory(&self) -> usize {
+        match self {
+            Value::String(data) => data.len(),
+            Value::Int(_) => 0,
+            Value::Stream(stream) => stream
+                .entries
+                .iter()
+                .map(|(id, entry)| stream_entry_memory(id, entry))
+                .sum(),
+            Value::Hash(hash) => hash.memory(),
+            Value::List(list) => list.memory(),
+            Value::Set(set) => set.memory(),
+            Value::SortedSet(zset) => zset.memory(),
+        }
+    }
+}
+
+pub(super) fn stream_entry_memory(id: &str, entry: &StreamEntry) -> usize {
+    let fields: usize = entry
+        .fields
+        .iter()
+        .map(|(field, value)| field.len() + value.len())
+        .sum();
+
+    id.len() + fields + STREAM_ENTRY_OVERHEAD
+}
+
+/// Approximate memory used by a key and its value.
+pub(super) fn entry_memory(key: &[u8], entry: &Entry) -> usize {
+    key.len() + entry.value.memory() + ENTRY_OVERHEAD
+}
+
+impl Shard {
+    pub(super) fn allocate_memory(&mut self, bytes: usize, shared: &Shared) {
+        self.memory += bytes;
+
+        let used = shared.used_memory.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        shared.peak_memory.fetch_max(used, Ordering::Relaxed);
+    }
+
+    pub(super) fn free_memory(&mut self, bytes: usize, shared: &Shared) {
+        self.memory -= bytes;
+        shared.used_memory.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Samples up to `samples` keys that `policy` may evict and returns the best
+    /// one to evict.
+    fn eviction_candidate(&self, policy: MaxmemoryPolicy, samples: usize) -> Option<Bytes> {
+        let (random, _) = self.keyspace.random()?;
+
+        let keys: Vec<&Bytes> = if policy.volatile() {
+            // the keys after a random key, since the volatile keys can't be
+            // picked at random
+            self.volatile
+                .range::<Bytes, _>((Bound::Included(random), Bound::Unbounded))
+                .chain(self.volatile.iter())
+                .take(samples)
+                .collect()
+        } else {
+            (0..samples)
+                .filter_map(|_| self.keyspace.random().map(|(key, _)| key))
+                .collect()
+        };
+
+        let now = unix_millis();
+
+        keys.into_iter()
+            .filter_map(|key| Some((key, self.keyspace.get(key)?)))
+            .filter(|(_, entry)| !policy.volatile() || entry.expires_at.is_some())
+            .max_by_key(|(_, entry)| match policy {
+                MaxmemoryPolicy::AllKeysLru | MaxmemoryPolicy::VolatileLru => {
+                    entry.access.idle(now)
+                }
+                MaxmemoryPolicy::AllKeysLfu | MaxmemoryPolicy::VolatileLfu => {
+                    (u8::MAX - entry.access.frequency(now)) as u64
+                }
+                MaxmemoryPolicy::VolatileTtl => u64::MAX - entry.expires_at.unwrap_or(u64::MAX),
+                _ => 0,
+            })
+            .map(|(key, _)| key.clone())
+    }
+
+    fn evict(&mut self, key: &[u8], shared: &Shared) {
+        self.remove(key, shared);
+        shared.evicted_keys.fetch_add(1, Ordering::Relaxed);
+        shared.notify_keyspace_event(KeyspaceEvents::EVICTED, "evicted", self.db, key);
+
+        shared.replication.propagate(
+            self.db,
+            RespValue::Array(vec![
+                RespValue::BulkString(Bytes::from_static(b"DEL")),
+                RespValue::BulkString(Bytes::copy_from_slice(key)),
+            ]),
+        );
+    }
+}
+
+impl Db {
+    /// Approximate memory used by the keys.
+    pub(crate) fn used_memory(&self) -> usize {
+        self.shared.used_memory.load(Ordering::Relaxed)
+    }
+
+    /// Evicts keys with the `maxmemory-policy` until the used memory is below
+    /// `maxmemory`, called before commands that may use more memory. Fails if
+    /// the policy doesn't allow evicting any of the keys.
+    pub(crate) async fn free_memory(&self) -> Result<(), DbError> {
+        let (maxmemory, policy, samples) = {
+            let config = self.shared.config.read().unwrap();
+
+            (
+                config.maxmemory(),
+                config.maxmemory_policy(),
+                config.maxmemory_samples(),
+            )
+        };
+
+        // replicas keep the keys of the master, which propagates its evictions
+        if maxmemory == 0 || !self.shared.replication.master {
+            return Ok(());
+        }
+
+        while self.used_memory() > maxmemory {
+            if policy == MaxmemoryPolicy::NoEviction || !self.evict(policy, samples).await {
+                return Err(DbError::OutOfMemory);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evicts a key from the first shard of any database with a key to evict,
+    /// starting at a random shard. In thread per core mode a core only evicts
+    /// the keys it owns. Returns `false` if there is no key to evict.
+    async fn evict(&self, policy: MaxmemoryPolicy, samples: usize) -> bool {
+        let shards: Vec<_> = self
+            .shared
+            .all_shards()
+            .filter(|shard| shard.is_held())
+            .collect();
+
+        if shards.is_empty() {
+            return false;
+        }
+
+        let start = thread_rng().gen_range(0..shards.len());
+
+        for shard in shards[start..].iter().chain(&shards[..start]) {
+            let mut shard = shard.lock().await;
+
+            if let Some(key) = shard.eviction_candidate(policy, samples) {
+                shard.evict(&key, &self.shared);
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::db_with;
+
+    fn new_shard(keys: &[(&str, u64, u8, Option<u64>)]) -> Shard {
+        let mut shard = Shard::default();
+
+        for (key, clock, counter, expires_at) in keys {
+            let mut entry = Entry::new(Value::String(Bytes::from_static(b"value")), *expires_at);
+            entry.access = Access {
+                clock: *clock,
+                counter: *counter,
+            };
+
+            if expires_at.is_some() {
+                shard
+                    .volatile
+                    .insert(Bytes::copy_from_slice(key.as_bytes()));
+            }
+
+            shard
+                .keyspace
+                .insert(Bytes::copy_from_slice(key.as_bytes()), entry);
+        }
+
+        shard
+    }
+
+    #[test]
+    fn test_eviction_candidate_by_policy() {
+        let now = unix_millis();
+
+        let shard = new_shard(&[
+            ("recent", now, 5, None),
+            ("idle", now - 10_000, 200, Some(now + 5_000)),
+            ("rare", now - 1_000, 1, Some(now + 1_000)),
+            ("expires_last", now, 100, Some(now + 60_000)),
+        ]);
+
+        let candidate = |policy| shard.eviction_candidate(policy, 64).unwrap();
+
+        assert_eq!(candidate(MaxmemoryPolicy::AllKeysLru), "idle");
+        assert_eq!(candidate(MaxmemoryPolicy::AllKeysLfu), "rare");
+        assert_eq!(candidate(MaxmemoryPolicy::VolatileTtl), "rare");
+        assert_ne!(candidate(MaxmemoryPolicy::VolatileRandom), "recent");
+
+        let persistent = new_shard(&[("recent", now, 5, None), ("idle", now - 10_000, 5, None)]);
+        assert_eq!(
+            persistent.eviction_candidate(MaxmemoryPolicy::VolatileLru, 64),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_accounting() {
+        let db = db_with(&[]);
+
+        db.set("key".into(), Bytes::from_static(b"value"), None)
+            .await;
+        let used = db.used_memory();
+        assert_eq!(used, "key".len() + "value".len() + ENTRY_OVERHEAD);
+
+        db.set("key".into(), Bytes::from_static(b"longer value"), None)
+            .await;
+        assert_eq!(db.used_memory(), used + "longer ".len());
+
+        db.xadd(
+            b"stream",
+            Some("1-1".into()),
+            vec![("field".into(), Bytes::new())],
+        )
+        .await
+        .unwrap();
+        db.rename(b"stream", b"renamed", false).await.unwrap();
+        db.del(&[Bytes::from("key")], false).await;
+        assert_eq!(
+            db.used_memory(),
+            "renamed".len() + ENTRY_OVERHEAD + "1-1field".len() + STREAM_ENTRY_OVERHEAD
+        );
+
+        db.flush(false, false).await;
+        assert_eq!(db.used_memory(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_free_memory() {
+        let db = db_with(&["--maxmemory", "2kb"]);
+
+        for i in 0..50 {
+            db.set(format!("key{i}").into(), Bytes::from_static(b"value"), None)
+                .await;
+        }
+
+        assert!(matches!(db.free_memory().await, Err(DbError::OutOfMemory)));
+
+        db.set_config("maxmemory-policy", "volatile-lru").unwrap();
+        assert!(matches!(db.free_memory().await, Err(DbError::OutOfMemory)));
+
+        db.set_config("maxmemory-policy", "allkeys-random").unwrap();
+        db.free_memory().await.unwrap();
+
+        assert!(db.used_memory() <= 2048);
+        assert_eq!(db.stats().evicted_keys as usize, 50 - db.dbsize().await);
+    }
+}
@@ -0,0 +1,1984 @@
+use radix_trie::{Trie, TrieCommon};
+use rand::{thread_rng, Rng};
+use tokio::sync::{broadcast, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::BuildHasher;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::conf::{Config, KeyspaceEvents, ReplicationRole};
+use crate::pubsub::PubSub;
+use crate::resp::RespValue;
+use crate::utils::glob::glob_match;
+use crate::utils::unix_millis;
+
+use self::collections::{Hash, List, Set, SortedSet};
+use self::evict::{entry_memory, stream_entry_memory, Access};
+pub(crate) use self::object::MemoryStats;
+pub(crate) use self::ownership::hold;
+use self::ownership::{Holders, ShardCell, ShardGuard};
+use self::table::Table;
+
+mod collections;
+mod databases;
+mod evict;
+mod object;
+mod ownership;
+mod restore;
+mod save;
+mod table;
+
+/// Number of independently locked partitions of the keyspace
+pub(crate) const SHARDS: usize = 64;
+
+/// Number of keys with an expiration checked per active expire loop
+const ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
+/// The active expire cycle keeps going while more than this percentage of
+/// the checked keys turned out to be expired
+const ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE: usize = 10;
+/// Percentage of every `1 / hz` period the active expire cycle may use
+const ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC: u64 = 25;
+
+/// Number of commands buffered for replicas before they start lagging
+const REPLICATION_BUFFER_SIZE: usize = 1024;
+
+/// Values that take more allocations than this to free are dropped in the
+/// background by `UNLINK` and `FLUSHALL ASYNC`
+const LAZYFREE_THRESHOLD: usize = 64;
+
+#[derive(Debug)]
+pub(crate) struct DbBuilder {
+    db: Db,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Db {
+    shared: Arc<Shared>,
+    /// Database selected with `SELECT`
+    index: usize,
+}
+
+#[derive(Debug)]
+struct Shared {
+    /// The keyspaces of the logical databases, each split by the hash of the
+    /// key, so that commands on keys in different shards don't wait for each other
+    databases: Box<[Box<[ShardCell]>]>,
+    /// Picks the shard of a key
+    hasher: RandomState,
+    /// Held shared by every command and exclusively by `EXEC`, so that the
+    /// commands of a transaction run without other clients in between. Unused
+    /// in thread per core mode, where `EXEC` holds the cores of its keys.
+    exec: RwLock<()>,
+    /// Holders of the shards of the cores, in thread per core mode only
+    holders: Option<Arc<Holders>>,
+    replication: Replication,
+    pubsub: Arc<PubSub>,
+    expired_keys: AtomicU64,
+    evicted_keys: AtomicU64,
+    /// Approximate memory used by the keys, see [`Db::free_memory`]
+    used_memory: AtomicUsize,
+    /// Highest `used_memory` since startup
+    peak_memory: AtomicUsize,
+    config: std::sync::RwLock<Config>,
+    /// Set while `SAVE` or `BGSAVE` write the RDB file
+    saving: AtomicBool,
+}
+
+/// Partition of the keyspace with the keys whose hash maps to it
+#[derive(Debug, Default)]
+struct Shard {
+    /// Database the shard belongs to
+    db: usize,
+    keyspace: Table<Entry>,
+    /// Keys that may have an expiration, scanned by the active expire cycle.
+    /// Keys that were deleted or persisted are removed lazily by the cycle.
+    volatile: BTreeSet<Bytes>,
+    /// Last key checked by the active expire cycle
+    expire_cursor: Option<Bytes>,
+    /// Dirty flags of the clients watching a key, set when the key is modified
+    watched: HashMap<Bytes, Vec<Arc<AtomicBool>>>,
+    /// Approximate memory used by the keys of the shard
+    memory: usize,
+}
+
+/// Locks of the shards holding the keys of a multi-key command
+struct ShardGuards<'a> {
+    shared: &'a Shared,
+    /// Sorted by shard index, which is also the order they were locked in
+    guards: Vec<(usize, ShardGuard<'a>)>,
+}
+
+impl ShardGuards<'_> {
+    /// Returns the shard of a key, which must be one of the locked keys.
+    fn get(&mut self, key: &[u8]) -> &mut Shard {
+        let index = self.shared.shard_index(key);
+
+        let position = self
+            .guards
+            .binary_search_by_key(&index, |(index, _)| *index)
+            .expect("shard of the key is locked");
+
+        &mut self.guards[position].1
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    pub(crate) expired_keys: u64,
+    pub(crate) evicted_keys: u64,
+}
+
+/// Stream of write commands sent to connected replicas
+#[derive(Debug)]
+struct Replication {
+    sender: broadcast::Sender<RespValue>,
+    offset: AtomicU64,
+    master: bool,
+    /// Database of the last propagated command, `None` until a replica needs
+    /// to be told with a `SELECT`
+    selected_db: std::sync::Mutex<Option<usize>>,
+}
+
+impl Replication {
+    /// Sends a write command on database `db` to the replicas and advances the
+    /// replication offset. Replicas never propagate, they only apply what the
+    /// master sends them.
+    fn propagate(&self, db: usize, command: RespValue) {
+        if !self.master {
+            return;
+        }
+
+        // held while sending, so that commands on different databases can't
+        // interleave with the `SELECT` before them
+        let mut selected_db = self.selected_db.lock().unwrap();
+
+        if *selected_db != Some(db) {
+            self.send(RespValue::Array(vec![
+                RespValue::BulkString(Bytes::from_static(b"SELECT")),
+                RespValue::BulkString(Bytes::from(db.to_string())),
+            ]));
+            *selected_db = Some(db);
+        }
+
+        self.send(command);
+    }
+
+    fn send(&self, command: RespValue) {
+        self.offset
+            .fetch_add(command.size() as u64, Ordering::SeqCst);
+
+        // there are no receivers until the first replica connects
+        let _ = self.sender.send(command);
+    }
+
+    /// Subscribes a new replica, which doesn't know the selected database yet.
+    fn subscribe(&self) -> broadcast::Receiver<RespValue> {
+        let mut selected_db = self.selected_db.lock().unwrap();
+        *selected_db = None;
+
+        self.sender.subscribe()
+    }
+}
+
+/// Entry in the key-value store
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Value,
+    /// Absolute expiration time as UNIX time in milliseconds
+    expires_at: Option<u64>,
+    access: Access,
+}
+
+impl Entry {
+    fn new(value: Value, expires_at: Option<u64>) -> Self {
+        Self {
+            value,
+            expires_at,
+            access: Access::new(),
+        }
+    }
+}
+
+/// Value stored at a key
+#[derive(Debug, Clone)]
+enum Value {
+    String(Bytes),
+    /// String holding a decimal integer, stored without an allocation
+    Int(i64),
+    Stream(Box<Stream>),
+    Hash(Box<Hash>),
+    List(Box<List>),
+    Set(Box<Set>),
+    SortedSet(Box<SortedSet>),
+}
+
+impl Value {
+    /// Stores a string as an integer if it is one and formats back to the same
+    /// bytes, so that `"007"` or `"+1"` are kept as they were set.
+    fn string(data: Bytes) -> Self {
+        match canonical_int(&data) {
+            Some(n) => Value::Int(n),
+            None => Value::String(data),
+        }
+    }
+
+    /// Name of the type as reported by `TYPE`
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) | Value::Int(_) => "string",
+            Value::Stream(_) => "stream",
+            Value::Hash(_) => "hash",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+        }
+    }
+
+    /// Approximate number of allocations freed when the value is dropped
+    fn free_effort(&self) -> usize {
+        match self {
+            Value::String(_) => 1,
+            Value::Int(_) => 0,
+            Value::Stream(stream) => stream.entries.len(),
+            Value::Hash(hash) => hash.len(),
+            Value::List(list) => list.len(),
+            Value::Set(set) => set.len(),
+            Value::SortedSet(zset) => zset.len(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum DbError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+    #[error("ERR Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+    #[error("ERR DB index is out of range")]
+    InvalidDbIndex,
+    #[error("ERR source and destination objects are the same")]
+    SameObject,
+    #[error("ERR Background save already in progress")]
+    SaveInProgress,
+    #[error("ERR failed to save the RDB file: {0}")]
+    Save(std::io::Error),
+}
+
+/// Conditions of the `EXPIRE` family of commands (`NX`, `XX`, `GT` and `LT` flags).
+/// A key without expiration is treated as having an infinite TTL.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ExpireCondition {
+    pub(crate) nx: bool,
+    pub(crate) xx: bool,
+    pub(crate) gt: bool,
+    pub(crate) lt: bool,
+}
+
+impl ExpireCondition {
+    fn allows(&self, current: Option<u64>, new: u64) -> bool {
+        match current {
+            None => !self.xx && !self.gt,
+            Some(current) => !self.nx && (!self.gt || new > current) && (!self.lt || new < current),
+        }
+    }
+}
+
+/// Parses `data` as an integer if formatting it back gives the same bytes.
+fn canonical_int(data: &[u8]) -> Option<i64> {
+    // longest i64 is 20 characters with the sign
+    if data.len() > 20 {
+        return None;
+    }
+
+    std::str::from_utf8(data)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|n| n.to_string().as_bytes() == data)
+}
+
+/// Period of the active expire cycle, run `hz` times per second
+pub(crate) fn active_expire_period(hz: u32) -> Duration {
+    Duration::from_micros(1_000_000 / hz.max(1) as u64)
+}
+
+fn is_expired(expires_at: Option<u64>, now: u64) -> bool {
+    matches!(expires_at, Some(expires_at) if now >= expires_at)
+}
+
+#[derive(Debug, Clone)]
+struct StreamID {
+    millis: u128,
+    seq: u64,
+}
+
+impl StreamID {
+    fn new(string: String, last_id: &Option<Self>) -> Result<Self, anyhow::Error> {
+        if string == "*" && last_id.is_some() {
+            let mut last_id = last_id.clone().unwrap();
+            last_id.seq += 1;
+            return Ok(last_id);
+        }
+
+        if string == "*" {
+            return Ok(Self {
+                millis: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis(),
+                seq: 0,
+            });
+        }
+
+        let parts = string.split("-").collect::<Vec<&str>>();
+
+        if parts.len() != 2 {
+            return Err(anyhow::anyhow!("Invalid Stream ID"));
+        }
+
+        let millis = parts[0].parse().unwrap();
+
+        let seq = match parts[1] {
+            "*" => {
+                if last_id.is_none() || millis != last_id.clone().unwrap().millis {
+                    if millis > 0 {
+                        0
+                    } else {
+                        1
+                    }
+                } else {
+                    last_id.clone().unwrap().seq + 1
+                }
+            }
+            _ => parts[1].parse().unwrap(),
+        };
+
+        if let Some(last_id) = last_id {
+            if millis < last_id.millis {
+                return Err(anyhow::anyhow!("Invalid Stream ID"));
+            }
+
+            if millis == last_id.millis && seq <= last_id.seq {
+                return Err(anyhow::anyhow!("Invalid Stream ID"));
+            }
+        }
+
+        Ok(Self { millis, seq })
+    }
+
+    fn xrange(string: String, seq: Option<u64>) -> Result<Self, anyhow::Error> {
+        // if seq is not specified, it is 0
+        if let (false, Some(seq)) = (string.contains('-'), seq) {
+            return Ok(Self {
+                millis: string.parse().unwrap(),
+                seq,
+            });
+        }
+
+        let parts = string.split("-").collect::<Vec<&str>>();
+
+        if parts.len() != 2 {
+            return Err(anyhow::anyhow!("Invalid Stream ID"));
+        }
+
+        let millis = parts[0].parse().unwrap();
+        let seq = parts[1].parse().unwrap();
+
+        Ok(Self { millis, seq })
+    }
+}
+
+impl From<StreamID> for String {
+    fn from(val: StreamID) -> Self {
+        format!("{}-{}", val.millis, val.seq)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Stream {
+    entries: Trie<String, StreamEntry>,
+    last_id: Option<StreamID>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamEntry {
+    fields: Vec<(Bytes, Bytes)>,
+}
+
+impl StreamEntry {
+    /// Field-value pairs of the entry, in the order they were added
+    pub fn fields(&self) -> &[(Bytes, Bytes)] {
+        &self.fields
+    }
+}
+
+impl Shared {
+    fn shard_index(&self, key: &[u8]) -> usize {
+        (self.hasher.hash_one(key) % SHARDS as u64) as usize
+    }
+
+    /// The shards of every database, one database after another.
+    fn all_shards(&self) -> impl Iterator<Item = &ShardCell> {
+        self.databases.iter().flat_map(|shards| shards.iter())
+    }
+
+    /// Publishes a keyspace notification about `key` of database `db` if events
+    /// of `class` are enabled by `notify-keyspace-events`.
+    fn notify_keyspace_event(&self, class: KeyspaceEvents, event: &str, db: usize, key: &[u8]) {
+        let events = self.config.read().unwrap().notify_keyspace_events();
+
+        if !events.enabled(class) {
+            return;
+        }
+
+        if events.contains(KeyspaceEvents::KEYSPACE) {
+            // channel names are text, unlike keys
+            let channel = format!("__keyspace@{}__:{}", db, String::from_utf8_lossy(key));
+            self.pubsub
+                .publish(&channel, &Bytes::copy_from_slice(event.as_bytes()));
+        }
+
+        if events.contains(KeyspaceEvents::KEYEVENT) {
+            let channel = format!("__keyevent@{}__:{}", db, event);
+            self.pubsub.publish(&channel, &Bytes::copy_from_slice(key));
+        }
+    }
+}
+
+impl Shard {
+    fn expiration(&self, key: &[u8]) -> Option<Option<u64>> {
+        self.keyspace.get(key).map(|entry| entry.expires_at)
+    }
+
+    /// Deletes the key if it is expired and returns whether it was. Replicas only
+    /// report the key as missing and wait for the `DEL` propagated by the master,
+    /// so that their dataset stays consistent with it.
+    fn expire_if_needed(&mut self, key: &[u8], shared: &Shared) -> bool {
+        let Some(expires_at) = self.expiration(key) else {
+            return false;
+        };
+
+        if !is_expired(expires_at, unix_millis()) {
+            return false;
+        }
+
+        if shared.replication.master {
+            self.remove(key, shared);
+            shared.expired_keys.fetch_add(1, Ordering::Relaxed);
+            shared.notify_keyspace_event(KeyspaceEvents::EXPIRED, "expired", self.db, key);
+
+            shared.replication.propagate(
+                self.db,
+                RespValue::Array(vec![
+                    RespValue::BulkString(Bytes::from_static(b"DEL")),
+                    RespValue::BulkString(Bytes::copy_from_slice(key)),
+                ]),
+            );
+        }
+
+        true
+    }
+
+    /// Returns the entry of a live key.
+    fn lookup(&mut self, key: &[u8], shared: &Shared) -> Option<&mut Entry> {
+        if self.expire_if_needed(key, shared) {
+            return None;
+        }
+
+        let entry = self.keyspace.get_mut(key)?;
+        entry.access.touch();
+
+        Some(entry)
+    }
+
+    fn remove(&mut self, key: &[u8], shared: &Shared) -> bool {
+        self.take(key, shared).is_some()
+    }
+
+    fn take(&mut self, key: &[u8], shared: &Shared) -> Option<Entry> {
+        self.volatile.remove(key);
+
+        let entry = self.keyspace.remove(key)?;
+        self.signal_modified_key(key);
+
+        self.free_memory(entry_memory(key, &entry), shared);
+
+        Some(entry)
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.keyspace.contains_key(key)
+    }
+
+    /// Inserts an entry, replacing the value of any type stored at `key`.
+    fn insert(&mut self, key: Bytes, entry: Entry, shared: &Shared) {
+        if entry.expires_at.is_some() {
+            self.volatile.insert(key.clone());
+        } else {
+            self.volatile.remove(&key);
+        }
+
+        self.signal_modified_key(&key);
+
+        match self.keyspace.get(&key) {
+            Some(replaced) => self.free_memory(entry_memory(&key, replaced), shared),
+            None => shared.notify_keyspace_event(KeyspaceEvents::NEW, "new", self.db, &key),
+        }
+
+        self.allocate_memory(entry_memory(&key, &entry), shared);
+
+        self.keyspace.insert(key, entry);
+    }
+
+    /// Marks the transactions of the clients watching `key` as dirty.
+    fn signal_modified_key(&self, key: &[u8]) {
+        if let Some(flags) = self.watched.get(key) {
+            for dirty in flags {
+                dirty.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Checks up to `count` keys with an expiration, continuing from where the
+    /// previous call stopped, and deletes the expired ones. Returns the number of
+    /// checked and expired keys.
+    fn expire_sample(&mut self, count: usize, shared: &Shared) -> (usize, usize) {
+        let start = match &self.expire_cursor {
+            Some(cursor) => Bound::Excluded(cursor.clone()),
+            None => Bound::Unbounded,
+        };
+
+        let keys: Vec<Bytes> = self
+            .volatile
+            .range((start, Bound::Unbounded))
+            .take(count)
+            .cloned()
+            .collect();
+
+        // start over from the beginning once the end of the keys is reached
+        self.expire_cursor = if keys.len() < count {
+            None
+        } else {
+            keys.last().cloned()
+        };
+
+        let mut expired = 0;
+
+        for key in keys.iter() {
+            match self.expiration(key) {
+                Some(Some(_)) => {
+                    if self.expire_if_needed(key, shared) {
+                        expired += 1;
+                    }
+                }
+                _ => {
+                    self.volatile.remove(key);
+                }
+            }
+        }
+
+        (keys.len(), expired)
+    }
+}
+
+impl DbBuilder {
+    pub(crate) fn new(config: Config) -> DbBuilder {
+        DbBuilder {
+            db: Db::new(config),
+        }
+    }
+
+    pub(crate) fn db(&self) -> Db {
+        self.db.clone()
+    }
+}
+
+impl Db {
+    pub(crate) fn new(config: Config) -> Db {
+        let (sender, _) = broadcast::channel(REPLICATION_BUFFER_SIZE);
+
+        let replication = Replication {
+            sender,
+            offset: AtomicU64::new(0),
+            master: matches!(config.replication().role, ReplicationRole::Master),
+            selected_db: std::sync::Mutex::new(None),
+        };
+
+        // shard `i` of every database belongs to core `i % cores`
+        let holders = config
+            .cores()
+            .map(|cores| Arc::new(Holders::new(cores.min(SHARDS))));
+
+        let shared = Arc::new(Shared {
+            databases: (0..config.databases())
+                .map(|db| {
+                    (0..SHARDS)
+                        .map(|index| {
+                            let shard = Shard {
+                                db,
+                                ..Default::default()
+                            };
+
+                            match &holders {
+                                Some(holders) => {
+                                    ShardCell::owned(shard, index % holders.len(), holders)
+                                }
+                                None => ShardCell::Locked(Mutex::new(shard)),
+                            }
+                        })
+                        .collect()
+                })
+                .collect(),
+            hasher: RandomState::new(),
+            exec: RwLock::new(()),
+            holders,
+            replication,
+            pubsub: Arc::new(PubSub::new()),
+            expired_keys: AtomicU64::new(0),
+            evicted_keys: AtomicU64::new(0),
+            used_memory: AtomicUsize::new(0),
+            peak_memory: AtomicUsize::new(0),
+            config: std::sync::RwLock::new(config),
+            saving: AtomicBool::new(false),
+        });
+
+        Db { shared, index: 0 }
+    }
+
+    /// Returns a handle to database `index`, as selected by `SELECT`.
+    pub(crate) fn select(&self, index: usize) -> Result<Db, DbError> {
+        if index >= self.shared.databases.len() {
+            return Err(DbError::InvalidDbIndex);
+        }
+
+        Ok(Db {
+            shared: Arc::clone(&self.shared),
+            index,
+        })
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The shards of the selected database.
+    fn shards(&self) -> &[ShardCell] {
+        &self.shared.databases[self.index]
+    }
+
+    fn notify_keyspace_event(&self, class: KeyspaceEvents, event: &str, key: &[u8]) {
+        self.shared
+            .notify_keyspace_event(class, event, self.index, key);
+    }
+
+    /// Returns the number of cores owning the keyspace in thread per core mode.
+    pub(crate) fn cores(&self) -> Option<usize> {
+        self.shared.holders.as_ref().map(|holders| holders.len())
+    }
+
+    /// Returns the core owning `key` in thread per core mode, `0` otherwise.
+    pub(crate) fn core_of(&self, key: &[u8]) -> usize {
+        self.shared.shard_index(key) % self.cores().unwrap_or(1)
+    }
+
+    /// Returns a holder id for a task borrowing the shards of cores.
+    pub(crate) fn new_holder(&self) -> usize {
+        self.holders().new_holder()
+    }
+
+    /// Gives the shards of `core` to `holder`, called by the thread of the core.
+    pub(crate) fn hand_over(&self, core: usize, holder: usize) {
+        self.holders().hand_over(core, holder);
+    }
+
+    fn holders(&self) -> &Holders {
+        self.shared
+            .holders
+            .as_ref()
+            .expect("shards are owned by cores in thread per core mode only")
+    }
+
+    /// Locks the shard holding `key`.
+    async fn shard(&self, key: &[u8]) -> ShardGuard<'_> {
+        self.shards()[self.shared.shard_index(key)].lock().await
+    }
+
+    /// Locks the shards holding `keys`. Shards are always locked in the order of
+    /// their index, so that commands locking several shards can't deadlock.
+    async fn lock_keys<'a>(&'a self, keys: impl IntoIterator<Item = &'a [u8]>) -> ShardGuards<'a> {
+        let mut indexes: Vec<usize> = keys
+            .into_iter()
+            .map(|key| self.shared.shard_index(key))
+            .collect();
+
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        let mut guards = Vec::with_capacity(indexes.len());
+
+        for index in indexes {
+            guards.push((index, self.shards()[index].lock().await));
+        }
+
+        ShardGuards {
+            shared: &self.shared,
+            guards,
+        }
+    }
+
+    pub(crate) async fn get(&self, key: &[u8]) -> Result<Option<Bytes>, DbError> {
+        let mut shard = self.shard(key).await;
+
+        match shard.lookup(key, &self.shared) {
+            None => {
+                self.notify_keyspace_event(KeyspaceEvents::KEY_MISS, "keymiss", key);
+                Ok(None)
+            }
+            Some(Entry {
+                value: Value::String(data),
+                ..
+            }) => Ok(Some(data.clone())),
+            Some(Entry {
+                value: Value::Int(n),
+                ..
+            }) => Ok(Some(Bytes::from(n.to_string()))),
+            Some(_) => Err(DbError::WrongType),
+        }
+    }
+
+    /// Sets a string value, replacing the value of any type stored at `key`.
+    /// `expires_at` is an absolute UNIX time in milliseconds.
+    pub(crate) async fn set(&self, key: Bytes, value: Bytes, expires_at: Option<u64>) {
+        let mut shard = self.shard(&key).await;
+
+        shard.expire_if_needed(&key, &self.shared);
+
+        shard.insert(
+            key.clone(),
+            Entry::new(Value::string(value), expires_at),
+            &self.shared,
+        );
+
+        self.notify_keyspace_event(KeyspaceEvents::STRING, "set", &key);
+    }
+
+    pub(crate) async fn xadd(
+        &self,
+        stream_key: &[u8],
+        id: Option<String>,
+        fields: Vec<(Bytes, Bytes)>,
+    ) -> Result<String, DbError> {
+        let mut shard = self.shard(stream_key).await;
+
+        if shard.lookup(stream_key, &self.shared).is_none() {
+            let empty = Stream {
+                entries: Trie::new(),
+                last_id: None,
+            };
+
+            shard.insert(
+                Bytes::copy_from_slice(stream_key),
+                Entry::new(Value::Stream(Box::new(empty)), None),
+                &self.shared,
+            );
+        }
+
+        let Some(Entry {
+            value: Value::Stream(stream),
+            ..
+        }) = shard.keyspace.get_mut(stream_key)
+        else {
+            return Err(DbError::WrongType);
+        };
+
+        if id.is_none() {
+            unimplemented!();
+        }
+        let id: String = id.unwrap();
+
+        let stream_id =
+            StreamID::new(id.clone(), &stream.last_id).map_err(|_| DbError::StreamIdTooSmall)?;
+
+        stream.last_id = Some(stream_id.clone());
+
+        let stream_id: String = stream_id.into();
+
+        let entry = StreamEntry { fields };
+        let memory = stream_entry_memory(&stream_id, &entry);
+
+        stream.entries.insert(stream_id.clone(), entry);
+
+        shard.allocate_memory(memory, &self.shared);
+
+        shard.signal_modified_key(stream_key);
+        self.notify_keyspace_event(KeyspaceEvents::STREAM, "xadd", stream_key);
+
+        Ok(stream_id)
+    }
+
+    /// Returns the entries of a stream between `start` and `end`, a missing stream
+    /// is empty.
+    pub(crate) async fn xrange(
+        &self,
+        stream: &[u8],
+        start: &str,
+        end: &str,
+        count: Option<usize>,
+    ) -> Result<Vec<(String, StreamEntry)>, DbError> {
+        let mut shard = self.shard(stream).await;
+
+        let mut entries = vec![];
+
+        let stream = match shard.lookup(stream, &self.shared) {
+            None => return Ok(entries),
+            Some(Entry {
+                value: Value::Stream(stream),
+                ..
+            }) => stream,
+            Some(_) => return Err(DbError::WrongType),
+        };
+
+        // find common prefix of start and end, if start or/and end are not specified
+        // then common prefix is empty string and subtrie is the whole stream
+        let common_prefix = start
+            .chars()
+            .zip(end.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect::<String>();
+
+        // take subtrie of common prefix and iterate over it
+        let subtrie = stream.entries.get_raw_descendant(&common_prefix);
+
+        if subtrie.is_none() {
+            return Ok(entries);
+        }
+
+        let subtrie = subtrie.unwrap();
+
+        // if start seq is not specified, it is 0 by default
+        // but it can be not present in the stream, so we need to find
+        // the first entry or check all entries in the subtrie
+        let start_id = match start {
+            "-" => StreamID { millis: 0, seq: 0 },
+            _ => StreamID::xrange(String::from(start), Some(0))
+                .map_err(|_| DbError::InvalidStreamId)?,
+        };
+
+        let end_id = match end {
+            "+" => StreamID {
+                millis: u128::MAX,
+                seq: u64::MAX,
+            },
+            _ => StreamID::xrange(String::from(end), Some(u64::MAX))
+                .map_err(|_| DbError::InvalidStreamId)?,
+        };
+
+        let mut count = count.unwrap_or(usize::MAX);
+
+        for (id, entry) in subtrie.iter().take(count) {
+            if count == 0 {
+                break;
+            }
+
+            let id = StreamID::xrange(id.clone(), None).unwrap();
+
+            if id.millis < start_id.millis {
+                continue;
+            }
+
+            if id.millis == start_id.millis && id.seq < start_id.seq {
+                continue;
+            }
+
+            if id.millis > end_id.millis {
+                continue;
+            }
+
+            if id.millis == end_id.millis && id.seq > end_id.seq {
+                continue;
+            }
+
+            count -= 1;
+
+            entries.push((id.into(), entry.clone()));
+        }
+
+        Ok(entries)
+    }
+
+    pub(crate) async fn xread(
+        &self,
+        stream_key: &[u8],
+        id: &str,
+    ) -> Result<Option<StreamEntry>, DbError> {
+        let entries = self.xrange(stream_key, id, "+", Some(1)).await?;
+
+        Ok(entries.into_iter().next().map(|(_, entry)| entry))
+    }
+
+    /// Returns the live keys of any type matching the glob-style `pattern`.
+    pub(crate) async fn keys(&self, pattern: &[u8]) -> Vec<Bytes> {
+        let mut keys = vec![];
+
+        // one shard at a time, other clients can use the rest in the meantime
+        for shard in self.shards().iter() {
+            let shard = shard.lock().await;
+
+            let now = unix_millis();
+
+            // Filter out expired keys and clone the keys
+            keys.extend(
+                shard
+                    .keyspace
+                    .iter()
+                    .filter(|(_, entry)| !is_expired(entry.expires_at, now))
+                    .filter(|(key, _)| pattern == b"*" || glob_match(pattern, key, false))
+                    .map(|(key, _)| key.clone()),
+            );
+        }
+
+        keys
+    }
+
+    pub(crate) async fn value_type(&self, key: &[u8]) -> &'static str {
+        let mut shard = self.shard(key).await;
+
+        match shard.lookup(key, &self.shared) {
+            Some(entry) => entry.value.type_name(),
+            None => "none",
+        }
+    }
+
+    /// Sets the expiration of a key of any type if `condition` allows it. An expiration
+    /// time in the past deletes the key. Returns `false` if the key does not exist or
+    /// the condition was not met.
+    pub(crate) async fn expire(&self, key: &[u8], at: i64, condition: ExpireCondition) -> bool {
+        let mut shard = self.shard(key).await;
+
+        let at = at.max(0) as u64;
+
+        match shard.lookup(key, &self.shared) {
+            None => return false,
+            Some(entry) if !condition.allows(entry.expires_at, at) => return false,
+            Some(entry) if at > unix_millis() => {
+                entry.expires_at = Some(at);
+                shard.volatile.insert(Bytes::copy_from_slice(key));
+                shard.signal_modified_key(key);
+                self.notify_keyspace_event(KeyspaceEvents::GENERIC, "expire", key);
+                return true;
+            }
+            Some(_) => {}
+        }
+
+        shard.remove(key, &self.shared);
+        self.notify_keyspace_event(KeyspaceEvents::GENERIC, "del", key);
+
+        true
+    }
+
+    /// Returns `None` if the key does not exist, otherwise its expiration time as
+    /// UNIX time in milliseconds, if any.
+    pub(crate) async fn expires_at(&self, key: &[u8]) -> Option<Option<u64>> {
+        let mut shard = self.shard(key).await;
+
+        shard
+            .lookup(key, &self.shared)
+            .map(|entry| entry.expires_at)
+    }
+
+    /// Removes the expiration of a key. Returns `false` if the key does not exist or
+    /// has no expiration.
+    pub(crate) async fn persist(&self, key: &[u8]) -> bool {
+        let mut shard = self.shard(key).await;
+
+        let persisted = match shard.lookup(key, &self.shared) {
+            Some(entry) => entry.expires_at.take().is_some(),
+            None => false,
+        };
+
+        if persisted {
+            shard.signal_modified_key(key);
+            self.notify_keyspace_event(KeyspaceEvents::GENERIC, "persist", key);
+        }
+
+        persisted
+    }
+
+    /// Deletes the keys and returns how many of them existed. With `lazy` set,
+    /// large values are freed in the background instead of on the request path.
+    pub(crate) async fn del(&self, keys: &[Bytes], lazy: bool) -> usize {
+        let mut shards = self.lock_keys(keys.iter().map(|key| &key[..])).await;
+
+        let mut removed = vec![];
+
+        let mut count = 0;
+
+        for key in keys {
+            let shard = shards.get(key);
+
+            // replicas keep expired keys until the master propagates their
+            // deletion, which is this `DEL`, so they are deleted but not counted
+            let expired = shard.expire_if_needed(key, &self.shared);
+
+            if let Some(entry) = shard.take(key, &self.shared) {
+                self.notify_keyspace_event(KeyspaceEvents::GENERIC, "del", key);
+                removed.push(entry);
+                count += !expired as usize;
+            }
+        }
+
+        drop(shards);
+
+        let effort: usize = removed.iter().map(|entry| entry.value.free_effort()).sum();
+
+        if lazy && effort > LAZYFREE_THRESHOLD {
+            tokio::task::spawn_blocking(move || drop(removed));
+        }
+
+        count
+    }
+
+    /// Counts the existing keys, a key mentioned multiple times is counted
+    /// multiple times.
+    pub(crate) async fn exists(&self, keys: &[Bytes]) -> usize {
+        let mut shards = self.lock_keys(keys.iter().map(|key| &key[..])).await;
+
+        keys.iter()
+            .filter(|key| {
+                let shard = shards.get(key);
+                !shard.expire_if_needed(key, &self.shared) && shard.contains(key)
+            })
+            .count()
+    }
+
+    /// Counts the existing keys like [`exists`](Self::exists), recording an
+    /// access to each of them.
+    pub(crate) async fn touch(&self, keys: &[Bytes]) -> usize {
+        let mut shards = self.lock_keys(keys.iter().map(|key| &key[..])).await;
+
+        keys.iter()
+            .filter(|key| shards.get(key).lookup(key, &self.shared).is_some())
+            .count()
+    }
+
+    /// Renames a key of any type keeping its expiration. With `nx` set the key is
+    /// only renamed if `to` does not exist. Returns whether the key was renamed.
+    pub(crate) async fn rename(&self, from: &[u8], to: &[u8], nx: bool) -> Result<bool, DbError> {
+        let mut shards = self.lock_keys([from, to]).await;
+
+        if shards.get(from).lookup(from, &self.shared).is_none() {
+            return Err(DbError::NoSuchKey);
+        }
+
+        shards.get(to).expire_if_needed(to, &self.shared);
+
+        if from == to {
+            return Ok(!nx);
+        }
+
+        if nx && shards.get(to).contains(to) {
+            return Ok(false);
+        }
+
+        let entry = shards
+            .get(from)
+            .take(from, &self.shared)
+            .expect("key exists");
+        self.notify_keyspace_event(KeyspaceEvents::GENERIC, "rename_from", from);
+
+        shards
+            .get(to)
+            .insert(Bytes::copy_from_slice(to), entry, &self.shared);
+        self.notify_keyspace_event(KeyspaceEvents::GENERIC, "rename_to", to);
+
+        Ok(true)
+    }
+
+    /// Copies a key of any type together with its expiration. Returns `false` if
+    /// `from` does not exist or `to` exists and `replace` is not set.
+    pub(crate) async fn copy(&self, from: &[u8], to: &[u8], replace: bool) -> bool {
+        let mut shards = self.lock_keys([from, to]).await;
+
+        let Some(entry) = shards.get(from).lookup(from, &self.shared).cloned() else {
+            return false;
+        };
+
+        let shard = shards.get(to);
+
+        shard.expire_if_needed(to, &self.shared);
+
+        if !replace && shard.contains(to) {
+            return false;
+        }
+
+        shard.insert(Bytes::copy_from_slice(to), entry, &self.shared);
+        self.notify_keyspace_event(KeyspaceEvents::GENERIC, "copy_to", to);
+
+        true
+    }
+
+    /// Incrementally iterates the keyspace, see [`Table::scan`]. Visits buckets
+    /// until at least `count` keys were collected, then filters them by the
+    /// glob-style `pattern` and type. Returns the next cursor, `0` once done.
+    ///
+    /// The shards are scanned one after another, the low bits of the cursor hold
+    /// the shard and the rest the cursor of its table.
+    pub(crate) async fn scan(
+        &self,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+        value_type: Option<&str>,
+    ) -> (u64, Vec<Bytes>) {
+        let mut index = (cursor % SHARDS as u64) as usize;
+        let mut cursor = cursor / SHARDS as u64;
+
+        let mut keys = vec![];
+        let mut visited = 0;
+
+        // don't block other clients for too long on sparse tables
+        let mut max_iterations = count.max(1).saturating_mul(10);
+
+        while index < SHARDS {
+            let mut shard = self.shards()[index].lock().await;
+
+            let mut found = vec![];
+
+            loop {
+                cursor = shard.keyspace.scan(cursor, |key, entry| {
+                    found.push((key.clone(), entry.value.type_name()))
+                });
+
+                max_iterations -= 1;
+
+                if cursor == 0 || max_iterations == 0 || visited + found.len() >= count {
+                    break;
+                }
+            }
+
+            visited += found.len();
+
+            keys.extend(
+                found
+                    .into_iter()
+                    .filter(|(key, _)| match pattern {
+                        Some(pattern) => glob_match(pattern, key, false),
+                        None => true,
+                    })
+                    .filter(|(_, key_type)| {
+                        value_type.is_none_or(|value_type| value_type == *key_type)
+                    })
+                    .filter(|(key, _)| !shard.expire_if_needed(key, &self.shared))
+                    .map(|(key, _)| key),
+            );
+
+            if cursor == 0 {
+                index += 1;
+            }
+
+            if max_iterations == 0 || visited >= count {
+                break;
+            }
+        }
+
+        if index == SHARDS {
+            return (0, keys);
+        }
+
+        (cursor * SHARDS as u64 + index as u64, keys)
+    }
+
+    /// Incrementally iterates the elements of the hash, set or sorted set at
+    /// `key`, followed by their value for hashes and their score for sorted
+    /// sets. Compact collections are returned whole, like Redis does. Larger
+    /// ones are visited bucket by bucket from `cursor`, like the keyspace.
+    pub(crate) async fn scan_collection(
+        &self,
+        key: &[u8],
+        value_type: &str,
+        cursor: u64,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<(u64, Vec<Bytes>), DbError> {
+        let mut shard = self.shard(key).await;
+
+        let Some(entry) = shard.lookup(key, &self.shared) else {
+            return Ok((0, vec![]));
+        };
+
+        if entry.value.type_name() != value_type {
+            return Err(DbError::WrongType);
+        }
+
+        let (cursor, elements) = match &entry.value {
+            Value::Hash(hash) => hash.scan(cursor, count),
+            Value::Set(set) => set.scan(cursor, count),
+            Value::SortedSet(zset) => zset.scan(cursor, count),
+            _ => return Err(DbError::WrongType),
+        };
+
+        let elements = elements
+            .into_iter()
+            .filter(|(element, _)| match pattern {
+                Some(pattern) => glob_match(pattern, element, false),
+                None => true,
+            })
+            .flat_map(|(element, value)| std::iter::once(element).chain(value))
+            .collect();
+
+        Ok((cursor, elements))
+    }
+
+    /// Returns a random live key.
+    pub(crate) async fn random_key(&self) -> Option<Bytes> {
+        // replicas don't delete expired keys, so give up after a few expired
+        // picks instead of looping over a keyspace full of them
+        'pick: for _ in 0..100 {
+            // keys are spread evenly by the hash, so starting from a random
+            // shard is close enough to picking from the whole keyspace
+            let start = thread_rng().gen_range(0..SHARDS);
+
+            for index in (start..SHARDS).chain(0..start) {
+                let mut shard = self.shards()[index].lock().await;
+
+                let Some(key) = shard.keyspace.random().map(|(key, _)| key.clone()) else {
+                    continue;
+                };
+
+                if shard.expire_if_needed(&key, &self.shared) {
+                    continue 'pick;
+                }
+
+                return Some(key);
+            }
+
+            return None;
+        }
+
+        None
+    }
+
+    /// Returns the number of keys, including expired keys that were not deleted yet.
+    pub(crate) async fn dbsize(&self) -> usize {
+        let mut size = 0;
+
+        for shard in self.shards().iter() {
+            size += shard.lock().await.keyspace.len();
+        }
+
+        size
+    }
+
+    /// Deletes all the keys of the selected database, or of every database with
+    /// `all` set. With `lazy` set the values are freed in the background.
+    /// Returns the number of deleted keys, expired ones included.
+    pub(crate) async fn flush(&self, all: bool, lazy: bool) -> usize {
+        let shards: Vec<_> = match all {
+            true => self.shared.all_shards().collect(),
+            false => self.shards().iter().collect(),
+        };
+
+        let mut keyspaces = Vec::with_capacity(shards.len());
+
+        // every shard is locked, in order, so no client sees a partial flush
+        let mut guards = Vec::with_capacity(shards.len());
+
+        for shard in shards {
+            guards.push(shard.lock().await);
+        }
+
+        for shard in guards.iter_mut() {
+            for key in shard.watched.keys() {
+                if shard.keyspace.contains_key(key) {
+                    shard.signal_modified_key(key);
+                }
+            }
+
+            keyspaces.push(std::mem::take(&mut shard.keyspace));
+
+            shard.volatile.clear();
+            shard.expire_cursor = None;
+
+            let memory = shard.memory;
+            shard.free_memory(memory, &self.shared);
+        }
+
+        drop(guards);
+
+        let deleted = keyspaces.iter().map(Table::len).sum();
+
+        if lazy {
+            tokio::task::spawn_blocking(move || drop(keyspaces));
+        }
+
+        deleted
+    }
+
+    /// Runs the active expire cycle `hz` times per second.
+    pub(crate) async fn active_expire(&self, hz: u32) {
+        let mut interval = tokio::time::interval(active_expire_period(hz));
+
+        // a cycle that runs out of time continues from this shard in the next one
+        let mut index = 0;
+
+        loop {
+            interval.tick().await;
+            self.active_expire_cycle(hz, &mut index).await;
+        }
+    }
+
+    /// Checks keys with an expiration of the shards the task may use in small
+    /// batches and deletes the expired ones, until few enough expired keys are
+    /// found or the time budget of the cycle is used up. Starts from the shard
+    /// at `index`, which is left at the shard to continue from.
+    pub(crate) async fn active_expire_cycle(&self, hz: u32, index: &mut usize) {
+        let time_limit = active_expire_period(hz) * ACTIVE_EXPIRE_CYCLE_SLOW_TIME_PERC as u32 / 100;
+
+        // in thread per core mode a core only expires the keys it owns
+        let shards: Vec<_> = self
+            .shared
+            .all_shards()
+            .filter(|shard| shard.is_held())
+            .collect();
+
+        let start = tokio::time::Instant::now();
+
+        for _ in 0..shards.len() {
+            *index %= shards.len();
+
+            if self.expire_shard(shards[*index], start, time_limit).await {
+                break;
+            }
+
+            *index += 1;
+        }
+    }
+
+    /// Expires keys of one shard until few enough expired keys are found.
+    /// Returns `true` if the time limit of the cycle was reached.
+    async fn expire_shard(
+        &self,
+        shard: &ShardCell,
+        start: tokio::time::Instant,
+        time_limit: Duration,
+    ) -> bool {
+        loop {
+            // the lock is released between batches to let clients through,
+            // and no keys expire while a transaction is executed
+            let _guard = self.lock_command().await;
+            let mut shard = shard.lock().await;
+
+            let (checked, expired) =
+                shard.expire_sample(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP, &self.shared);
+
+            if start.elapsed() >= time_limit {
+                return true;
+            }
+
+            if checked == 0 || expired * 100 <= checked * ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE {
+                return false;
+            }
+        }
+    }
+
+    /// Locks out transactions while a single command is executed. Cores run
+    /// their commands one after another, so they don't need to.
+    pub(crate) async fn lock_command(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        match self.shared.holders {
+            Some(_) => None,
+            None => Some(self.shared.exec.read().await),
+        }
+    }
+
+    /// Waits for running commands to finish and locks out all other clients
+    /// while a transaction is executed. In thread per core mode the transaction
+    /// holds the cores of its keys instead.
+    pub(crate) async fn lock_exec(&self) -> Option<RwLockWriteGuard<'_, ()>> {
+        match self.shared.holders {
+            Some(_) => None,
+            None => Some(self.shared.exec.write().await),
+        }
+    }
+
+    /// Registers the dirty flag of a client to be set once `key` is modified.
+    pub(crate) async fn watch(&self, key: &[u8], dirty: &Arc<AtomicBool>) {
+        let mut shard = self.shard(key).await;
+
+        // a key that is already expired must not dirty the transaction later
+        shard.expire_if_needed(key, &self.shared);
+
+        shard
+            .watched
+            .entry(Bytes::copy_from_slice(key))
+            .or_default()
+            .push(Arc::clone(dirty));
+    }
+
+    pub(crate) async fn unwatch(&self, keys: &[Bytes], dirty: &Arc<AtomicBool>) {
+        let mut shards = self.lock_keys(keys.iter().map(|key| &key[..])).await;
+
+        for key in keys {
+            let shard = shards.get(key);
+
+            let Some(flags) = shard.watched.get_mut(key) else {
+                continue;
+            };
+
+            flags.retain(|flag| !Arc::ptr_eq(flag, dirty));
+
+            if flags.is_empty() {
+                shard.watched.remove(key);
+            }
+        }
+    }
+
+    /// Deletes the watched keys that expired since they were watched, which
+    /// marks the transactions watching them as dirty.
+    pub(crate) async fn expire_watched(&self, keys: &[Bytes]) {
+        let mut shards = self.lock_keys(keys.iter().map(|key| &key[..])).await;
+
+        for key in keys {
+            shards.get(key).expire_if_needed(key, &self.shared);
+        }
+    }
+
+    pub(crate) fn pubsub(&self) -> &PubSub {
+        &self.shared.pubsub
+    }
+
+    /// Propagates a write command to the replicas.
+    pub(crate) fn propagate(&self, command: RespValue) {
+        self.shared.replication.propagate(self.index, command);
+    }
+
+    /// Sends a command to the replicas without advancing the replication offset.
+    pub(crate) fn send_to_replicas(&self, command: RespValue) {
+        let _ = self.shared.replication.sender.send(command);
+    }
+
+    pub(crate) fn subscribe_replication(&self) -> broadcast::Receiver<RespValue> {
+        self.shared.replication.subscribe()
+    }
+
+    pub(crate) fn master_offset(&self) -> u64 {
+        self.shared.replication.offset.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        Stats {
+            expired_keys: self.shared.expired_keys.load(Ordering::Relaxed),
+            evicted_keys: self.shared.evicted_keys.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Changes a configuration parameter at runtime.
+    pub(crate) fn set_config(&self, param: &str, value: &str) -> Result<(), anyhow::Error> {
+        self.shared.config.write().unwrap().set(param, value)
+    }
+
+    pub(crate) fn config(&self) -> Config {
+        self.shared.config.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::RdbValue;
+    use crate::test_utils::{db, db_with};
+
+    fn condition(flags: &str) -> ExpireCondition {
+        ExpireCondition {
+            nx: flags.contains("nx"),
+            xx: flags.contains("xx"),
+            gt: flags.contains("gt"),
+            lt: flags.contains("lt"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_integer_strings() {
+        let db = db();
+
+        for value in [
+            "12",
+            "-9223372036854775808",
+            "007",
+            "+1",
+            "-0",
+            "1 ",
+            "99999999999999999999",
+        ] {
+            db.set("key".into(), Bytes::from(value), None).await;
+            assert_eq!(db.get(b"key").await.unwrap(), Some(Bytes::from(value)));
+        }
+
+        let shard = db.shard(b"key").await;
+        assert!(matches!(
+            shard.keyspace.get(b"key").unwrap().value,
+            Value::String(_)
+        ));
+        drop(shard);
+
+        db.set("key".into(), Bytes::from("-12"), None).await;
+        let shard = db.shard(b"key").await;
+        assert!(matches!(
+            shard.keyspace.get(b"key").unwrap().value,
+            Value::Int(-12)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expire_conditions() {
+        let db = db();
+        let in_a_minute = (unix_millis() + 60_000) as i64;
+
+        db.set("key".into(), Bytes::from("value"), None).await;
+
+        assert!(!db.expire(b"key", in_a_minute, condition("xx")).await);
+        assert!(!db.expire(b"key", in_a_minute, condition("gt")).await);
+        assert!(db.expire(b"key", in_a_minute, condition("nx")).await);
+        assert!(!db.expire(b"key", in_a_minute, condition("nx")).await);
+        assert!(!db.expire(b"key", in_a_minute - 1, condition("gt")).await);
+        assert!(db.expire(b"key", in_a_minute + 1, condition("xx gt")).await);
+        assert!(db.expire(b"key", in_a_minute, condition("lt")).await);
+
+        assert_eq!(db.expires_at(b"key").await, Some(Some(in_a_minute as u64)));
+        assert!(!db.expire(b"missing", in_a_minute, condition("")).await);
+    }
+
+    #[tokio::test]
+    async fn test_expire_in_the_past_deletes_key() {
+        let db = db();
+
+        db.set("key".into(), Bytes::from("value"), None).await;
+
+        assert!(db.expire(b"key", -1, condition("")).await);
+        assert_eq!(db.get(b"key").await.unwrap(), None);
+        assert_eq!(db.expires_at(b"key").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_expire_stream() {
+        let db = db();
+
+        db.xadd(
+            b"stream",
+            Some("1-1".into()),
+            vec![("key".into(), Bytes::from("value"))],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(db.expires_at(b"stream").await, Some(None));
+        assert!(db.expire(b"stream", 1, condition("")).await);
+        assert_eq!(db.value_type(b"stream").await, "none");
+    }
+
+    #[tokio::test]
+    async fn test_active_expire_deletes_and_propagates() {
+        let db = db();
+        let mut replication = db.subscribe_replication();
+
+        for i in 0..30 {
+            db.set(
+                format!("key{i}").into(),
+                Bytes::from("value"),
+                Some(unix_millis() + 1),
+            )
+            .await;
+        }
+        db.set("persistent".into(), Bytes::from("value"), None)
+            .await;
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        let mut sampled = 0;
+
+        for shard in db.shards().iter() {
+            let mut shard = shard.lock().await;
+            let volatile = shard.volatile.len();
+
+            assert_eq!(shard.expire_sample(20, &db.shared), (volatile, volatile));
+            assert_eq!(shard.expire_sample(20, &db.shared), (0, 0));
+
+            sampled += volatile;
+        }
+
+        assert_eq!(sampled, 30);
+        assert_eq!(db.dbsize().await, 1);
+        assert_eq!(db.stats().expired_keys, 30);
+
+        // replicas are told the database before the first write
+        let select = replication.recv().await.unwrap();
+        assert!(
+            matches!(select, RespValue::Array(args) if args[0] == RespValue::BulkString(Bytes::from_static(b"SELECT")))
+        );
+
+        let del = replication.recv().await.unwrap();
+        assert!(
+            matches!(del, RespValue::Array(args) if args[0] == RespValue::BulkString(Bytes::from_static(b"DEL")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_and_copy_keep_type_and_expiration() {
+        let db = db();
+        let in_a_minute = unix_millis() + 60_000;
+
+        db.set("string".into(), Bytes::from("value"), Some(in_a_minute))
+            .await;
+        db.xadd(
+            b"stream",
+            Some("1-1".into()),
+            vec![("key".into(), Bytes::from("value"))],
+        )
+        .await
+        .unwrap();
+
+        assert!(db.rename(b"string", b"renamed", false).await.unwrap());
+        assert_eq!(db.expires_at(b"renamed").await, Some(Some(in_a_minute)));
+        assert!(db.rename(b"string", b"renamed", false).await.is_err());
+
+        assert!(!db.rename(b"renamed", b"stream", true).await.unwrap());
+        assert!(db.rename(b"stream", b"renamed", false).await.unwrap());
+        assert_eq!(db.value_type(b"renamed").await, "stream");
+        assert_eq!(db.expires_at(b"renamed").await, Some(None));
+
+        db.set("string".into(), Bytes::from("value"), None).await;
+
+        assert!(!db.copy(b"string", b"renamed", false).await);
+        assert!(db.copy(b"string", b"renamed", true).await);
+        assert_eq!(
+            db.get(b"renamed").await.unwrap(),
+            Some(Bytes::from("value"))
+        );
+        assert_eq!(db.dbsize().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_del_exists_and_flush() {
+        let db = db();
+
+        db.set("a".into(), Bytes::from("value"), None).await;
+        db.xadd(
+            b"b",
+            Some("1-1".into()),
+            vec![("key".into(), Bytes::from("value"))],
+        )
+        .await
+        .unwrap();
+
+        let keys = [
+            Bytes::from("a"),
+            Bytes::from("b"),
+            Bytes::from("a"),
+            Bytes::from("c"),
+        ];
+
+        assert_eq!(db.exists(&keys).await, 3);
+        assert_eq!(db.del(&keys, true).await, 2);
+        assert_eq!(db.exists(&keys).await, 0);
+
+        db.set("a".into(), Bytes::from("value"), None).await;
+        db.flush(false, false).await;
+
+        assert_eq!(db.dbsize().await, 0);
+        assert_eq!(db.random_key().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_touch_records_access() {
+        let db = db();
+
+        db.set("a".into(), Bytes::from("value"), None).await;
+        let freq = db.object(b"a").await.unwrap().freq;
+
+        let keys = [Bytes::from("a"), Bytes::from("b")];
+
+        assert_eq!(db.exists(&keys).await, 1);
+        assert_eq!(db.object(b"a").await.unwrap().freq, freq);
+
+        // the first accesses of a new key always count
+        assert_eq!(db.touch(&keys).await, 1);
+        assert_eq!(db.object(b"a").await.unwrap().freq, freq + 1);
+    }
+
+    #[tokio::test]
+    async fn test_replica_deletes_expired_key() {
+        let db = db_with(&["--replicaof", "127.0.0.1 6379"]);
+
+        db.set("a".into(), Bytes::from("value"), Some(unix_millis() + 20))
+            .await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        // the replica hides the key but keeps it until the master deletes it
+        assert_eq!(db.get(b"a").await.unwrap(), None);
+        assert_eq!(db.dbsize().await, 1);
+
+        assert_eq!(db.del(&[Bytes::from("a")], false).await, 0);
+        assert_eq!(db.dbsize().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_type_and_set_overwrites_stream() {
+        let db = db();
+
+        db.set("string".into(), Bytes::from("value"), None).await;
+        db.xadd(
+            b"stream",
+            Some("1-1".into()),
+            vec![("key".into(), Bytes::from("value"))],
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(db.get(b"stream").await, Err(DbError::WrongType)));
+        assert!(matches!(
+            db.xadd(
+                b"string",
+                Some("1-1".into()),
+                vec![("key".into(), Bytes::new())]
+            )
+            .await,
+            Err(DbError::WrongType)
+        ));
+        assert!(matches!(
+            db.xrange(b"string", "-", "+", None).await,
+            Err(DbError::WrongType)
+        ));
+
+        db.set("stream".into(), Bytes::from("value"), None).await;
+
+        assert_eq!(db.value_type(b"stream").await, "string");
+        assert_eq!(db.get(b"stream").await.unwrap(), Some(Bytes::from("value")));
+        assert_eq!(db.dbsize().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_keyspace_notifications() {
+        use crate::pubsub::{Kind, Subscriptions};
+
+        let db = db();
+        let mut subscriptions = Subscriptions::new(db.pubsub());
+
+        subscriptions.subscribe(db.pubsub(), &["__key*__:*".to_string()], Kind::Pattern);
+
+        // notifications are disabled by default
+        db.set("key".into(), Bytes::from("value"), None).await;
+
+        db.set_config("notify-keyspace-events", "Eg$x").unwrap();
+
+        db.set("key".into(), Bytes::from("value"), None).await;
+        db.expire(b"key", 1, condition("")).await;
+        db.set("key".into(), Bytes::from("value"), Some(unix_millis() - 1))
+            .await;
+        db.get(b"key").await.unwrap();
+
+        let mut events = vec![];
+
+        for _ in 0..4 {
+            match subscriptions.next_message().await {
+                Some(RespValue::Push(frame)) => events.push((frame[2].clone(), frame[3].clone())),
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+
+        let event = |event: &str| {
+            (
+                RespValue::BulkString(format!("__keyevent@0__:{}", event).into()),
+                RespValue::BulkString(Bytes::from_static(b"key")),
+            )
+        };
+
+        assert_eq!(
+            events,
+            vec![event("set"), event("del"), event("set"), event("expired")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binary_keys_and_values() {
+        let db = db();
+        let key = Bytes::from_static(b"\xff\x00key\r\n");
+        let value = Bytes::from_static(b"\x80\x81");
+
+        db.set(key.clone(), value.clone(), None).await;
+        assert_eq!(db.get(&key).await.unwrap(), Some(value.clone()));
+        assert_eq!(db.keys(b"\xff*").await, vec![key.clone()]);
+        assert_eq!(
+            db.scan(0, 1000, Some(b"*key*"), None).await.1,
+            vec![key.clone()]
+        );
+
+        db.xadd(
+            b"\xfe",
+            Some("1-1".into()),
+            vec![(value.clone(), key.clone())],
+        )
+        .await
+        .unwrap();
+        let entries = db.xrange(b"\xfe", "-", "+", None).await.unwrap();
+        assert_eq!(entries[0].1.fields(), [(value, key)]);
+    }
+
+    #[tokio::test]
+    async fn test_scan_visits_every_shard() {
+        let db = db();
+
+        for i in 0..500 {
+            db.set(format!("key{i}").into(), Bytes::from("value"), None)
+                .await;
+        }
+
+        let mut keys = vec![];
+        let mut cursor = 0;
+
+        loop {
+            let (next, found) = db.scan(cursor, 7, None, None).await;
+            keys.extend(found);
+            cursor = next;
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        keys.sort();
+        keys.dedup();
+
+        assert_eq!(keys.len(), 500);
+        assert_eq!(db.keys(b"key1*").await.len(), 111);
+
+        // SCAN 0 COUNT 18446744073709551615
+        assert_eq!(db.scan(0, usize::MAX, None, None).await.1.len(), 500);
+    }
+
+    #[tokio::test]
+    async fn test_scan_collections() {
+        let db = db();
+        db.load_fixture(include_str!("../../fixtures/keyspace.jsonl"))
+            .await;
+
+        let (cursor, elements) = db
+            .scan_collection(b"board", "zset", 0, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(cursor, 0);
+        assert_eq!(elements, ["alice", "1.5", "bob", "inf"]);
+
+        let (_, elements) = db
+            .scan_collection(b"user:1", "hash", 0, 10, Some(b"n*"))
+            .await
+            .unwrap();
+        assert_eq!(elements, ["name", "Ada"]);
+
+        assert!(matches!(
+            db.scan_collection(b"tags", "hash", 0, 10, None).await,
+            Err(DbError::WrongType)
+        ));
+        assert_eq!(
+            db.scan_collection(b"missing", "set", 0, 10, None)
+                .await
+                .unwrap(),
+            (0, vec![])
+        );
+
+        // a set too large to be compact is returned in pages
+        let mut members: Vec<_> = (0..1000).map(|i| Bytes::from(format!("m{i}"))).collect();
+        db.restore("big".into(), RdbValue::Set(members.clone()), None)
+            .await;
+
+        let mut scanned = vec![];
+        let mut cursor = 0;
+        let mut pages = 0;
+
+        loop {
+            let (next, elements) = db
+                .scan_collection(b"big", "set", cursor, 100, None)
+                .await
+                .unwrap();
+            scanned.extend(elements);
+            pages += 1;
+
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        // elements may be returned twice if the table is resized in between
+        scanned.sort();
+        scanned.dedup();
+        members.sort();
+
+        assert!(pages > 1);
+        assert_eq!(scanned, members);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_multi_key_commands_dont_deadlock() {
+        let db = db();
+
+        let keys: Vec<Bytes> = (0..8).map(|i| Bytes::from(format!("key{i}"))).collect();
+
+        for key in keys.iter() {
+            db.set(key.clone(), Bytes::from("value"), None).await;
+        }
+
+        // every task locks the shards of the keys in a different order
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let db = db.clone();
+                let mut keys = keys.clone();
+                keys.rotate_left(i);
+
+                tokio::spawn(async move {
+                    for _ in 0..200 {
+                        db.copy(&keys[0], &keys[1], true).await;
+                        db.exists(&keys).await;
+                        db.rename(&keys[2], &keys[3], false).await.ok();
+                        db.rename(&keys[3], &keys[2], false).await.ok();
+                    }
+                })
+            })
+            .collect();
+
+        let all = async {
+            for task in tasks {
+                task.await.unwrap();
+            }
+        };
+
+        tokio::time::timeout(Duration::from_secs(10), all)
+            .await
+            .expect("multi-key commands deadlocked");
+    }
+
+    #[tokio::test]
+    async fn test_persist() {
+        let db = db();
+
+        db.set(
+            "key".into(),
+            Bytes::from("value"),
+            Some(unix_millis() + 60_000),
+        )
+        .await;
+
+        assert!(db.persist(b"key").await);
+        assert!(!db.persist(b"key").await);
+        assert_eq!(db.expires_at(b"key").await, Some(None));
+    }
+}
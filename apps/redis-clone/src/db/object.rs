@@ -0,0 +1,160 @@
+use std::sync::atomic::Ordering;
+
+use radix_trie::TrieCommon;
+
+use super::evict::{entry_memory, stream_entry_memory, ENTRY_OVERHEAD};
+use super::{Db, Value};
+use crate::utils::unix_millis;
+
+/// Strings up to this length are allocated together with their object
+const EMBSTR_SIZE_LIMIT: usize = 44;
+
+/// Internals of a key reported by `OBJECT`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ObjectInfo {
+    pub(crate) encoding: &'static str,
+    /// Seconds since the last access
+    pub(crate) idle: u64,
+    /// Logarithmic access frequency
+    pub(crate) freq: u8,
+}
+
+/// Memory usage of the server reported by `MEMORY STATS`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MemoryStats {
+    pub(crate) peak_allocated: usize,
+    pub(crate) total_allocated: usize,
+    pub(crate) keys_count: usize,
+    pub(crate) expires_count: usize,
+    /// Memory used by the keyspace besides the keys and values
+    pub(crate) overhead: usize,
+    /// Memory used by the keys and values
+    pub(crate) dataset: usize,
+}
+
+impl Value {
+    /// Internal representation of the value as reported by `OBJECT ENCODING`
+    fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(data) if data.len() <= EMBSTR_SIZE_LIMIT => "embstr",
+            Value::String(_) => "raw",
+            Value::Int(_) => "int",
+            Value::Stream(_) => "stream",
+            Value::Hash(hash) => hash.encoding(),
+            Value::List(list) => list.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::SortedSet(zset) => zset.encoding(),
+        }
+    }
+}
+
+impl Db {
+    /// Returns the internals of a key without counting it as an access.
+    pub(crate) async fn object(&self, key: &[u8]) -> Option<ObjectInfo> {
+        let mut shard = self.shard(key).await;
+
+        if shard.expire_if_needed(key, &self.shared) {
+            return None;
+        }
+
+        let entry = shard.keyspace.get(key)?;
+        let now = unix_millis();
+
+        Some(ObjectInfo {
+            encoding: entry.value.encoding(),
+            idle: entry.access.idle(now) / 1000,
+            freq: entry.access.frequency(now),
+        })
+    }
+
+    /// Estimates the memory used by a key and its value. Only `samples` entries
+    /// of a stream are measured, or all of them if `samples` is 0.
+    pub(crate) async fn memory_usage(&self, key: &[u8], samples: usize) -> Option<usize> {
+        let mut shard = self.shard(key).await;
+
+        if shard.expire_if_needed(key, &self.shared) {
+            return None;
+        }
+
+        let entry = shard.keyspace.get(key)?;
+
+        let usage = match &entry.value {
+            Value::Stream(stream) if samples > 0 && stream.entries.len() > samples => {
+                let sampled: usize = stream
+                    .entries
+                    .iter()
+                    .take(samples)
+                    .map(|(id, entry)| stream_entry_memory(id, entry))
+                    .sum();
+
+                key.len() + ENTRY_OVERHEAD + sampled * stream.entries.len() / samples
+            }
+            _ => entry_memory(key, entry),
+        };
+
+        Some(usage)
+    }
+
+    pub(crate) async fn memory_stats(&self) -> MemoryStats {
+        let mut keys_count = 0;
+        let mut expires_count = 0;
+
+        for shard in self.shared.all_shards() {
+            let shard = shard.lock().await;
+
+            keys_count += shard.keyspace.len();
+            expires_count += shard.volatile.len();
+        }
+
+        let total_allocated = self.used_memory();
+        let overhead = keys_count * ENTRY_OVERHEAD;
+
+        MemoryStats {
+            peak_allocated: self.shared.peak_memory.load(Ordering::Relaxed),
+            total_allocated,
+            keys_count,
+            expires_count,
+            overhead,
+            dataset: total_allocated.saturating_sub(overhead),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::test_utils::db;
+
+    #[tokio::test]
+    async fn test_object_and_memory_usage() {
+        let db = db();
+
+        for (key, value, encoding) in [
+            ("int", "-12345", "int"),
+            ("padded", "012", "embstr"),
+            ("embstr", "hello", "embstr"),
+            ("raw", &"x".repeat(45), "raw"),
+        ] {
+            db.set(key.into(), Bytes::copy_from_slice(value.as_bytes()), None)
+                .await;
+            assert_eq!(db.object(key.as_bytes()).await.unwrap().encoding, encoding);
+        }
+
+        assert!(db.object(b"missing").await.is_none());
+        assert!(db.memory_usage(b"missing", 0).await.is_none());
+
+        for i in 1..=9 {
+            let data = Bytes::from("x".repeat(i));
+            db.xadd(b"stream", Some(format!("{i}-1")), vec![("f".into(), data)])
+                .await
+                .unwrap();
+        }
+
+        let exact = db.memory_usage(b"stream", 0).await.unwrap();
+        assert_eq!(exact, db.memory_usage(b"stream", 9).await.unwrap());
+
+        // the first entries are smaller than the average
+        assert!(db.memory_usage(b"stream", 2).await.unwrap() < exact);
+    }
+}
@@ -0,0 +1,174 @@
+//! Access to the shards of the keyspace. By default every shard is behind a
+//! lock taken by the commands using it. In thread per core mode the shards are
+//! not locked: they belong to a core and are only used by the task holding that
+//! core, the core's own thread or a task the core lent its shards to.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::Shard;
+
+tokio::task_local! {
+    /// Holder the current task acts as in thread per core mode
+    static HOLDER: usize;
+}
+
+/// Runs `f` as `holder`, which may use the shards of the cores it holds.
+pub(crate) async fn hold<F: Future>(holder: usize, f: F) -> F::Output {
+    HOLDER.scope(holder, f).await
+}
+
+/// Holder of the shards of every core in thread per core mode. Core `i` is
+/// holder `i`, tasks borrowing the shards of cores get higher ids.
+#[derive(Debug)]
+pub(super) struct Holders {
+    holders: Box<[AtomicUsize]>,
+    next: AtomicUsize,
+}
+
+impl Holders {
+    pub(super) fn new(cores: usize) -> Self {
+        Self {
+            holders: (0..cores).map(AtomicUsize::new).collect(),
+            next: AtomicUsize::new(cores),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.holders.len()
+    }
+
+    /// Returns a holder id that no core or task uses.
+    pub(super) fn new_holder(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Gives the shards of `core` to `holder`. Only the thread of the core hands
+    /// its shards over, and it tells the holder through a channel, which orders
+    /// the uses of the shards by the previous holder before the next one's.
+    pub(super) fn hand_over(&self, core: usize, holder: usize) {
+        self.holders[core].store(holder, Ordering::Release);
+    }
+
+    /// Returns whether the current task holds the shards of `core`.
+    fn holds(&self, core: usize) -> bool {
+        HOLDER
+            .try_with(|holder| *holder == self.holders[core].load(Ordering::Acquire))
+            .unwrap_or(false)
+    }
+}
+
+/// Shard of a core in thread per core mode
+pub(super) struct OwnedShard {
+    holders: Arc<Holders>,
+    core: usize,
+    shard: UnsafeCell<Shard>,
+    /// Set while the holder uses the shard, so that it can't use it twice
+    borrowed: Cell<bool>,
+}
+
+// SAFETY: the shard and its borrow flag are only used by the task holding the
+// core, which is checked before every use, see `Holders::hand_over`.
+unsafe impl Sync for OwnedShard {}
+
+impl fmt::Debug for OwnedShard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedShard")
+            .field("core", &self.core)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Shard along with what keeps two tasks from using it at once
+#[derive(Debug)]
+pub(super) enum ShardCell {
+    Locked(Mutex<Shard>),
+    Owned(OwnedShard),
+}
+
+impl ShardCell {
+    pub(super) fn owned(shard: Shard, core: usize, holders: &Arc<Holders>) -> Self {
+        ShardCell::Owned(OwnedShard {
+            holders: Arc::clone(holders),
+            core,
+            shard: UnsafeCell::new(shard),
+            borrowed: Cell::new(false),
+        })
+    }
+
+    /// Locks the shard, or checks that the current task holds its core.
+    ///
+    /// # Panics
+    ///
+    /// In thread per core mode, if the current task doesn't hold the core of the
+    /// shard or already uses it.
+    pub(super) async fn lock(&self) -> ShardGuard<'_> {
+        match self {
+            ShardCell::Locked(shard) => ShardGuard::Locked(shard.lock().await),
+            ShardCell::Owned(shard) => {
+                assert!(
+                    shard.holders.holds(shard.core),
+                    "shard of core {} used by a task not holding it",
+                    shard.core
+                );
+                assert!(
+                    !shard.borrowed.replace(true),
+                    "shard of core {} is already in use",
+                    shard.core
+                );
+
+                ShardGuard::Owned(shard)
+            }
+        }
+    }
+
+    /// Returns whether the current task may use the shard: always by locking it,
+    /// only when holding its core in thread per core mode.
+    pub(super) fn is_held(&self) -> bool {
+        match self {
+            ShardCell::Locked(_) => true,
+            ShardCell::Owned(shard) => shard.holders.holds(shard.core),
+        }
+    }
+}
+
+pub(super) enum ShardGuard<'a> {
+    Locked(MutexGuard<'a, Shard>),
+    Owned(&'a OwnedShard),
+}
+
+impl Deref for ShardGuard<'_> {
+    type Target = Shard;
+
+    fn deref(&self) -> &Shard {
+        match self {
+            ShardGuard::Locked(shard) => shard,
+            // SAFETY: the borrow flag makes this guard the only user of the shard
+            ShardGuard::Owned(shard) => unsafe { &*shard.shard.get() },
+        }
+    }
+}
+
+impl DerefMut for ShardGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Shard {
+        match self {
+            ShardGuard::Locked(shard) => shard,
+            // SAFETY: the borrow flag makes this guard the only user of the shard
+            ShardGuard::Owned(shard) => unsafe { &mut *shard.shard.get() },
+        }
+    }
+}
+
+impl Drop for ShardGuard<'_> {
+    fn drop(&mut self) {
+        if let ShardGuard::Owned(shard) = self {
+            shard.borrowed.set(false);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use bytes::Bytes;
+use radix_trie::Trie;
+
+use super::collections::{Hash, List, Set, SortedSet};
+use super::{Db, Entry, Stream, StreamEntry, StreamID, Value};
+use crate::rdb::{RDBParser, RDBParsingError, RdbValue};
+use crate::utils::unix_millis;
+
+impl Value {
+    /// Converts a value loaded from an RDB file, picking the encoding the
+    /// configured limits allow.
+    fn restore(value: RdbValue, db: &Db) -> Self {
+        let limits = db.config().encoding_limits();
+
+        match value {
+            RdbValue::String(data) => Value::string(data),
+            RdbValue::List(elements) => Value::List(Box::new(List::new(elements, &limits))),
+            RdbValue::Set(members) => Value::Set(Box::new(Set::new(members, &limits))),
+            RdbValue::SortedSet(members) => {
+                Value::SortedSet(Box::new(SortedSet::new(members, &limits)))
+            }
+            RdbValue::Hash(pairs) => Value::Hash(Box::new(Hash::new(pairs, &limits))),
+            RdbValue::Stream { entries, last_id } => {
+                let mut stream = Stream {
+                    entries: Trie::new(),
+                    last_id: Some(StreamID {
+                        millis: last_id.0 as u128,
+                        seq: last_id.1,
+                    }),
+                };
+
+                for ((millis, seq), fields) in entries {
+                    stream
+                        .entries
+                        .insert(format!("{}-{}", millis, seq), StreamEntry { fields });
+                }
+
+                Value::Stream(Box::new(stream))
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Stores a value loaded from an RDB file, replacing the value of any type
+    /// stored at `key`.
+    pub(crate) async fn restore(&self, key: Bytes, value: RdbValue, expires_at: Option<u64>) {
+        let value = Value::restore(value, self);
+
+        let mut shard = self.shard(&key).await;
+
+        shard.expire_if_needed(&key, &self.shared);
+        shard.insert(key, Entry::new(value, expires_at), &self.shared);
+    }
+
+    /// Loads every key of the file read by `parser` that isn't expired into the
+    /// databases. The whole file is read and its checksum verified first, so
+    /// that nothing is loaded from an invalid file.
+    pub(crate) async fn load(&self, mut parser: RDBParser) -> Result<(), RDBParsingError> {
+        parser.read_header().await?;
+
+        let mut entries = vec![];
+
+        while let Some(entry) = parser.next_entry().await? {
+            if matches!(entry.expires_at, Some(expires_at) if expires_at <= unix_millis()) {
+                continue;
+            }
+
+            let db = self.select(entry.db).map_err(|_| {
+                RDBParsingError::InvalidRDBFile(format!("db index {} out of range", entry.db))
+            })?;
+
+            entries.push((db, entry));
+        }
+
+        for (db, entry) in entries {
+            db.restore(entry.key, entry.value, entry.expires_at).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Db {
+    /// Loads keys written as JSON Lines, in the format of [`crate::rdb::json`].
+    pub(crate) async fn load_fixture(&self, jsonl: &str) {
+        for line in jsonl.lines() {
+            let entry = crate::rdb::json::parse(line).unwrap();
+
+            self.select(entry.db)
+                .unwrap()
+                .restore(entry.key, entry.value, entry.expires_at)
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::test_utils::db;
+
+    #[tokio::test]
+    async fn test_load_selects_databases() {
+        let db = db();
+
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0xFE, 0x00, 0xFB, 0x01, 0x00]);
+        rdb.extend([0x00, 0x01, b'a', 0x01, b'0']);
+        rdb.extend([0xFE, 0x03, 0xFB, 0x01, 0x00]);
+        rdb.extend([0x00, 0x01, b'b', 0x01, b'3']);
+        rdb.extend([0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        db.load(RDBParser::new(Cursor::new(rdb))).await.unwrap();
+
+        let other = db.select(3).unwrap();
+        assert_eq!(db.get(b"a").await.unwrap(), Some(Bytes::from("0")));
+        assert_eq!(db.get(b"b").await.unwrap(), None);
+        assert_eq!(other.get(b"b").await.unwrap(), Some(Bytes::from("3")));
+
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0xFE, 0x10]);
+        assert!(db.load(RDBParser::new(Cursor::new(rdb))).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_loads_nothing() {
+        let db = db();
+
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0x00, 0x01, b'a', 0x01, b'0']);
+        rdb.extend([0xFF, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert!(matches!(
+            db.load(RDBParser::new(Cursor::new(rdb))).await,
+            Err(RDBParsingError::InvalidChecksum { .. })
+        ));
+        assert_eq!(db.dbsize().await, 0);
+
+        // a truncated file
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0x00, 0x01, b'a', 0x01, b'0', 0x00, 0x01]);
+
+        assert!(db.load(RDBParser::new(Cursor::new(rdb))).await.is_err());
+        assert_eq!(db.dbsize().await, 0);
+    }
+
+    /// Appends a string with its length, shorter than 16 kb.
+    fn string(rdb: &mut Vec<u8>, data: &[u8]) {
+        rdb.extend([0x40 | (data.len() >> 8) as u8, data.len() as u8]);
+        rdb.extend(data);
+    }
+
+    /// Encodes short strings as a listpack.
+    fn listpack(elements: &[&[u8]]) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        buf.extend((elements.len() as u16).to_le_bytes());
+
+        for element in elements {
+            buf.push(0x80 | element.len() as u8);
+            buf.extend(*element);
+            buf.push(1 + element.len() as u8);
+        }
+
+        buf.push(0xFF);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_load_value_types() {
+        let db = db();
+
+        let mut rdb = b"REDIS0011".to_vec();
+
+        // function library and module auxiliary data are skipped
+        rdb.push(0xF5);
+        string(&mut rdb, b"#!lua name=lib");
+        rdb.extend([0xF7, 0x81, 1, 2, 3, 4, 5, 6, 7, 8, 0x02, 0x00]);
+        rdb.extend([0x02, 0x05, 0x05]);
+        string(&mut rdb, b"aux");
+        rdb.push(0x00);
+        rdb.extend([0xFE, 0x00, 0xF4, 0x01, 0x05, 0x00]);
+
+        // LZF compressed string with an idle time
+        rdb.extend([0xF8, 0x10, 0x00]);
+        string(&mut rdb, b"lzf");
+        rdb.extend([
+            0xC3, 0x08, 0x16, 0x00, b'a', 0xE0, 0x0A, 0x00, 0x01, b'b', b'c',
+        ]);
+
+        // quicklist of a listpack and a plain node
+        rdb.push(18);
+        string(&mut rdb, b"list");
+        rdb.extend([0x02, 0x02]);
+        string(&mut rdb, &listpack(&[b"a", b"b"]));
+        rdb.push(0x01);
+        string(&mut rdb, b"c");
+
+        rdb.push(11);
+        string(&mut rdb, b"intset");
+        string(&mut rdb, &[2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 2, 0]);
+
+        rdb.extend([0xFC]);
+        rdb.extend((unix_millis() + 60_000).to_le_bytes());
+        rdb.push(5);
+        string(&mut rdb, b"zset");
+        rdb.push(0x02);
+        string(&mut rdb, b"one");
+        rdb.extend(1.0f64.to_le_bytes());
+        string(&mut rdb, b"inf");
+        rdb.extend(f64::INFINITY.to_le_bytes());
+
+        rdb.push(3);
+        string(&mut rdb, b"old zset");
+        rdb.push(0x02);
+        string(&mut rdb, b"a");
+        rdb.extend([0x03, b'1', b'.', b'5']);
+        string(&mut rdb, b"b");
+        rdb.push(255);
+
+        rdb.push(16);
+        string(&mut rdb, b"hash");
+        string(&mut rdb, &listpack(&[b"f", b"v"]));
+
+        // stream of a deleted entry, one with the master fields and another
+        // with its own, and a consumer group
+        let node = listpack(&[
+            b"2", b"1", b"1", b"f", b"0", // master entry
+            b"2", b"0", b"0", b"v1", b"4", // 5-0
+            b"3", b"0", b"1", b"v2", b"4", // 5-1, deleted
+            b"0", b"1", b"0", b"2", b"a", b"1", b"b", b"2", b"7", // 6-0
+        ]);
+        rdb.push(21);
+        string(&mut rdb, b"stream");
+        rdb.push(0x01);
+        string(&mut rdb, &[0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0]);
+        string(&mut rdb, &node);
+        rdb.extend([0x02, 0x06, 0x00, 0x05, 0x00, 0x05, 0x01, 0x03]);
+        rdb.push(0x01);
+        string(&mut rdb, b"group");
+        rdb.extend([0x06, 0x00, 0x02, 0x01]);
+        rdb.extend([0; 24]);
+        rdb.extend([0x01, 0x01]);
+        string(&mut rdb, b"consumer");
+        rdb.extend([0; 16]);
+        rdb.push(0x01);
+        rdb.extend([0; 16]);
+
+        // module value
+        rdb.push(7);
+        string(&mut rdb, b"module");
+        rdb.extend([0x05, 0x04]);
+        rdb.extend(0.5f64.to_le_bytes());
+        rdb.push(0x00);
+
+        rdb.extend([0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+        db.load(RDBParser::new(Cursor::new(rdb))).await.unwrap();
+
+        let lzf = db.get(b"lzf").await.unwrap().unwrap();
+        assert_eq!(lzf, Bytes::from(format!("{}bc", "a".repeat(20))));
+
+        let encodings = [
+            ("list", "list", "listpack"),
+            ("intset", "set", "intset"),
+            ("zset", "zset", "listpack"),
+            ("old zset", "zset", "listpack"),
+            ("hash", "hash", "listpack"),
+            ("stream", "stream", "stream"),
+        ];
+        for (key, value_type, encoding) in encodings {
+            assert_eq!(db.value_type(key.as_bytes()).await, value_type, "{}", key);
+            assert_eq!(
+                db.object(key.as_bytes()).await.unwrap().encoding,
+                encoding,
+                "{}",
+                key
+            );
+        }
+
+        assert!(db.expires_at(b"zset").await.unwrap().is_some());
+        assert_eq!(db.value_type(b"module").await, "none");
+
+        let entries = db.xrange(b"stream", "-", "+", None).await.unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(id, entry)| (id.as_str(), entry.fields().len()))
+            .collect();
+        assert_eq!(entries, [("5-0", 1), ("6-0", 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_load_fixture() {
+        let db = db();
+        db.load_fixture(include_str!("../../fixtures/keyspace.jsonl"))
+            .await;
+
+        let binary = b"\xff\x00";
+        assert_eq!(
+            db.get(binary).await.unwrap(),
+            Some(Bytes::from_static(b"\x80\x81"))
+        );
+        assert!(db.expires_at(binary).await.unwrap().is_some());
+
+        let encodings = [
+            ("counter", "int"),
+            ("queue", "listpack"),
+            ("tags", "intset"),
+            ("board", "listpack"),
+            ("user:1", "listpack"),
+            ("events", "stream"),
+        ];
+        for (key, encoding) in encodings {
+            let object = db.object(key.as_bytes()).await.unwrap();
+            assert_eq!(object.encoding, encoding, "{}", key);
+        }
+
+        let entries = db.xrange(b"events", "-", "+", None).await.unwrap();
+        assert_eq!(entries[1].1.fields().len(), 2);
+
+        let other = db.select(1).unwrap();
+        assert_eq!(other.get(b"other").await.unwrap(), Some(Bytes::from("x")));
+        assert_eq!(db.get(b"other").await.unwrap(), None);
+    }
+}
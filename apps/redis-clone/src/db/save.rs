@@ -0,0 +1,190 @@
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+use radix_trie::TrieCommon;
+
+use super::{is_expired, Db, DbError, Value};
+use crate::rdb::writer::RDBWriter;
+use crate::rdb::{RdbEntry, RdbValue};
+use crate::utils::unix_millis;
+
+impl Value {
+    /// Converts a value to its RDB counterpart, the reverse of `Value::restore`.
+    fn dump(&self) -> RdbValue {
+        match self {
+            Value::String(data) => RdbValue::String(data.clone()),
+            Value::Int(n) => RdbValue::String(Bytes::from(n.to_string())),
+            Value::List(list) => RdbValue::List(list.elements()),
+            Value::Set(set) => RdbValue::Set(set.members()),
+            Value::SortedSet(zset) => RdbValue::SortedSet(zset.members()),
+            Value::Hash(hash) => RdbValue::Hash(hash.pairs()),
+            Value::Stream(stream) => {
+                let mut entries: Vec<_> = stream
+                    .entries
+                    .iter()
+                    .filter_map(|(id, entry)| {
+                        let (millis, seq) = id.split_once('-')?;
+                        let id = (millis.parse().ok()?, seq.parse().ok()?);
+
+                        Some((id, entry.fields.clone()))
+                    })
+                    .collect();
+
+                // the trie orders the ids as strings
+                entries.sort_unstable_by_key(|(id, _)| *id);
+
+                let last_id = stream
+                    .last_id
+                    .as_ref()
+                    .map_or((0, 0), |id| (id.millis as u64, id.seq));
+
+                RdbValue::Stream { entries, last_id }
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Writes the keys of every database to the RDB file, see [`Db::dump`].
+    pub(crate) async fn save(&self) -> Result<(), DbError> {
+        self.start_saving()?;
+
+        let saved = self.write_dump(self.dump().await).await;
+        self.shared.saving.store(false, Ordering::Release);
+
+        saved.map_err(DbError::Save)
+    }
+
+    /// Writes the RDB file like [`Db::save`] in a background task.
+    pub(crate) async fn background_save(&self) -> Result<(), DbError> {
+        self.start_saving()?;
+
+        // dumped before replying, like the fork of Redis, as the shards of the
+        // cores can't be used by another task in thread per core mode
+        let rdb = self.dump().await;
+        let db = self.clone();
+
+        tokio::spawn(async move {
+            match db.write_dump(rdb).await {
+                Ok(()) => println!("Background saving terminated with success"),
+                Err(e) => println!("Background saving error: {}", e),
+            }
+
+            db.shared.saving.store(false, Ordering::Release);
+        });
+
+        Ok(())
+    }
+
+    fn start_saving(&self) -> Result<(), DbError> {
+        self.shared
+            .saving
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .map(|_| ())
+            .map_err(|_| DbError::SaveInProgress)
+    }
+
+    async fn write_dump(&self, rdb: Vec<u8>) -> std::io::Result<()> {
+        let dbfile = self.config().persistence().dbfile();
+
+        // written aside then renamed, so that a failed save keeps the last dump
+        let temp = dbfile.with_file_name(format!("temp-{}.rdb", std::process::id()));
+
+        tokio::fs::write(&temp, rdb).await?;
+        tokio::fs::rename(&temp, &dbfile).await
+    }
+
+    /// Returns the keys of every database that aren't expired as an RDB file.
+    /// The shards are locked one after another, so the keys of different shards
+    /// may be dumped at different times.
+    pub(crate) async fn dump(&self) -> Vec<u8> {
+        let mut writer = RDBWriter::new();
+
+        for (db, shards) in self.shared.databases.iter().enumerate() {
+            for shard in shards.iter() {
+                let shard = shard.lock().await;
+                let now = unix_millis();
+
+                for (key, entry) in shard.keyspace.iter() {
+                    if is_expired(entry.expires_at, now) {
+                        continue;
+                    }
+
+                    writer.write_entry(&RdbEntry {
+                        db,
+                        key: key.clone(),
+                        value: entry.value.dump(),
+                        expires_at: entry.expires_at,
+                        encoding: "",
+                        size: 0,
+                    });
+                }
+            }
+        }
+
+        writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::rdb::{json, RDBParser};
+    use crate::test_utils::{db, db_with};
+
+    const FIXTURE: &str = include_str!("../../fixtures/keyspace.jsonl");
+
+    #[tokio::test]
+    async fn test_dump_is_loaded_back() {
+        let db = db();
+        db.load_fixture(FIXTURE).await;
+        db.set("int".into(), Bytes::from("-12"), None).await;
+        db.set("expired".into(), Bytes::from("v"), Some(1)).await;
+
+        let rdb = db.dump().await;
+
+        let mut keys = vec![];
+        let mut parser = RDBParser::new(Cursor::new(rdb.clone()));
+        parser.read_header().await.unwrap();
+
+        while let Some(entry) = parser.next_entry().await.unwrap() {
+            keys.push(json::to_json(&entry).to_string());
+        }
+
+        keys.sort();
+        let mut expected: Vec<_> = FIXTURE
+            .lines()
+            .map(|line| json::to_json(&json::parse(line).unwrap()).to_string())
+            .chain([r#"{"db":0,"key":"int","type":"string","value":"-12"}"#.to_string()])
+            .collect();
+        expected.sort();
+
+        assert_eq!(keys, expected);
+
+        let loaded = db_with(&[]);
+        loaded.load(RDBParser::new(Cursor::new(rdb))).await.unwrap();
+        assert_eq!(loaded.dump().await.len(), db.dump().await.len());
+    }
+
+    #[tokio::test]
+    async fn test_save_writes_the_dbfile() {
+        let dir = std::env::temp_dir().join(format!("redis-clone-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let db = db_with(&["--dir", dir.to_str().unwrap()]);
+        db.set("key".into(), Bytes::from("value"), None).await;
+
+        db.shared.saving.store(true, Ordering::Relaxed);
+        assert!(matches!(db.save().await, Err(DbError::SaveInProgress)));
+        db.shared.saving.store(false, Ordering::Relaxed);
+
+        db.save().await.unwrap();
+
+        let rdb = std::fs::read(dir.join("dump.rdb")).unwrap();
+        assert_eq!(rdb, db.dump().await);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use bytes::Bytes;
+use rand::{thread_rng, Rng};
+
+/// Smallest number of buckets of a table
+const MIN_SIZE: usize = 4;
+
+/// Hash table with a power of two number of buckets, used for the keyspace
+/// and for the hashtable encodings of collections.
+///
+/// Unlike `std::collections::HashMap` its buckets are exposed, which allows a
+/// cursor based incremental iteration ([`Table::scan`]) and picking random keys.
+#[derive(Debug, Clone)]
+pub(crate) struct Table<V> {
+    buckets: Vec<Vec<(Bytes, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> FromIterator<(Bytes, V)> for Table<V> {
+    /// Builds a table, keeping the last value of keys given twice.
+    fn from_iter<I: IntoIterator<Item = (Bytes, V)>>(entries: I) -> Self {
+        let mut table = Self::new();
+        for (key, value) in entries {
+            table.insert(key, value);
+        }
+        table
+    }
+}
+
+impl<V> Table<V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: Self::empty_buckets(MIN_SIZE),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn empty_buckets(size: usize) -> Vec<Vec<(Bytes, V)>> {
+        (0..size).map(|_| Vec::new()).collect()
+    }
+
+    fn mask(&self) -> u64 {
+        self.buckets.len() as u64 - 1
+    }
+
+    fn bucket(&self, key: &[u8]) -> usize {
+        (self.hasher.hash_one(key) & self.mask()) as usize
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, key: &[u8]) -> Option<&V> {
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub(crate) fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let bucket = self.bucket(key);
+
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub(crate) fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a value and returns the value previously stored at `key`.
+    pub(crate) fn insert(&mut self, key: Bytes, value: V) -> Option<V> {
+        if let Some(current) = self.get_mut(&key) {
+            return Some(std::mem::replace(current, value));
+        }
+
+        let bucket = self.bucket(&key);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+
+        if self.len > self.buckets.len() {
+            self.resize(self.buckets.len() * 2);
+        }
+
+        None
+    }
+
+    pub(crate) fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let bucket = self.bucket(key);
+
+        let index = self.buckets[bucket].iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(index);
+        self.len -= 1;
+
+        // shrink tables that are less than 1/8 full
+        if self.buckets.len() > MIN_SIZE && self.len * 8 < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_SIZE));
+        }
+
+        Some(value)
+    }
+
+    fn resize(&mut self, size: usize) {
+        let buckets = std::mem::replace(&mut self.buckets, Self::empty_buckets(size));
+
+        for (key, value) in buckets.into_iter().flatten() {
+            let bucket = self.bucket(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Bytes, &V)> {
+        self.buckets.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    /// Visits one bucket of the table and returns the cursor of the next bucket to
+    /// visit, or `0` once the iteration is complete. Start with cursor `0`.
+    ///
+    /// Works like Redis's `dictScan`: the cursor is incremented with its bits
+    /// reversed, so buckets are visited in an order that stays valid when the
+    /// table grows or shrinks between calls. Keys present for the whole iteration
+    /// are returned at least once, keys may be returned more than once.
+    pub(crate) fn scan(&self, cursor: u64, mut visit: impl FnMut(&Bytes, &V)) -> u64 {
+        let mask = self.mask();
+
+        for (key, value) in self.buckets[(cursor & mask) as usize].iter() {
+            visit(key, value);
+        }
+
+        // set the unmasked bits so that incrementing the reversed cursor carries
+        // over to the masked bits only
+        let cursor = cursor | !mask;
+
+        cursor.reverse_bits().wrapping_add(1).reverse_bits()
+    }
+
+    /// Returns a random entry, or `None` if the table is empty.
+    pub(crate) fn random(&self) -> Option<(&Bytes, &V)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut rng = thread_rng();
+
+        // tables are at least 1/8 full, so a non empty bucket is found quickly
+        loop {
+            let bucket = &self.buckets[rng.gen_range(0..self.buckets.len())];
+
+            if !bucket.is_empty() {
+                let (key, value) = &bucket[rng.gen_range(0..bucket.len())];
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn key(i: usize) -> Bytes {
+        Bytes::from(format!("key{i}"))
+    }
+
+    fn scan_all(table: &Table<usize>) -> Vec<Bytes> {
+        let mut keys = vec![];
+        let mut cursor = 0;
+
+        loop {
+            cursor = table.scan(cursor, |key, _| keys.push(key.clone()));
+
+            if cursor == 0 {
+                return keys;
+            }
+        }
+    }
+
+    #[test]
+    fn test_table_insert_get_remove() {
+        let mut table = Table::new();
+
+        for i in 0..1000 {
+            assert_eq!(table.insert(key(i), i), None);
+        }
+
+        assert_eq!(table.len(), 1000);
+        assert_eq!(table.insert(key(1), 42), Some(1));
+        assert_eq!(table.get(b"key1"), Some(&42));
+
+        for i in 0..1000 {
+            assert!(table.remove(&key(i)).is_some());
+        }
+
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.buckets.len(), MIN_SIZE);
+        assert_eq!(table.random(), None);
+    }
+
+    #[test]
+    fn test_table_scan_returns_every_key_once() {
+        let mut table = Table::new();
+
+        for i in 0..1000 {
+            table.insert(key(i), i);
+        }
+
+        let keys = scan_all(&table);
+
+        assert_eq!(keys.len(), 1000);
+        assert_eq!(keys.iter().collect::<HashSet<_>>().len(), 1000);
+    }
+
+    #[test]
+    fn test_table_scan_across_resizes() {
+        let table = std::cell::RefCell::new(Table::new());
+
+        for i in 0..100 {
+            table.borrow_mut().insert(key(i), i);
+        }
+
+        // grow the table while iterating
+        let mut next = 100;
+        let mut grown = vec![];
+        let mut cursor = 0;
+
+        loop {
+            cursor = table
+                .borrow()
+                .scan(cursor, |key, _| grown.push(key.clone()));
+
+            for _ in 0..50 {
+                if next < 5000 {
+                    table.borrow_mut().insert(key(next), next);
+                    next += 1;
+                }
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let grown: HashSet<_> = grown.into_iter().collect();
+        assert!((0..100).all(|i| grown.contains(&key(i))));
+
+        // shrink the table while iterating
+        let mut shrunk = vec![];
+        let mut cursor = 0;
+        let mut removed = 100;
+
+        loop {
+            cursor = table
+                .borrow()
+                .scan(cursor, |key, _| shrunk.push(key.clone()));
+
+            for _ in 0..200 {
+                if removed < next {
+                    table.borrow_mut().remove(&key(removed));
+                    removed += 1;
+                }
+            }
+
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        let shrunk: HashSet<_> = shrunk.into_iter().collect();
+        assert!((0..100).all(|i| shrunk.contains(&key(i))));
+    }
+}
@@ -0,0 +1,4 @@
+//! Parts of redis-clone that don't depend on the server, shared by the server
+//! and the `redis-check-rdb` and `rdb-json` binaries.
+
+pub mod rdb;
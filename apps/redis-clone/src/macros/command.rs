@@ -98,15 +98,15 @@ macro_rules! next_arg {
 
 #[cfg(test)]
 mod tests {
-    use crate::next_arg;
     use crate::resp::RespValue;
+    use bytes::Bytes;
     use std::convert::TryInto;
 
     #[test]
     fn test_next_arg() {
         let mut args = vec![
-            RespValue::BulkString(b"key".to_vec()),
-            RespValue::BulkString(b"value".to_vec()),
+            RespValue::BulkString(Bytes::from_static(b"key")),
+            RespValue::BulkString(Bytes::from_static(b"value")),
         ]
         .into_iter();
 
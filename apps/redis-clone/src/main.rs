@@ -1,27 +1,36 @@
 mod commands;
 mod conf;
 mod connection;
+mod cores;
 mod db;
 mod macros;
-mod rdb;
+mod pubsub;
 mod resp;
+#[cfg(test)]
+mod test_utils;
+mod transaction;
 mod utils;
 
+use bytes::Bytes;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use clap::Parser;
-use tokio::net::TcpListener;
-use tokio::sync::broadcast::{self, Sender};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 
+use redis_clone::rdb;
+
 use crate::commands::CommandTrait;
 use crate::conf::Config;
 use crate::connection::Connection;
 use crate::connection::ConnectionRead;
 use crate::connection::ConnectionWrite;
-use crate::db::DbBuilder;
-use crate::resp::RespValue;
+use crate::cores::Cores;
+use crate::db::{Db, DbBuilder};
+use crate::pubsub::Subscriptions;
+use crate::resp::{Protocol, RespValue};
+use crate::transaction::Transaction;
 
 // const DEFAULT_ACK_EVERY: u64 = 1000;
 
@@ -35,14 +44,41 @@ struct Cli {
     dir: Option<std::path::PathBuf>,
     #[clap(long, default_value = "dump.rdb")]
     dbfilename: String,
+    #[clap(long, default_value = "10")]
+    hz: u32,
+    #[clap(long, default_value = "")]
+    notify_keyspace_events: conf::KeyspaceEvents,
+    #[clap(long, default_value = "512mb")]
+    proto_max_bulk_len: conf::MemorySize,
+    #[clap(long, default_value = "1gb")]
+    client_query_buffer_limit: conf::MemorySize,
+    #[clap(long, default_value = "0")]
+    maxmemory: conf::MemorySize,
+    #[clap(long, default_value = "noeviction")]
+    maxmemory_policy: conf::MaxmemoryPolicy,
+    #[clap(long, default_value = "5")]
+    maxmemory_samples: usize,
+    #[clap(long, default_value = "16")]
+    databases: usize,
+    /// Run a thread per core, each owning a part of the keyspace
+    #[clap(long)]
+    thread_per_core: bool,
+    /// Number of core threads with `--thread-per-core`, the number of CPUs by default
+    #[clap(long)]
+    cores: Option<usize>,
 }
 
-async fn propaginate_slave(connection: &mut ConnectionWrite, sender: Arc<Sender<RespValue>>) {
-    let mut receiver = sender.subscribe();
+async fn propaginate_slave(connection: &mut ConnectionWrite, db: Db) {
+    let mut receiver = db.subscribe_replication();
 
     while let Ok(f) = receiver.recv().await {
         println!("Sending {:?}", f);
         connection.write(&f).await;
+
+        // batch the commands that were propagated while writing
+        if receiver.is_empty() {
+            connection.flush().await;
+        }
     }
 }
 
@@ -64,37 +100,33 @@ async fn read_slave(
             }
             Err(_) => {
                 println!("Failed to read from connection");
-                continue;
+                let mut replica_offsets = replica_offsets.lock().await;
+                replica_offsets.remove(&connection.id());
+
+                break;
             }
         };
 
-        match commands::Command::try_from(resp.clone()) {
-            Ok(commands::Command::Replconf(command)) => match command {
-                commands::Replconf::Ack(offset) => {
-                    let mut replica_offsets = replica_offsets.lock().await;
+        if let Ok(commands::Command::Replconf(commands::Replconf::Ack(offset))) =
+            commands::Command::try_from(resp.clone())
+        {
+            let mut replica_offsets = replica_offsets.lock().await;
 
-                    let (_, start_offset) = replica_offsets.get(&connection.id()).unwrap().clone();
-                    replica_offsets.remove(&connection.id());
-                    replica_offsets.insert(connection.id(), (offset, start_offset));
-                }
-                _ => {}
-            },
-            _ => {}
+            let (_, start_offset) = *replica_offsets.get(&connection.id()).unwrap();
+            replica_offsets.remove(&connection.id());
+            replica_offsets.insert(connection.id(), (offset, start_offset));
         }
     }
 }
 
 async fn count_sync_replicas(
     replica_offsets: Arc<Mutex<HashMap<String, (u64, u64)>>>,
-    master_offset: Arc<Mutex<u64>>,
+    master_offset: u64,
 ) -> u32 {
     let replica_offsets = replica_offsets.lock().await;
-    let master_offset = master_offset.lock().await;
 
     let mut count = 0;
 
-    let master_offset = *master_offset;
-
     println!("master offset: {}", master_offset);
     println!("replica offsets: {:?}", replica_offsets);
 
@@ -113,8 +145,7 @@ async fn wait_for_more_replicas(
     connection: &mut Connection,
     num_of_replicas: u32,
     replica_offsets: Arc<Mutex<HashMap<String, (u64, u64)>>>,
-    master_offset: Arc<Mutex<u64>>,
-    sender: Arc<Sender<RespValue>>,
+    db: &Db,
     timeout: u64,
 ) {
     const WAIT_ATTEMPTS: u64 = 75;
@@ -125,19 +156,18 @@ async fn wait_for_more_replicas(
 
     while connected < num_of_replicas && atempts > 0 {
         let getack = RespValue::Array(vec![
-            RespValue::BulkString("REPLCONF".as_bytes().to_vec()),
-            RespValue::BulkString("GETACK".as_bytes().to_vec()),
-            RespValue::BulkString("*".as_bytes().to_vec()),
+            RespValue::BulkString(Bytes::from_static(b"REPLCONF")),
+            RespValue::BulkString(Bytes::from_static(b"GETACK")),
+            RespValue::BulkString(Bytes::from_static(b"*")),
         ]);
 
-        sender.send(getack).unwrap();
+        db.send_to_replicas(getack);
 
         tokio::time::sleep(std::time::Duration::from_millis(WAIT_ATTEMPTS)).await;
 
         let replica_offsets = Arc::clone(&replica_offsets);
-        let master_offset = Arc::clone(&master_offset);
 
-        connected = count_sync_replicas(replica_offsets, master_offset).await;
+        connected = count_sync_replicas(replica_offsets, db.master_offset()).await;
         atempts -= 1;
     }
 
@@ -159,14 +189,23 @@ async fn main() {
 
     let db_builder = DbBuilder::new(config);
 
-    let (sender, _rx) = broadcast::channel(16);
-    let sender = Arc::new(sender);
-
     println!("Server started at {}", address);
 
     let db = db_builder.db();
 
-    let config = db.config().await;
+    let config = db.config();
+
+    let replica_offsets: Arc<Mutex<HashMap<String, (u64, u64)>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+
+    // the cores own the keyspace, so they run before anything uses it
+    let cores = db.cores().map(|_| {
+        let replica_offsets = Arc::clone(&replica_offsets);
+
+        Cores::start(&db, move |stream, db, cores| {
+            handle_connection(stream, db, Arc::clone(&replica_offsets), Some(cores))
+        })
+    });
 
     let dbfile = config.persistence().dbfile();
 
@@ -178,173 +217,200 @@ async fn main() {
                 let cursor = std::io::Cursor::new(rdb);
                 println!("Loading RDB file");
 
-                let mut parser = rdb::RDBParser::new(cursor);
-                match parser.load(&db).await {
-                    Ok(_) => {
+                let parser = rdb::RDBParser::new(cursor);
+
+                let loaded = match &cores {
+                    Some(cores) => cores.exclusive_all(db.load(parser)).await,
+                    None => Ok(db.load(parser).await),
+                };
+
+                match loaded {
+                    Ok(Ok(_)) => {
                         println!("RDB loaded");
                     }
-                    Err(e) => {
+                    // like Redis, refuse to start rather than serve a partial dataset
+                    Ok(Err(e)) => {
                         println!("Failed to load RDB {:?}", e);
+                        std::process::exit(1);
+                    }
+                    Err(e) => {
+                        println!("Failed to load RDB: {}", e);
+                        std::process::exit(1);
                     }
                 }
             }
-            Err(_) => {
-                println!("Failed to read RDB file");
+            Err(e) => {
+                println!("Failed to read RDB file: {}", e);
+                std::process::exit(1);
             }
         }
     }
 
-    match config.replication().role {
-        conf::ReplicationRole::Slave {
-            master_host,
-            master_port,
-        } => {
-            tokio::spawn(async move {
-                println!("Connecting to master");
+    if let conf::ReplicationRole::Slave {
+        master_host,
+        master_port,
+    } = config.replication().role
+    {
+        let mut db = db.clone();
+        let cores = cores.clone();
 
-                let stream =
-                    tokio::net::TcpStream::connect(format!("{}:{}", master_host, master_port))
-                        .await
-                        .unwrap();
+        tokio::spawn(async move {
+            println!("Connecting to master");
 
-                let mut connection = Connection::new(stream);
+            let stream = tokio::net::TcpStream::connect(format!("{}:{}", master_host, master_port))
+                .await
+                .unwrap();
 
-                let command_resp = RespValue::from("PING").as_bulk().unwrap();
+            let mut connection = Connection::new(stream);
 
-                let command = RespValue::Array(vec![command_resp]);
+            let command_resp = RespValue::from("PING").as_bulk().unwrap();
 
-                connection.write(&command).await;
+            let command = RespValue::Array(vec![command_resp]);
 
-                let (response, _) = connection.read().await.unwrap();
+            connection.send(&command).await;
 
-                if response != RespValue::SimpleString("PONG".to_string()) {
-                    println!("ERR master is not responding");
-                    return;
-                }
+            let (response, _) = connection.read().await.unwrap();
 
-                let replconf = RespValue::Array(vec![
-                    RespValue::BulkString("REPLCONF".as_bytes().to_vec()),
-                    RespValue::BulkString("listening-port".as_bytes().to_vec()),
-                    RespValue::BulkString(port.to_string().as_bytes().to_vec()),
-                ]);
+            if response != RespValue::SimpleString("PONG".to_string()) {
+                println!("ERR master is not responding");
+                return;
+            }
 
-                connection.write(&replconf).await;
+            let replconf = RespValue::Array(vec![
+                RespValue::BulkString(Bytes::from_static(b"REPLCONF")),
+                RespValue::BulkString(Bytes::from_static(b"listening-port")),
+                RespValue::BulkString(Bytes::copy_from_slice(port.to_string().as_bytes())),
+            ]);
 
-                let (response, _) = connection.read().await.unwrap();
+            connection.send(&replconf).await;
 
-                if response != RespValue::SimpleString("OK".to_string()) {
-                    println!("ERR failed to set listening port on master");
-                    return;
-                }
+            let (response, _) = connection.read().await.unwrap();
 
-                let replconf = RespValue::Array(vec![
-                    RespValue::BulkString("REPLCONF".as_bytes().to_vec()),
-                    RespValue::BulkString("capa".as_bytes().to_vec()),
-                    RespValue::BulkString("psync2".as_bytes().to_vec()),
-                ]);
+            if response != RespValue::SimpleString("OK".to_string()) {
+                println!("ERR failed to set listening port on master");
+                return;
+            }
 
-                connection.write(&replconf).await;
+            let replconf = RespValue::Array(vec![
+                RespValue::BulkString(Bytes::from_static(b"REPLCONF")),
+                RespValue::BulkString(Bytes::from_static(b"capa")),
+                RespValue::BulkString(Bytes::from_static(b"psync2")),
+            ]);
 
-                let (response, _) = connection.read().await.unwrap();
+            connection.send(&replconf).await;
 
-                if response != RespValue::SimpleString("OK".to_string()) {
-                    println!("ERR failed to set capa on master");
-                    return;
-                }
+            let (response, _) = connection.read().await.unwrap();
 
-                let psync = RespValue::Array(vec![
-                    RespValue::BulkString("PSYNC".as_bytes().to_vec()),
-                    RespValue::BulkString("?".as_bytes().to_vec()),
-                    RespValue::BulkString("-1".as_bytes().to_vec()),
-                ]);
+            if response != RespValue::SimpleString("OK".to_string()) {
+                println!("ERR failed to set capa on master");
+                return;
+            }
+
+            let psync = RespValue::Array(vec![
+                RespValue::BulkString(Bytes::from_static(b"PSYNC")),
+                RespValue::BulkString(Bytes::from_static(b"?")),
+                RespValue::BulkString(Bytes::from_static(b"-1")),
+            ]);
 
-                connection.write(&psync).await;
+            connection.send(&psync).await;
 
-                let (response, _) = connection.read().await.unwrap();
+            let (response, _) = connection.read().await.unwrap();
 
-                match response {
-                    RespValue::SimpleString(response) => {
-                        if response.starts_with("FULLRESYNC") {
-                            println!("Full resync from master");
+            match response {
+                RespValue::SimpleString(response) => {
+                    if response.starts_with("FULLRESYNC") {
+                        println!("Full resync from master");
+                    }
+                    let rdb = connection.read().await;
+                    match rdb {
+                        Ok(rdb) => {
+                            // TODO: apply RDB
+                            println!("RDB received {:?}", rdb);
                         }
-                        let rdb = connection.read().await;
-                        match rdb {
-                            Ok(rdb) => {
-                                // TODO: apply RDB
-                                println!("RDB received {:?}", rdb);
-                            }
-                            Err(_) => {
-                                return;
-                            }
+                        Err(_) => {
+                            return;
                         }
                     }
-                    _ => {
-                        println!("ERR failed to start replication");
-                        return;
-                    }
                 }
+                _ => {
+                    println!("ERR failed to start replication");
+                    return;
+                }
+            }
 
-                println!(
-                    "Connected to master at {}:{}. Starting replication",
-                    master_host, master_port
-                );
+            println!(
+                "Connected to master at {}:{}. Starting replication",
+                master_host, master_port
+            );
 
-                let mut offset: i64 = 0;
+            let mut offset: i64 = 0;
+            let mut transaction = Transaction::new();
 
-                loop {
-                    let request_result = connection.read().await;
+            loop {
+                let request_result = connection.read().await;
 
-                    let request = match &request_result {
-                        Ok(request) => request,
-                        Err(connection::ConnectionError::ResetByPeer) => {
-                            println!("Connection reset by peer");
-                            return;
-                        }
-                        Err(_) => {
-                            println!("Failed to read from connection");
-                            return;
-                        }
-                    };
+                let request = match &request_result {
+                    Ok(request) => request,
+                    Err(connection::ConnectionError::ResetByPeer) => {
+                        println!("Connection reset by peer");
+                        return;
+                    }
+                    Err(_) => {
+                        println!("Failed to read from connection");
+                        return;
+                    }
+                };
 
-                    let (resp, len) = request.clone();
+                let (resp, len) = request.clone();
 
-                    match commands::Command::try_from(resp) {
-                        Ok(commands::Command::Replconf(_)) => {
-                            let resp = RespValue::Array(vec![
-                                RespValue::BulkString("REPLCONF".as_bytes().to_vec()),
-                                RespValue::BulkString("ACK".as_bytes().to_vec()),
-                                RespValue::BulkString(offset.to_string().as_bytes().to_vec()),
-                            ]);
+                match commands::Command::try_from(resp) {
+                    Ok(commands::Command::Replconf(_)) => {
+                        let resp = RespValue::Array(vec![
+                            RespValue::BulkString(Bytes::from_static(b"REPLCONF")),
+                            RespValue::BulkString(Bytes::from_static(b"ACK")),
+                            RespValue::BulkString(Bytes::copy_from_slice(
+                                offset.to_string().as_bytes(),
+                            )),
+                        ]);
 
-                            offset += len as i64;
-                            connection.write(&resp).await;
-                        }
-                        Ok(command) => {
-                            let _resp = command.execute(&db).await.unwrap();
-                            offset += len as i64;
-                        }
-                        Err(e) => {
-                            println!("ERR unknown command {:?}", e);
-                            connection
-                                .write(&RespValue::SimpleError("ERR unknown command".to_string()))
-                                .await;
-                        }
-                    };
-                }
-            });
-        }
-        _ => {}
+                        offset += len as i64;
+                        connection.send(&resp).await;
+                    }
+                    Ok(command) => {
+                        let request = &request.0;
+                        let _resp = match &cores {
+                            Some(cores) => {
+                                cores
+                                    .execute(command, request, &mut transaction, &mut db)
+                                    .await
+                            }
+                            None => transaction.execute(command, request, &mut db).await,
+                        };
+                        offset += len as i64;
+                    }
+                    Err(e) => {
+                        println!("ERR unknown command {:?}", e);
+                        connection
+                            .send(&RespValue::SimpleError("ERR unknown command".to_string()))
+                            .await;
+                    }
+                };
+            }
+        });
     }
 
-    let master_offset: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+    // the cores expire the keys they own
+    if let (conf::ReplicationRole::Master, None) = (&config.replication().role, &cores) {
+        let db = db.clone();
+        let hz = config.hz();
 
-    let replica_offsets: Arc<Mutex<HashMap<String, (u64, u64)>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(async move {
+            db.active_expire(hz).await;
+        });
+    }
 
     // if let conf::ReplicationRole::Master = config.replication().role {
-    //     let sender: Arc<broadcast::Sender<RespValue>> = Arc::clone(&sender);
-
-    //     let master_offset = Arc::clone(&master_offset);
+    //     let db = db.clone();
 
     //     tokio::spawn(async move {
     //         loop {
@@ -353,19 +419,12 @@ async fn main() {
     //             // TODO: send ack only if master has pending data\
 
     //             let getack = RespValue::Array(vec![
-    //                 RespValue::BulkString("REPLCONF".as_bytes().to_vec()),
-    //                 RespValue::BulkString("GETACK".as_bytes().to_vec()),
-    //                 RespValue::BulkString("*".as_bytes().to_vec()),
+    //                 RespValue::BulkString(Bytes::from_static(b"REPLCONF")),
+    //                 RespValue::BulkString(Bytes::from_static(b"GETACK")),
+    //                 RespValue::BulkString(Bytes::from_static(b"*")),
     //             ]);
 
-    //             let len = getack.size() as u64;
-
-    //             sender.send(getack).unwrap();
-
-    //             {
-    //                 let mut master_offset = master_offset.lock().await;
-    //                 *master_offset += len;
-    //             }
+    //             db.propagate(getack);
     //         }
     //     });
     // }
@@ -373,124 +432,270 @@ async fn main() {
     loop {
         let db = db_builder.db();
 
-        let config = db.config().await;
-
         let (stream, _) = listener.accept().await.unwrap();
 
-        let sender: Arc<broadcast::Sender<RespValue>> = Arc::clone(&sender);
-
-        let master_offset = Arc::clone(&master_offset);
+        if let Some(cores) = &cores {
+            cores.assign(stream);
+            continue;
+        }
 
         let replica_offsets = Arc::clone(&replica_offsets);
 
-        tokio::spawn(async move {
-            println!("Accepted new connection");
+        tokio::spawn(handle_connection(stream, db, replica_offsets, None));
+    }
+}
 
-            let mut connection = Connection::new(stream);
+/// Serves the requests of a client until it disconnects. With `cores` set the
+/// commands are executed by the cores owning their keys.
+async fn handle_connection(
+    stream: TcpStream,
+    mut db: Db,
+    replica_offsets: Arc<Mutex<HashMap<String, (u64, u64)>>>,
+    cores: Option<Cores>,
+) {
+    println!("Accepted new connection");
 
-            loop {
-                let request_result = connection.read().await;
+    let mut connection = Connection::new(stream);
+    connection.set_limits(db.config().protocol_limits());
 
-                let request = match &request_result {
-                    Ok(request) => request,
-                    Err(connection::ConnectionError::ResetByPeer) => {
-                        println!("Connection reset by peer");
-                        break;
-                    }
-                    Err(_) => {
-                        println!("Failed to read from connection");
-                        continue;
+    let mut transaction = Transaction::new();
+    let mut subscriptions = Subscriptions::new(db.pubsub());
+
+    loop {
+        // pipelined requests are handled before waiting for more, and their
+        // replies are flushed together once the buffer has no complete request
+        let request_result = match connection.read_buffered() {
+            Ok(Some(request)) => Ok(request),
+            Ok(None) => {
+                connection.flush().await;
+
+                tokio::select! {
+                    request = connection.read() => request,
+                    message = subscriptions.next_message() => {
+                        match message {
+                            Some(message) => {
+                                connection.write(&message).await;
+                                continue;
+                            }
+                            None => {
+                                println!("Disconnecting slow subscriber");
+                                break;
+                            }
+                        }
                     }
-                };
+                }
+            }
+            Err(e) => Err(e),
+        };
 
-                let (resp_clone, _) = request.clone();
+        let request = match &request_result {
+            Ok(request) => request,
+            Err(connection::ConnectionError::ResetByPeer) => {
+                println!("Connection reset by peer");
+                break;
+            }
+            // the rest of the buffer can't be trusted, so reply and close
+            Err(e @ connection::ConnectionError::Protocol(_)) => {
+                println!("{}", e);
+                connection
+                    .send(&RespValue::SimpleError(format!("ERR {}", e)))
+                    .await;
+                break;
+            }
+            Err(e @ connection::ConnectionError::QueryBufferLimit) => {
+                println!("Closing client: {}", e);
+                break;
+            }
+            Err(_) => {
+                println!("Failed to read from connection");
+                break;
+            }
+        };
 
-                match commands::Command::try_from(resp_clone) {
-                    Ok(commands::Command::Psync(command)) => {
-                        let resp = command.execute(&db).await.unwrap();
+        let (resp_clone, _) = request.clone();
+
+        match commands::Command::try_from(resp_clone) {
+            // RESP3 connections can use any command since pushed messages
+            // can't be confused with replies
+            Ok(command)
+                if subscriptions.is_subscribed()
+                    && connection.protocol() == Protocol::Resp2
+                    && !command.allowed_when_subscribed() =>
+            {
+                connection
+                    .write(&RespValue::SimpleError(format!(
+                        "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT are allowed in this context",
+                        command_name(&request.0)
+                    )))
+                    .await;
+            }
+            Ok(commands::Command::Quit) => {
+                connection
+                    .send(&RespValue::SimpleString("OK".to_string()))
+                    .await;
+                break;
+            }
+            Ok(
+                commands::Command::Psync(_)
+                | commands::Command::Wait(_)
+                | commands::Command::Hello(_)
+                | commands::Command::Subscribe(_)
+                | commands::Command::Unsubscribe(_),
+            ) if transaction.in_multi() => {
+                transaction.flag_failed();
+                connection
+                    .write(&RespValue::SimpleError(
+                        "ERR Command not allowed inside a transaction".to_string(),
+                    ))
+                    .await;
+            }
+            Ok(commands::Command::Psync(command)) => {
+                let resp = command.execute(&db).await.unwrap();
+
+                connection.write(&resp).await;
+
+                {
+                    let hex_string = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
+                    let empty_file_payload = (0..hex_string.len())
+                        .step_by(2)
+                        .map(|i| {
+                            u8::from_str_radix(&hex_string[i..i + 2], 16)
+                                .expect("hex_string is invalid")
+                        })
+                        .collect::<Vec<_>>();
+
+                    connection
+                        .write_bytes(format!("${}\r\n", empty_file_payload.len()).as_bytes())
+                        .await;
 
-                        connection.write(&resp).await;
+                    connection.write_bytes(empty_file_payload.as_slice()).await;
+                    connection.flush().await;
+                }
 
-                        {
-                            let hex_string = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
-                            let empty_file_payload = (0..hex_string.len())
-                                .step_by(2)
-                                .map(|i| {
-                                    u8::from_str_radix(&hex_string[i..i + 2], 16)
-                                        .expect("hex_string is invalid")
-                                })
-                                .collect::<Vec<_>>();
+                let db_clone = db.clone();
 
-                            connection
-                                .write_bytes(
-                                    format!("${}\r\n", empty_file_payload.len()).as_bytes(),
-                                )
-                                .await;
+                {
+                    let mut replica_offsets = replica_offsets.lock().await;
+                    replica_offsets.insert(connection.id(), (0, db.master_offset()));
+                }
 
-                            connection.write_bytes(empty_file_payload.as_slice()).await;
-                            connection.flush().await;
-                        }
+                let (mut read_connection, mut write_connection) = connection.split();
 
-                        let sender_clone = Arc::clone(&sender);
+                tokio::spawn(async move {
+                    propaginate_slave(&mut write_connection, db_clone).await;
+                });
 
-                        {
-                            let mut replica_offsets = replica_offsets.lock().await;
-                            let master_offset = master_offset.lock().await;
-                            replica_offsets.insert(connection.id(), (0, *master_offset));
-                        }
+                let replica_offsets = Arc::clone(&replica_offsets);
 
-                        let (mut read_connection, mut write_connection) = connection.split();
+                tokio::spawn(async move {
+                    read_slave(&mut read_connection, replica_offsets).await;
+                });
 
-                        tokio::spawn(async move {
-                            propaginate_slave(&mut write_connection, sender_clone).await;
-                        });
+                break;
+            }
+            Ok(commands::Command::Wait(command)) => {
+                // the replies of the earlier requests don't wait for the replicas
+                connection.flush().await;
+
+                let replica_offsets = Arc::clone(&replica_offsets);
+
+                wait_for_more_replicas(
+                    &mut connection,
+                    command.num_of_replicas,
+                    replica_offsets,
+                    &db,
+                    command.timeout,
+                )
+                .await;
+            }
+            Ok(commands::Command::Subscribe(command)) => {
+                let replies = subscriptions.subscribe(db.pubsub(), &command.channels, command.kind);
 
-                        let replica_offsets = Arc::clone(&replica_offsets);
+                for reply in replies {
+                    connection.write(&reply).await;
+                }
+            }
+            Ok(commands::Command::Unsubscribe(command)) => {
+                let replies =
+                    subscriptions.unsubscribe(db.pubsub(), &command.channels, command.kind);
 
-                        tokio::spawn(async move {
-                            read_slave(&mut read_connection, replica_offsets).await;
-                        });
+                for reply in replies {
+                    connection.write(&reply).await;
+                }
+            }
+            Ok(commands::Command::Hello(command)) => {
+                let resp = match command.negotiate(connection.protocol()) {
+                    Ok(protocol) => {
+                        connection.set_protocol(protocol);
 
-                        break;
-                    }
-                    Ok(commands::Command::Wait(command)) => {
-                        let replica_offsets = Arc::clone(&replica_offsets);
-                        let master_offset = Arc::clone(&master_offset);
-
-                        wait_for_more_replicas(
-                            &mut connection,
-                            command.num_of_replicas,
-                            replica_offsets,
-                            master_offset,
-                            Arc::clone(&sender),
-                            command.timeout,
-                        )
-                        .await;
-                    }
-                    Ok(command) => {
-                        let resp = command.execute(&db).await.unwrap();
-
-                        connection.write(&resp).await;
-
-                        if command.is_propagated() {
-                            if let conf::ReplicationRole::Master = config.replication().role {
-                                let (resp, _) = request.clone();
-                                {
-                                    let mut master_offset = master_offset.lock().await;
-                                    *master_offset += resp.size() as u64;
-                                }
-                                sender.send(resp).unwrap();
-                            }
+                        if let Some(name) = &command.setname {
+                            connection.set_name(name.clone());
                         }
+
+                        command.reply(&db, connection.client_id(), protocol)
                     }
-                    Err(e) => {
-                        println!("ERR unknown command {:?}", e);
-                        connection
-                            .write(&RespValue::SimpleError("ERR unknown command".to_string()))
-                            .await;
+                    Err(e) => e,
+                };
+
+                connection.write(&resp).await;
+            }
+            Ok(commands::Command::Ping(command))
+                if subscriptions.is_subscribed() && connection.protocol() == Protocol::Resp2 =>
+            {
+                let message = command.message().unwrap_or_default();
+
+                connection
+                    .write(&RespValue::Array(vec![
+                        RespValue::BulkString(Bytes::from_static(b"pong")),
+                        RespValue::BulkString(Bytes::copy_from_slice(message.as_bytes())),
+                    ]))
+                    .await;
+            }
+            Ok(command) => {
+                let resp = match &cores {
+                    Some(cores) => {
+                        cores
+                            .execute(command, &request.0, &mut transaction, &mut db)
+                            .await
                     }
+                    None => transaction.execute(command, &request.0, &mut db).await,
                 };
+
+                // `COMMAND` has no reply yet, answer it rather than dropping the client
+                let resp = resp.unwrap_or_else(|| {
+                    RespValue::SimpleError(format!(
+                        "ERR no reply to '{}'",
+                        command_name(&request.0)
+                    ))
+                });
+
+                connection.write(&resp).await;
             }
-        });
+            Err(e) => {
+                println!("ERR {:?}", e);
+                transaction.flag_failed();
+                connection
+                    .write(&RespValue::SimpleError(format!("ERR {}", e)))
+                    .await;
+            }
+        };
+    }
+
+    match &cores {
+        Some(cores) => cores.unwatch(&mut transaction).await,
+        None => transaction.unwatch().await,
+    }
+    subscriptions.clear(db.pubsub());
+}
+
+/// Returns the lowercase name of the command in a request.
+fn command_name(request: &RespValue) -> String {
+    match request {
+        RespValue::Array(args) => args
+            .first()
+            .and_then(|name| String::try_from(name).ok())
+            .unwrap_or_default()
+            .to_lowercase(),
+        _ => String::new(),
     }
 }
@@ -0,0 +1,509 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use tokio::sync::{mpsc, Notify};
+
+use crate::resp::RespValue;
+use crate::utils::glob::glob_match;
+
+/// Number of messages buffered for a subscriber. Subscribers that fall further
+/// behind are disconnected instead of blocking publishers.
+const SUBSCRIBER_BUFFER_SIZE: usize = 1024;
+
+/// Kind of subscription, each kind has its own registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// `SUBSCRIBE` to a channel
+    Channel,
+    /// `PSUBSCRIBE` to a glob-style pattern
+    Pattern,
+    /// `SSUBSCRIBE` to a shard channel
+    Shard,
+}
+
+impl Kind {
+    fn subscribe(&self) -> &'static str {
+        match self {
+            Kind::Channel => "subscribe",
+            Kind::Pattern => "psubscribe",
+            Kind::Shard => "ssubscribe",
+        }
+    }
+
+    fn unsubscribe(&self) -> &'static str {
+        match self {
+            Kind::Channel => "unsubscribe",
+            Kind::Pattern => "punsubscribe",
+            Kind::Shard => "sunsubscribe",
+        }
+    }
+}
+
+/// Registry of the channels and patterns clients are subscribed to
+#[derive(Debug, Default)]
+pub(crate) struct PubSub {
+    registry: Mutex<Registry>,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    channels: HashMap<String, HashMap<u64, Subscriber>>,
+    patterns: HashMap<String, HashMap<u64, Subscriber>>,
+    shard_channels: HashMap<String, HashMap<u64, Subscriber>>,
+}
+
+/// Sending half of a subscribed client
+#[derive(Debug, Clone)]
+struct Subscriber {
+    sender: mpsc::Sender<RespValue>,
+    /// Notified once the client fell too far behind and must be disconnected
+    overflow: Arc<Notify>,
+}
+
+impl Subscriber {
+    /// Queues a message without waiting. Returns `false` if the subscriber
+    /// should be removed from the registry.
+    fn deliver(&self, message: RespValue) -> bool {
+        match self.sender.try_send(message) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.overflow.notify_one();
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+impl Registry {
+    fn subscriptions(&mut self, kind: Kind) -> &mut HashMap<String, HashMap<u64, Subscriber>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        for subscriptions in [
+            &mut self.channels,
+            &mut self.patterns,
+            &mut self.shard_channels,
+        ] {
+            subscriptions.retain(|_, subscribers| {
+                subscribers.remove(&id);
+                !subscribers.is_empty()
+            });
+        }
+    }
+}
+
+/// Delivers a frame to every subscriber, collecting the ones that have to be
+/// removed. Returns the number of subscribers that received it.
+fn deliver_all(
+    subscribers: &HashMap<u64, Subscriber>,
+    frame: RespValue,
+    slow: &mut Vec<u64>,
+) -> usize {
+    let mut received = 0;
+
+    for (id, subscriber) in subscribers {
+        if subscriber.deliver(frame.clone()) {
+            received += 1;
+        } else {
+            slow.push(*id);
+        }
+    }
+
+    received
+}
+
+impl PubSub {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delivers a message to the subscribers of `channel` and of the patterns
+    /// matching it. Returns the number of clients that received the message.
+    pub(crate) fn publish(&self, channel: &str, message: &Bytes) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+
+        let mut received = 0;
+        let mut slow = vec![];
+
+        if let Some(subscribers) = registry.channels.get(channel) {
+            let frame = RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"message")),
+                RespValue::BulkString(Bytes::copy_from_slice(channel.as_bytes())),
+                RespValue::BulkString(message.clone()),
+            ]);
+
+            received += deliver_all(subscribers, frame, &mut slow);
+        }
+
+        for (pattern, subscribers) in registry.patterns.iter() {
+            if !glob_match(pattern.as_bytes(), channel.as_bytes(), false) {
+                continue;
+            }
+
+            let frame = RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"pmessage")),
+                RespValue::BulkString(Bytes::copy_from_slice(pattern.as_bytes())),
+                RespValue::BulkString(Bytes::copy_from_slice(channel.as_bytes())),
+                RespValue::BulkString(message.clone()),
+            ]);
+
+            received += deliver_all(subscribers, frame, &mut slow);
+        }
+
+        for id in slow {
+            registry.remove(id);
+        }
+
+        received
+    }
+
+    /// Delivers a message to the subscribers of the shard channel `channel`.
+    /// Patterns don't apply to shard channels.
+    pub(crate) fn spublish(&self, channel: &str, message: &Bytes) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+
+        let Some(subscribers) = registry.shard_channels.get(channel) else {
+            return 0;
+        };
+
+        let frame = RespValue::Push(vec![
+            RespValue::BulkString(Bytes::from_static(b"smessage")),
+            RespValue::BulkString(Bytes::copy_from_slice(channel.as_bytes())),
+            RespValue::BulkString(message.clone()),
+        ]);
+
+        let mut slow = vec![];
+        let received = deliver_all(subscribers, frame, &mut slow);
+
+        for id in slow {
+            registry.remove(id);
+        }
+
+        received
+    }
+
+    /// Returns the channels of a kind with at least one subscriber, optionally
+    /// filtered by a glob-style pattern.
+    pub(crate) fn channels(&self, kind: Kind, pattern: Option<&str>) -> Vec<String> {
+        let mut registry = self.registry.lock().unwrap();
+
+        registry
+            .subscriptions(kind)
+            .keys()
+            .filter(|channel| {
+                pattern
+                    .is_none_or(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes(), false))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub(crate) fn numsub(&self, kind: Kind, channel: &str) -> usize {
+        let mut registry = self.registry.lock().unwrap();
+
+        registry
+            .subscriptions(kind)
+            .get(channel)
+            .map_or(0, HashMap::len)
+    }
+
+    /// Returns the number of patterns clients are subscribed to.
+    pub(crate) fn numpat(&self) -> usize {
+        let registry = self.registry.lock().unwrap();
+
+        registry.patterns.len()
+    }
+}
+
+/// Per-connection pub/sub state. The connection is in subscribed mode while it
+/// is subscribed to at least one channel, pattern or shard channel.
+#[derive(Debug)]
+pub(crate) struct Subscriptions {
+    id: u64,
+    subscriber: Subscriber,
+    receiver: mpsc::Receiver<RespValue>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    shard_channels: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    pub(crate) fn new(pubsub: &PubSub) -> Self {
+        let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE);
+
+        Self {
+            id: pubsub.next_id.fetch_add(1, Ordering::Relaxed),
+            subscriber: Subscriber {
+                sender,
+                overflow: Arc::new(Notify::new()),
+            },
+            receiver,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            shard_channels: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn is_subscribed(&self) -> bool {
+        self.count(Kind::Channel) + self.count(Kind::Shard) > 0
+    }
+
+    /// Number of subscriptions reported in confirmations. Channels and patterns
+    /// are counted together, shard channels separately.
+    fn count(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Channel | Kind::Pattern => self.channels.len() + self.patterns.len(),
+            Kind::Shard => self.shard_channels.len(),
+        }
+    }
+
+    fn subscribed(&mut self, kind: Kind) -> &mut BTreeSet<String> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// Subscribes to channels of a kind. Returns one confirmation per channel.
+    pub(crate) fn subscribe(
+        &mut self,
+        pubsub: &PubSub,
+        channels: &[String],
+        kind: Kind,
+    ) -> Vec<RespValue> {
+        let mut registry = pubsub.registry.lock().unwrap();
+
+        channels
+            .iter()
+            .map(|channel| {
+                registry
+                    .subscriptions(kind)
+                    .entry(channel.clone())
+                    .or_default()
+                    .insert(self.id, self.subscriber.clone());
+
+                self.subscribed(kind).insert(channel.clone());
+
+                self.confirmation(kind.subscribe(), Some(channel), kind)
+            })
+            .collect()
+    }
+
+    /// Unsubscribes from channels of a kind, no channels means all of them.
+    /// Returns one confirmation per channel.
+    pub(crate) fn unsubscribe(
+        &mut self,
+        pubsub: &PubSub,
+        channels: &[String],
+        kind: Kind,
+    ) -> Vec<RespValue> {
+        let mut registry = pubsub.registry.lock().unwrap();
+
+        let channels = match channels {
+            [] => self.subscribed(kind).iter().cloned().collect(),
+            channels => channels.to_vec(),
+        };
+
+        if channels.is_empty() {
+            return vec![self.confirmation(kind.unsubscribe(), None, kind)];
+        }
+
+        channels
+            .iter()
+            .map(|channel| {
+                let subscriptions = registry.subscriptions(kind);
+
+                if let Some(subscribers) = subscriptions.get_mut(channel) {
+                    subscribers.remove(&self.id);
+
+                    if subscribers.is_empty() {
+                        subscriptions.remove(channel);
+                    }
+                }
+
+                self.subscribed(kind).remove(channel);
+
+                self.confirmation(kind.unsubscribe(), Some(channel), kind)
+            })
+            .collect()
+    }
+
+    /// Removes all the subscriptions, called once the connection is closed.
+    pub(crate) fn clear(&mut self, pubsub: &PubSub) {
+        if !self.is_subscribed() {
+            return;
+        }
+
+        pubsub.registry.lock().unwrap().remove(self.id);
+
+        self.channels.clear();
+        self.patterns.clear();
+        self.shard_channels.clear();
+    }
+
+    /// Waits for the next message published to the subscriptions. Returns `None`
+    /// once the client fell too far behind and has to be disconnected.
+    pub(crate) async fn next_message(&mut self) -> Option<RespValue> {
+        tokio::select! {
+            biased;
+            _ = self.subscriber.overflow.notified() => None,
+            message = self.receiver.recv() => message,
+        }
+    }
+
+    fn confirmation(&self, name: &str, channel: Option<&String>, kind: Kind) -> RespValue {
+        RespValue::Push(vec![
+            RespValue::BulkString(Bytes::copy_from_slice(name.as_bytes())),
+            match channel {
+                Some(channel) => RespValue::BulkString(Bytes::copy_from_slice(channel.as_bytes())),
+                None => RespValue::Null,
+            },
+            RespValue::Integer(self.count(kind) as i64),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_publish_to_channels_and_patterns() {
+        let pubsub = PubSub::new();
+        let mut first = Subscriptions::new(&pubsub);
+        let mut second = Subscriptions::new(&pubsub);
+
+        first.subscribe(&pubsub, &channels(&["news", "sport"]), Kind::Channel);
+        second.subscribe(&pubsub, &channels(&["n*"]), Kind::Pattern);
+
+        assert_eq!(pubsub.publish("news", &Bytes::from("hello")), 2);
+        assert_eq!(pubsub.publish("weather", &Bytes::from("hello")), 0);
+
+        assert_eq!(
+            first.next_message().await,
+            Some(RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"message")),
+                RespValue::BulkString(Bytes::from_static(b"news")),
+                RespValue::BulkString(Bytes::from_static(b"hello")),
+            ]))
+        );
+        assert_eq!(
+            second.next_message().await,
+            Some(RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"pmessage")),
+                RespValue::BulkString(Bytes::from_static(b"n*")),
+                RespValue::BulkString(Bytes::from_static(b"news")),
+                RespValue::BulkString(Bytes::from_static(b"hello")),
+            ]))
+        );
+
+        assert_eq!(pubsub.numsub(Kind::Channel, "news"), 1);
+        assert_eq!(pubsub.numpat(), 1);
+        assert_eq!(
+            pubsub.channels(Kind::Channel, Some("s*")),
+            channels(&["sport"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_all() {
+        let pubsub = PubSub::new();
+        let mut subscriptions = Subscriptions::new(&pubsub);
+
+        subscriptions.subscribe(&pubsub, &channels(&["a", "b"]), Kind::Channel);
+
+        let replies = subscriptions.unsubscribe(&pubsub, &[], Kind::Channel);
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(
+            replies[1],
+            RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"unsubscribe")),
+                RespValue::BulkString(Bytes::from_static(b"b")),
+                RespValue::Integer(0),
+            ])
+        );
+        assert!(!subscriptions.is_subscribed());
+        assert!(pubsub.channels(Kind::Channel, None).is_empty());
+
+        let replies = subscriptions.unsubscribe(&pubsub, &[], Kind::Pattern);
+        assert_eq!(
+            replies,
+            vec![RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"punsubscribe")),
+                RespValue::Null,
+                RespValue::Integer(0),
+            ])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shard_channels_have_their_own_registry() {
+        let pubsub = PubSub::new();
+        let mut subscriptions = Subscriptions::new(&pubsub);
+
+        subscriptions.subscribe(&pubsub, &channels(&["*"]), Kind::Pattern);
+        let replies = subscriptions.subscribe(&pubsub, &channels(&["orders"]), Kind::Shard);
+
+        assert_eq!(
+            replies,
+            vec![RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"ssubscribe")),
+                RespValue::BulkString(Bytes::from_static(b"orders")),
+                RespValue::Integer(1),
+            ])]
+        );
+
+        assert_eq!(pubsub.spublish("orders", &Bytes::from("hello")), 1);
+        assert_eq!(
+            subscriptions.next_message().await,
+            Some(RespValue::Push(vec![
+                RespValue::BulkString(Bytes::from_static(b"smessage")),
+                RespValue::BulkString(Bytes::from_static(b"orders")),
+                RespValue::BulkString(Bytes::from_static(b"hello")),
+            ]))
+        );
+
+        assert!(pubsub.channels(Kind::Channel, None).is_empty());
+        assert_eq!(pubsub.channels(Kind::Shard, None), channels(&["orders"]));
+        assert_eq!(pubsub.numsub(Kind::Shard, "orders"), 1);
+
+        subscriptions.unsubscribe(&pubsub, &[], Kind::Pattern);
+        assert!(subscriptions.is_subscribed());
+
+        subscriptions.unsubscribe(&pubsub, &[], Kind::Shard);
+        assert!(!subscriptions.is_subscribed());
+        assert_eq!(pubsub.spublish("orders", &Bytes::from("hello")), 0);
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber_is_dropped() {
+        let pubsub = PubSub::new();
+        let mut subscriptions = Subscriptions::new(&pubsub);
+
+        subscriptions.subscribe(&pubsub, &channels(&["news"]), Kind::Channel);
+
+        for _ in 0..SUBSCRIBER_BUFFER_SIZE {
+            assert_eq!(pubsub.publish("news", &Bytes::from("hello")), 1);
+        }
+
+        // the publisher doesn't wait for the full subscriber
+        assert_eq!(pubsub.publish("news", &Bytes::from("hello")), 0);
+        assert_eq!(pubsub.numsub(Kind::Channel, "news"), 0);
+        assert_eq!(subscriptions.next_message().await, None);
+    }
+}
@@ -0,0 +1,46 @@
+/// Reflected Jones polynomial used by Redis for the RDB checksum
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = table();
+
+const fn table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+/// CRC-64/Jones checksum of `data`, as stored at the end of an RDB file.
+pub(crate) fn crc64(data: &[u8]) -> u64 {
+    data.iter().fold(0, |crc, byte| {
+        TABLE[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(crc64(b""), 0);
+    }
+}
@@ -0,0 +1,404 @@
+//! Decoders of the compact encodings that RDB files store as a single string:
+//! ziplists, listpacks, intsets and zipmaps.
+
+use bytes::Bytes;
+
+use super::RDBParsingError;
+
+fn invalid(encoding: &str) -> RDBParsingError {
+    RDBParsingError::InvalidRDBFile(format!("invalid {}", encoding))
+}
+
+/// Reads a compact encoding front to back.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    encoding: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], encoding: &'static str) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            encoding,
+        }
+    }
+
+    fn read_n(&mut self, n: usize) -> Result<&'a [u8], RDBParsingError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| invalid(self.encoding))?;
+
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, RDBParsingError> {
+        Ok(self.read_n(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, RDBParsingError> {
+        self.buf
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| invalid(self.encoding))
+    }
+
+    /// Reads a little endian signed integer of `n` bytes.
+    fn read_int(&mut self, n: usize) -> Result<i64, RDBParsingError> {
+        let bytes = self.read_n(n)?;
+
+        let mut buf = [0; 8];
+        buf[..n].copy_from_slice(bytes);
+
+        // shifting back and forth extends the sign
+        let shift = 64 - 8 * n as u32;
+        Ok(i64::from_le_bytes(buf) << shift >> shift)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, RDBParsingError> {
+        Ok(u16::from_le_bytes(self.read_n(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, RDBParsingError> {
+        Ok(u32::from_le_bytes(self.read_n(4)?.try_into().unwrap()))
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<Bytes, RDBParsingError> {
+        Ok(Bytes::copy_from_slice(self.read_n(len)?))
+    }
+}
+
+fn int(n: i64) -> Bytes {
+    Bytes::from(n.to_string())
+}
+
+/// Decodes a ziplist, the list encoding of RDB versions before 10.
+pub(super) fn ziplist(buf: &[u8]) -> Result<Vec<Bytes>, RDBParsingError> {
+    let mut reader = Reader::new(buf, "ziplist");
+
+    // total bytes, offset of the last entry and number of entries, which
+    // saturates at u16::MAX
+    reader.read_n(8)?;
+    let count = reader.read_u16()?;
+    let mut elements = vec![];
+
+    while reader.peek()? != 0xFF {
+        // length of the previous entry, to iterate backwards
+        if reader.read_u8()? == 0xFE {
+            reader.read_n(4)?;
+        }
+
+        let encoding = reader.read_u8()?;
+
+        let element = match encoding >> 6 {
+            0b00 => reader.read_string((encoding & 0x3F) as usize)?,
+            0b01 => {
+                let len = ((encoding as usize & 0x3F) << 8) | reader.read_u8()? as usize;
+                reader.read_string(len)?
+            }
+            0b10 => {
+                let len = u32::from_be_bytes(reader.read_n(4)?.try_into().unwrap());
+                reader.read_string(len as usize)?
+            }
+            _ => match encoding {
+                0xC0 => int(reader.read_int(2)?),
+                0xD0 => int(reader.read_int(4)?),
+                0xE0 => int(reader.read_int(8)?),
+                0xF0 => int(reader.read_int(3)?),
+                0xFE => int(reader.read_int(1)?),
+                // the value from 1 to 13 is stored in the encoding, minus one
+                0xF1..=0xFD => int((encoding & 0x0F) as i64 - 1),
+                _ => return Err(invalid("ziplist")),
+            },
+        };
+
+        elements.push(element);
+    }
+
+    if count != u16::MAX && count as usize != elements.len() {
+        return Err(invalid("ziplist"));
+    }
+
+    Ok(elements)
+}
+
+/// Decodes a listpack, the compact encoding of collections since RDB version 10.
+pub(super) fn listpack(buf: &[u8]) -> Result<Vec<Bytes>, RDBParsingError> {
+    let mut reader = Reader::new(buf, "listpack");
+
+    // total bytes and number of elements, which saturates at u16::MAX
+    reader.read_n(4)?;
+    let mut elements = Vec::with_capacity(reader.read_int(2)? as u16 as usize);
+
+    while reader.peek()? != 0xFF {
+        let start = reader.pos;
+        let encoding = reader.read_u8()?;
+
+        let element = if encoding >> 7 == 0 {
+            int(encoding as i64)
+        } else if encoding >> 6 == 0b10 {
+            reader.read_string((encoding & 0x3F) as usize)?
+        } else if encoding >> 5 == 0b110 {
+            let n = ((encoding as i64 & 0x1F) << 8) | reader.read_u8()? as i64;
+            // 13 bit two's complement
+            int(n << 51 >> 51)
+        } else if encoding >> 4 == 0b1110 {
+            let len = ((encoding as usize & 0x0F) << 8) | reader.read_u8()? as usize;
+            reader.read_string(len)?
+        } else {
+            match encoding {
+                0xF0 => {
+                    let len = reader.read_u32()?;
+                    reader.read_string(len as usize)?
+                }
+                0xF1 => int(reader.read_int(2)?),
+                0xF2 => int(reader.read_int(3)?),
+                0xF3 => int(reader.read_int(4)?),
+                0xF4 => int(reader.read_int(8)?),
+                _ => return Err(invalid("listpack")),
+            }
+        };
+
+        // each entry ends with its own length, to iterate backwards
+        let len = reader.pos - start;
+        let backlen = match len {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        reader.read_n(backlen)?;
+
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+/// Decodes an intset, a sorted array of integers of the same width.
+pub(super) fn intset(buf: &[u8]) -> Result<Vec<Bytes>, RDBParsingError> {
+    let mut reader = Reader::new(buf, "intset");
+
+    let width = reader.read_u32()? as usize;
+    let len = reader.read_u32()? as usize;
+
+    if !matches!(width, 2 | 4 | 8) || buf.len() != 8 + width * len {
+        return Err(invalid("intset"));
+    }
+
+    (0..len).map(|_| Ok(int(reader.read_int(width)?))).collect()
+}
+
+/// Decodes a zipmap, the hash encoding of RDB versions before 4, into fields
+/// and values alternating.
+pub(super) fn zipmap(buf: &[u8]) -> Result<Vec<Bytes>, RDBParsingError> {
+    let mut reader = Reader::new(buf, "zipmap");
+
+    let read_len = |reader: &mut Reader| -> Result<usize, RDBParsingError> {
+        match reader.read_u8()? {
+            len @ 0..=253 => Ok(len as usize),
+            254 => Ok(reader.read_u32()? as usize),
+            _ => Err(invalid("zipmap")),
+        }
+    };
+
+    // number of entries, unreliable past 253
+    reader.read_u8()?;
+    let mut elements = vec![];
+
+    while reader.peek()? != 0xFF {
+        let len = read_len(&mut reader)?;
+        elements.push(reader.read_string(len)?);
+
+        let len = read_len(&mut reader)?;
+        // unused bytes left after the value when it was updated in place
+        let free = reader.read_u8()? as usize;
+        elements.push(reader.read_string(len)?);
+        reader.read_n(free)?;
+    }
+
+    Ok(elements)
+}
+
+/// Groups elements two by two, such as the fields and values of a hash.
+pub(super) fn pairs(elements: Vec<Bytes>) -> Result<Vec<(Bytes, Bytes)>, RDBParsingError> {
+    if !elements.len().is_multiple_of(2) {
+        return Err(RDBParsingError::InvalidRDBFile(
+            "odd number of elements".into(),
+        ));
+    }
+
+    let mut elements = elements.into_iter();
+    let mut pairs = vec![];
+
+    while let (Some(a), Some(b)) = (elements.next(), elements.next()) {
+        pairs.push((a, b));
+    }
+
+    Ok(pairs)
+}
+
+/// Entries of a stream with their id as milliseconds and sequence number
+pub type StreamEntries = Vec<((u64, u64), Vec<(Bytes, Bytes)>)>;
+
+/// Stream entry flag of a deleted entry
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+/// Stream entry flag of an entry with the same fields as the master entry
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+/// Decodes a node of a stream, a listpack of entries with ids relative to the
+/// `master` id the node is stored with. Deleted entries are skipped.
+pub(super) fn stream_node(
+    master: (u64, u64),
+    buf: &[u8],
+) -> Result<StreamEntries, RDBParsingError> {
+    let mut elements = listpack(buf)?.into_iter();
+
+    let mut next = || elements.next().ok_or_else(|| invalid("stream node"));
+    let int = |element: Bytes| -> Result<i64, RDBParsingError> {
+        std::str::from_utf8(&element)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid("stream node"))
+    };
+
+    // the master entry holds the number of live and deleted entries and the
+    // fields shared by the entries
+    let count = int(next()?)?
+        .checked_add(int(next()?)?)
+        .ok_or_else(|| invalid("stream node"))?;
+    let master_fields = (0..int(next()?)?)
+        .map(|_| next())
+        .collect::<Result<Vec<_>, _>>()?;
+    next()?;
+
+    let mut entries = vec![];
+
+    for _ in 0..count {
+        let flags = int(next()?)?;
+        let millis = master.0.wrapping_add(int(next()?)? as u64);
+        let seq = master.1.wrapping_add(int(next()?)? as u64);
+
+        let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone(), next()?)))
+                .collect::<Result<Vec<_>, RDBParsingError>>()?
+        } else {
+            (0..int(next()?)?)
+                .map(|_| Ok((next()?, next()?)))
+                .collect::<Result<Vec<_>, RDBParsingError>>()?
+        };
+
+        // number of elements of the entry, to iterate backwards
+        next()?;
+
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.push(((millis, seq), fields));
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(elements: Vec<Bytes>) -> Vec<String> {
+        elements
+            .iter()
+            .map(|e| String::from_utf8_lossy(e).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_ziplist() {
+        let mut buf = vec![0; 8];
+        buf.extend([4, 0]);
+        // "ab", 12, -2 as int16 and 1 000 000 as int32
+        buf.extend([0, 0x02, b'a', b'b']);
+        buf.extend([4, 0xFD]);
+        buf.extend([2, 0xC0, 0xFE, 0xFF]);
+        buf.extend([4, 0xD0, 0x40, 0x42, 0x0F, 0x00]);
+        buf.push(0xFF);
+
+        assert_eq!(
+            strings(ziplist(&buf).unwrap()),
+            ["ab", "12", "-2", "1000000"]
+        );
+        assert!(ziplist(&buf[..buf.len() - 1]).is_err());
+
+        // the number of entries saturates, so u16::MAX is not checked
+        buf[8..10].copy_from_slice(&[0xFF, 0xFF]);
+        assert_eq!(ziplist(&buf).unwrap().len(), 4);
+
+        buf[8..10].copy_from_slice(&[3, 0]);
+        assert!(ziplist(&buf).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_ziplist() {
+        let mut buf = vec![0; 8];
+        buf.extend([1, 0]);
+        // a string claiming 4GB
+        buf.extend([0, 0x80, 0xFF, 0xFF, 0xFF, 0xFF, b'a']);
+        buf.push(0xFF);
+        assert!(ziplist(&buf).is_err());
+
+        // an unknown integer encoding
+        buf.truncate(10);
+        buf.extend([0, 0xFF, 0xFF]);
+        assert!(ziplist(&buf).is_err());
+    }
+
+    #[test]
+    fn test_listpack() {
+        let mut buf = vec![0; 4];
+        buf.extend([5, 0]);
+        // 7, "ab", -1 as 13 bit int, 300 as int16 and a 12 bit string
+        buf.extend([0x07, 1]);
+        buf.extend([0x82, b'a', b'b', 3]);
+        buf.extend([0xDF, 0xFF, 2]);
+        buf.extend([0xF1, 0x2C, 0x01, 3]);
+        buf.extend([0xE0, 0x80]);
+        buf.extend([b'x'; 128]);
+        buf.extend([0x01, 0x82]);
+        buf.push(0xFF);
+
+        let elements = strings(listpack(&buf).unwrap());
+        assert_eq!(elements[..4], ["7", "ab", "-1", "300"]);
+        assert_eq!(elements[4], "x".repeat(128));
+    }
+
+    #[test]
+    fn test_stream_node_with_overflowing_count() {
+        let mut buf = vec![0; 4];
+        buf.extend([2, 0]);
+        // i64::MAX live entries and 1 deleted entry as int64
+        buf.push(0xF4);
+        buf.extend(i64::MAX.to_le_bytes());
+        buf.push(9);
+        buf.extend([0x01, 1]);
+        buf.push(0xFF);
+
+        assert!(stream_node((0, 0), &buf).is_err());
+    }
+
+    #[test]
+    fn test_intset_and_zipmap() {
+        let mut buf = vec![2, 0, 0, 0, 2, 0, 0, 0];
+        buf.extend([0xFF, 0xFF, 0x05, 0x00]);
+        assert_eq!(strings(intset(&buf).unwrap()), ["-1", "5"]);
+        assert!(intset(&buf[..10]).is_err());
+
+        // "f" => "v1" followed by a free byte
+        let buf = [1, 1, b'f', 2, 1, b'v', b'1', 0, 0xFF];
+        let pairs = pairs(zipmap(&buf).unwrap()).unwrap();
+        assert_eq!(pairs, [(Bytes::from("f"), Bytes::from("v1"))]);
+    }
+}
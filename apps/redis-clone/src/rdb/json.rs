@@ -0,0 +1,268 @@
+//! Conversion of keys to and from JSON, used by the `rdb-json` binary to export
+//! and import datasets as JSON Lines, and by the tests to load readable fixtures.
+//!
+//! Each line holds a key as an object:
+//!
+//! ```json
+//! {"db":0,"key":"greeting","type":"string","value":"hello","expires_at":1700000000000}
+//! ```
+//!
+//! - `db` defaults to 0, and `expires_at`, a UNIX time in milliseconds, to no
+//!   expiry
+//! - strings are JSON strings, or `{"hex":"ff00"}` when they aren't UTF-8
+//! - the value depends on `type`:
+//!   - `string`: a string
+//!   - `list` and `set`: an array of strings
+//!   - `zset`: an array of `[member, score]`, with the scores that JSON can't
+//!     represent written as `"inf"`, `"-inf"` and `"nan"`
+//!   - `hash`: an array of `[field, value]`
+//!   - `stream`: `{"last_id":"5-1","entries":[{"id":"5-1","fields":[["f","v"]]}]}`
+
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+
+use super::{RdbEntry, RdbValue};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JsonError {
+    #[error("Invalid JSON: {0}")]
+    Syntax(#[from] serde_json::Error),
+    #[error("Invalid {0}")]
+    Invalid(&'static str),
+}
+
+/// Converts a key to its JSON object.
+pub fn to_json(entry: &RdbEntry) -> Value {
+    let (value_type, value) = match &entry.value {
+        RdbValue::String(data) => ("string", string(data)),
+        RdbValue::List(elements) => ("list", elements.iter().map(|e| string(e)).collect()),
+        RdbValue::Set(members) => ("set", members.iter().map(|m| string(m)).collect()),
+        RdbValue::SortedSet(members) => (
+            "zset",
+            members
+                .iter()
+                .map(|(member, score)| json!([string(member), self::score(*score)]))
+                .collect(),
+        ),
+        RdbValue::Hash(pairs) => ("hash", pairs.iter().map(|(f, v)| pair(f, v)).collect()),
+        RdbValue::Stream { entries, last_id } => {
+            let entries: Vec<_> = entries
+                .iter()
+                .map(|(id, fields)| {
+                    let fields: Vec<_> = fields.iter().map(|(f, v)| pair(f, v)).collect();
+                    json!({ "id": stream_id(*id), "fields": fields })
+                })
+                .collect();
+
+            (
+                "stream",
+                json!({ "last_id": stream_id(*last_id), "entries": entries }),
+            )
+        }
+    };
+
+    let mut object = Map::new();
+    object.insert("db".into(), entry.db.into());
+    object.insert("key".into(), string(&entry.key));
+    object.insert("type".into(), value_type.into());
+    object.insert("value".into(), value);
+
+    if let Some(expires_at) = entry.expires_at {
+        object.insert("expires_at".into(), expires_at.into());
+    }
+
+    Value::Object(object)
+}
+
+/// Parses a key from a line of JSON.
+pub fn parse(line: &str) -> Result<RdbEntry, JsonError> {
+    from_json(&serde_json::from_str(line)?)
+}
+
+/// Converts a JSON object back to a key.
+pub fn from_json(json: &Value) -> Result<RdbEntry, JsonError> {
+    let db = match json.get("db") {
+        None => 0,
+        Some(db) => db.as_u64().ok_or(JsonError::Invalid("db"))? as usize,
+    };
+
+    let expires_at = match json.get("expires_at") {
+        None | Some(Value::Null) => None,
+        Some(expires_at) => Some(expires_at.as_u64().ok_or(JsonError::Invalid("expiry"))?),
+    };
+
+    let key = parse_string(json.get("key").ok_or(JsonError::Invalid("key"))?)?;
+    let value = json.get("value").ok_or(JsonError::Invalid("value"))?;
+
+    let value = match json.get("type").and_then(Value::as_str) {
+        Some("string") => RdbValue::String(parse_string(value)?),
+        Some("list") => RdbValue::List(parse_strings(value)?),
+        Some("set") => RdbValue::Set(parse_strings(value)?),
+        Some("zset") => RdbValue::SortedSet(
+            array(value)?
+                .iter()
+                .map(|member| match member.as_array().map(Vec::as_slice) {
+                    Some([member, score]) => Ok((parse_string(member)?, parse_score(score)?)),
+                    _ => Err(JsonError::Invalid("sorted set member")),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        Some("hash") => RdbValue::Hash(parse_pairs(value)?),
+        Some("stream") => {
+            let entries = array(value.get("entries").ok_or(JsonError::Invalid("stream"))?)?
+                .iter()
+                .map(|entry| {
+                    let id = parse_stream_id(entry.get("id"))?;
+                    let fields = entry.get("fields").ok_or(JsonError::Invalid("stream"))?;
+                    Ok((id, parse_pairs(fields)?))
+                })
+                .collect::<Result<_, JsonError>>()?;
+
+            RdbValue::Stream {
+                entries,
+                last_id: parse_stream_id(value.get("last_id"))?,
+            }
+        }
+        _ => return Err(JsonError::Invalid("type")),
+    };
+
+    Ok(RdbEntry {
+        db,
+        key,
+        value,
+        expires_at,
+        encoding: "",
+        size: 0,
+    })
+}
+
+fn string(data: &[u8]) -> Value {
+    match std::str::from_utf8(data) {
+        Ok(s) => s.into(),
+        Err(_) => {
+            let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+            json!({ "hex": hex })
+        }
+    }
+}
+
+fn parse_string(json: &Value) -> Result<Bytes, JsonError> {
+    if let Some(s) = json.as_str() {
+        return Ok(Bytes::copy_from_slice(s.as_bytes()));
+    }
+
+    let hex = json
+        .get("hex")
+        .and_then(Value::as_str)
+        .filter(|hex| hex.len().is_multiple_of(2))
+        .ok_or(JsonError::Invalid("string"))?;
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(JsonError::Invalid("hex string"))
+        })
+        .collect()
+}
+
+fn array(json: &Value) -> Result<&Vec<Value>, JsonError> {
+    json.as_array().ok_or(JsonError::Invalid("array"))
+}
+
+fn parse_strings(json: &Value) -> Result<Vec<Bytes>, JsonError> {
+    array(json)?.iter().map(parse_string).collect()
+}
+
+fn pair(a: &[u8], b: &[u8]) -> Value {
+    json!([string(a), string(b)])
+}
+
+fn parse_pairs(json: &Value) -> Result<Vec<(Bytes, Bytes)>, JsonError> {
+    array(json)?
+        .iter()
+        .map(|pair| match pair.as_array().map(Vec::as_slice) {
+            Some([a, b]) => Ok((parse_string(a)?, parse_string(b)?)),
+            _ => Err(JsonError::Invalid("pair")),
+        })
+        .collect()
+}
+
+fn score(score: f64) -> Value {
+    if score.is_nan() {
+        "nan".into()
+    } else if score.is_infinite() {
+        if score > 0.0 { "inf" } else { "-inf" }.into()
+    } else {
+        score.into()
+    }
+}
+
+fn parse_score(json: &Value) -> Result<f64, JsonError> {
+    match json {
+        Value::Number(score) => score.as_f64(),
+        Value::String(score) => match score.as_str() {
+            "inf" => Some(f64::INFINITY),
+            "-inf" => Some(f64::NEG_INFINITY),
+            "nan" => Some(f64::NAN),
+            _ => None,
+        },
+        _ => None,
+    }
+    .ok_or(JsonError::Invalid("score"))
+}
+
+fn stream_id((millis, seq): (u64, u64)) -> String {
+    format!("{}-{}", millis, seq)
+}
+
+fn parse_stream_id(json: Option<&Value>) -> Result<(u64, u64), JsonError> {
+    json.and_then(Value::as_str)
+        .and_then(|id| id.split_once('-'))
+        .and_then(|(millis, seq)| Some((millis.parse().ok()?, seq.parse().ok()?)))
+        .ok_or(JsonError::Invalid("stream id"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::super::writer::RDBWriter;
+    use super::super::RDBParser;
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../fixtures/keyspace.jsonl");
+
+    #[tokio::test]
+    async fn test_fixture_round_trips_through_rdb() {
+        let keys: Vec<_> = FIXTURE.lines().map(|line| parse(line).unwrap()).collect();
+
+        let mut writer = RDBWriter::new();
+        for key in &keys {
+            writer.write_entry(key);
+        }
+
+        let mut parser = RDBParser::new(Cursor::new(writer.finish()));
+        parser.read_header().await.unwrap();
+
+        for key in &keys {
+            let mut entry = parser.next_entry().await.unwrap().unwrap();
+            (entry.encoding, entry.size) = ("", 0);
+
+            assert_eq!(&entry, key);
+            assert_eq!(from_json(&to_json(&entry)).unwrap(), entry);
+        }
+
+        assert!(parser.next_entry().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let line = r#"{"key":{"hex":"f"},"type":"string","value":"v"}"#;
+        assert!(matches!(parse(line), Err(JsonError::Invalid("string"))));
+        let line = r#"{"key":"k","type":"zset","value":[["m"]]}"#;
+        assert!(matches!(parse(line), Err(JsonError::Invalid(_))));
+        assert!(matches!(parse("{"), Err(JsonError::Syntax(_))));
+    }
+}
@@ -0,0 +1,83 @@
+use super::RDBParsingError;
+
+/// Decompresses LZF data into a buffer of the `len` bytes it was compressed from.
+pub(super) fn decompress(input: &[u8], len: usize) -> Result<Vec<u8>, RDBParsingError> {
+    let invalid = || RDBParsingError::InvalidRDBFile("invalid LZF data".into());
+
+    // `len` comes from the file, so the buffer grows as data is decompressed
+    let mut output = Vec::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+
+        if ctrl < 32 {
+            // literal run of ctrl + 1 bytes
+            let literal = input.get(i..i + ctrl + 1).ok_or_else(invalid)?;
+            if output.len() + literal.len() > len {
+                return Err(invalid());
+            }
+            output.extend_from_slice(literal);
+            i += ctrl + 1;
+            continue;
+        }
+
+        // back reference, the length is stored minus 2 with 7 meaning another byte
+        let mut run = ctrl >> 5;
+        if run == 7 {
+            run += *input.get(i).ok_or_else(invalid)? as usize;
+            i += 1;
+        }
+
+        let offset = ((ctrl & 0x1f) << 8) + *input.get(i).ok_or_else(invalid)? as usize + 1;
+        i += 1;
+
+        let start = output.len().checked_sub(offset).ok_or_else(invalid)?;
+        if output.len() + run + 2 > len {
+            return Err(invalid());
+        }
+
+        // the reference may overlap the bytes being copied
+        for j in start..start + run + 2 {
+            output.push(output[j]);
+        }
+    }
+
+    if output.len() != len {
+        return Err(invalid());
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        // a literal "a", 19 bytes copied from 1 byte back and a literal "bc"
+        let compressed = [0x00, b'a', 0xe0, 0x0a, 0x00, 0x01, b'b', b'c'];
+
+        assert_eq!(
+            decompress(&compressed, 22).unwrap(),
+            b"aaaaaaaaaaaaaaaaaaaabc"
+        );
+
+        assert!(decompress(&compressed, 23).is_err());
+        assert!(decompress(&[0x20, 0x05], 3).is_err());
+    }
+
+    #[test]
+    fn test_decompress_corrupt_lengths() {
+        let compressed = [0x00, b'a', 0xe0, 0x0a, 0x00, 0x01, b'b', b'c'];
+
+        // a length larger than the data can expand to doesn't allocate it
+        assert!(decompress(&compressed, usize::MAX).is_err());
+
+        // the literal or the back reference goes past the declared length
+        assert!(decompress(&compressed, 0).is_err());
+        assert!(decompress(&compressed, 10).is_err());
+    }
+}
@@ -0,0 +1,648 @@
+//! Parser of RDB files. It lives in the library shared by the server and the
+//! `redis-check-rdb` and `rdb-json` binaries. Warnings go to stderr to keep
+//! them out of the output of the binaries.
+
+use std::io::Cursor;
+
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+
+use self::crc64::crc64;
+pub use self::encodings::StreamEntries;
+
+mod crc64;
+mod encodings;
+pub mod json;
+mod lzf;
+pub mod writer;
+
+/// Latest version of the format that can be loaded, written by Redis 7.4
+const RDB_VERSION: u32 = 12;
+
+/// https://rdb.fnordig.de/file_format.html
+pub struct RDBParser {
+    cursor: Cursor<Vec<u8>>,
+    version: u32,
+    /// Database of the last selector
+    db: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RDBParsingError {
+    #[error("Invalid RDB header, expected REDIS")]
+    InvalidHeader,
+    #[error("Unsupported RDB version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid opcode")]
+    InvalidOpcode,
+    #[error("Invalid RDB file: {0}")]
+    InvalidRDBFile(String),
+    #[error("Wrong RDB checksum, expected {expected:#x} got {actual:#x}")]
+    InvalidChecksum { expected: u64, actual: u64 },
+    #[error("Unimplemented: {0}")]
+    Unimplemented(&'static str),
+}
+
+/// Value of a key as stored in an RDB file, whatever its on-disk encoding
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdbValue {
+    String(Bytes),
+    List(Vec<Bytes>),
+    Set(Vec<Bytes>),
+    SortedSet(Vec<(Bytes, f64)>),
+    /// Fields and values
+    Hash(Vec<(Bytes, Bytes)>),
+    Stream {
+        entries: StreamEntries,
+        last_id: (u64, u64),
+    },
+}
+
+/// Key read from an RDB file
+#[derive(Debug, Clone, PartialEq)]
+pub struct RdbEntry {
+    pub db: usize,
+    pub key: Bytes,
+    pub value: RdbValue,
+    /// Absolute expiration time as UNIX time in milliseconds
+    pub expires_at: Option<u64>,
+    /// Encoding of the value in the file, only used by `redis-check-rdb`, empty
+    /// for keys that weren't read from a file
+    pub encoding: &'static str,
+    /// Bytes taken by the key and its value in the file, or 0
+    pub size: u64,
+}
+
+impl RDBParser {
+    pub fn new(cursor: Cursor<Vec<u8>>) -> Self {
+        Self {
+            cursor,
+            version: 0,
+            db: 0,
+        }
+    }
+
+    /// Checks the magic string and returns the version of the file.
+    pub async fn read_header(&mut self) -> Result<u32, RDBParsingError> {
+        let header = self.read_n(5).await?;
+
+        if header != *b"REDIS" {
+            return Err(RDBParsingError::InvalidHeader);
+        }
+
+        let version = self.read_n(4).await?;
+        let version = std::str::from_utf8(&version)
+            .ok()
+            .and_then(|version| version.parse().ok())
+            .ok_or(RDBParsingError::InvalidHeader)?;
+
+        if !(1..=RDB_VERSION).contains(&version) {
+            return Err(RDBParsingError::UnsupportedVersion(version));
+        }
+
+        self.version = version;
+
+        Ok(version)
+    }
+
+    /// Reads the next key, or `None` at the end of the file once the checksum
+    /// is verified.
+    pub async fn next_entry(&mut self) -> Result<Option<RdbEntry>, RDBParsingError> {
+        let mut expires_at = None;
+
+        loop {
+            let opcode = self.read_n(1).await?[0];
+
+            match opcode {
+                // # Auxiliary field, containing a db metadata, such as version, creation time, etc.
+                0xFA => {
+                    self.read_string().await?;
+                    self.read_string().await?;
+                }
+                // # Database selector
+                0xFE => {
+                    self.db = self.read_length().await? as usize;
+                }
+                // # Resize DB field, the sizes of the keyspace and of the expires
+                0xFB => {
+                    self.read_length().await?;
+                    self.read_length().await?;
+                }
+                // # Expiry time field, followed by the key it applies to
+                0xFC | 0xFD => {
+                    expires_at = Some(self.read_expiry(opcode).await?);
+                }
+                // # LRU idle time and LFU frequency of the next key
+                0xF8 => {
+                    self.read_length().await?;
+                }
+                0xF9 => {
+                    self.read_n(1).await?;
+                }
+                // # Cluster slot info, the slot and the sizes of its keyspace and expires
+                0xF4 => {
+                    for _ in 0..3 {
+                        self.read_length().await?;
+                    }
+                }
+                // # Function library, functions aren't supported
+                0xF5 => {
+                    self.read_string().await?;
+                    eprintln!("Skipping function library in RDB file");
+                }
+                0xF6 => {
+                    return Err(RDBParsingError::Unimplemented("pre-GA function format"));
+                }
+                // # Module auxiliary data, the module id, when it was saved and its values
+                0xF7 => {
+                    let module = self.read_length().await?;
+                    self.read_length().await?;
+                    self.read_length().await?;
+                    self.skip_module_value().await?;
+                    eprintln!("Skipping auxiliary data of module {:#x}", module);
+                }
+                // # End of RDB file
+                0xFF => {
+                    self.verify_checksum().await?;
+                    return Ok(None);
+                }
+                // # Key-Value pair
+                value_type => {
+                    let start = self.cursor.position() - 1;
+                    let key = self.read_string().await?;
+
+                    let Some(value) = self.read_value(value_type).await? else {
+                        eprintln!("Skipping module value of key {:?}", key);
+                        expires_at = None;
+                        continue;
+                    };
+
+                    return Ok(Some(RdbEntry {
+                        db: self.db,
+                        key,
+                        value,
+                        expires_at,
+                        encoding: encoding(value_type),
+                        size: self.cursor.position() - start,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Checks the CRC64 of the file, written since version 5 and left to 0 when
+    /// checksums are disabled.
+    async fn verify_checksum(&mut self) -> Result<(), RDBParsingError> {
+        if self.version < 5 {
+            return Ok(());
+        }
+
+        let end = self.cursor.position() as usize;
+        let actual = crc64(&self.cursor.get_ref()[..end]);
+        let expected = u64::from_le_bytes(self.read_n(8).await?.try_into().unwrap());
+
+        if expected != 0 && expected != actual {
+            return Err(RDBParsingError::InvalidChecksum { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    /// Reads a value of type `value_type`, or `None` for module values which
+    /// are skipped.
+    async fn read_value(&mut self, value_type: u8) -> Result<Option<RdbValue>, RDBParsingError> {
+        let value = match value_type {
+            0 => RdbValue::String(self.read_string().await?),
+            1 => RdbValue::List(self.read_strings(1).await?),
+            2 => RdbValue::Set(self.read_strings(1).await?),
+            // sorted set with scores as strings, then as binary doubles
+            3 | 5 => {
+                let len = self.read_length().await?;
+                let mut members = vec![];
+
+                for _ in 0..len {
+                    let member = self.read_string().await?;
+                    let score = match value_type {
+                        3 => self.read_string_double().await?,
+                        _ => f64::from_le_bytes(self.read_n(8).await?.try_into().unwrap()),
+                    };
+
+                    members.push((member, score));
+                }
+
+                RdbValue::SortedSet(members)
+            }
+            4 => RdbValue::Hash(encodings::pairs(self.read_strings(2).await?)?),
+            6 => return Err(RDBParsingError::Unimplemented("module values of version 1")),
+            7 => {
+                self.read_length().await?;
+                self.skip_module_value().await?;
+                return Ok(None);
+            }
+            9 => RdbValue::Hash(encodings::pairs(encodings::zipmap(
+                &self.read_string().await?,
+            )?)?),
+            10 => RdbValue::List(encodings::ziplist(&self.read_string().await?)?),
+            11 => RdbValue::Set(encodings::intset(&self.read_string().await?)?),
+            12 => RdbValue::SortedSet(scores(encodings::ziplist(&self.read_string().await?)?)?),
+            13 => RdbValue::Hash(encodings::pairs(encodings::ziplist(
+                &self.read_string().await?,
+            )?)?),
+            // quicklist of ziplists
+            14 => {
+                let mut elements = vec![];
+
+                for _ in 0..self.read_length().await? {
+                    elements.extend(encodings::ziplist(&self.read_string().await?)?);
+                }
+
+                RdbValue::List(elements)
+            }
+            // streams, the versions add the fields of consumer groups
+            15 | 19 | 21 => self.read_stream(value_type).await?,
+            16 => RdbValue::Hash(encodings::pairs(encodings::listpack(
+                &self.read_string().await?,
+            )?)?),
+            17 => RdbValue::SortedSet(scores(encodings::listpack(&self.read_string().await?)?)?),
+            // quicklist of listpacks or of single large elements
+            18 => {
+                let mut elements = vec![];
+
+                for _ in 0..self.read_length().await? {
+                    match self.read_length().await? {
+                        1 => elements.push(self.read_string().await?),
+                        2 => elements.extend(encodings::listpack(&self.read_string().await?)?),
+                        _ => Err(RDBParsingError::InvalidRDBFile(
+                            "invalid quicklist container".into(),
+                        ))?,
+                    }
+                }
+
+                RdbValue::List(elements)
+            }
+            20 => RdbValue::Set(encodings::listpack(&self.read_string().await?)?),
+            22..=25 => return Err(RDBParsingError::Unimplemented("hash field expiration")),
+            _ => return Err(RDBParsingError::InvalidOpcode),
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Reads the entries of a stream and skips its consumer groups.
+    async fn read_stream(&mut self, value_type: u8) -> Result<RdbValue, RDBParsingError> {
+        let mut entries = vec![];
+
+        for _ in 0..self.read_length().await? {
+            let master = self.read_string().await?;
+
+            if master.len() != 16 {
+                return Err(RDBParsingError::InvalidRDBFile(
+                    "invalid stream node".into(),
+                ));
+            }
+
+            let millis = u64::from_be_bytes(master[..8].try_into().unwrap());
+            let seq = u64::from_be_bytes(master[8..].try_into().unwrap());
+
+            let node = self.read_string().await?;
+            entries.extend(encodings::stream_node((millis, seq), &node)?);
+        }
+
+        // number of entries and last id
+        self.read_length().await?;
+        let last_id = (self.read_length().await?, self.read_length().await?);
+
+        if value_type >= 19 {
+            // first id, max deleted id and number of entries ever added
+            for _ in 0..5 {
+                self.read_length().await?;
+            }
+        }
+
+        let groups = self.read_length().await?;
+
+        for _ in 0..groups {
+            // name and last delivered id
+            self.read_string().await?;
+            self.read_length().await?;
+            self.read_length().await?;
+
+            if value_type >= 19 {
+                // entries read
+                self.read_length().await?;
+            }
+
+            // pending entries with their delivery time and count
+            for _ in 0..self.read_length().await? {
+                self.read_n(16 + 8).await?;
+                self.read_length().await?;
+            }
+
+            for _ in 0..self.read_length().await? {
+                // name, seen time and active time
+                self.read_string().await?;
+                self.read_n(if value_type >= 21 { 16 } else { 8 }).await?;
+
+                let pending = usize::try_from(self.read_length().await?)
+                    .ok()
+                    .and_then(|pending| pending.checked_mul(16))
+                    .ok_or_else(|| {
+                        RDBParsingError::InvalidRDBFile("length overflow".to_string())
+                    })?;
+                self.read_n(pending).await?;
+            }
+        }
+
+        if groups > 0 {
+            eprintln!("Skipping {} consumer groups of stream", groups);
+        }
+
+        Ok(RdbValue::Stream { entries, last_id })
+    }
+
+    /// Skips a value saved by a module, a list of typed fields ending with 0.
+    async fn skip_module_value(&mut self) -> Result<(), RDBParsingError> {
+        loop {
+            match self.read_length().await? {
+                0 => return Ok(()),
+                // signed and unsigned integers
+                1 | 2 => {
+                    self.read_length().await?;
+                }
+                3 => {
+                    self.read_n(4).await?;
+                }
+                4 => {
+                    self.read_n(8).await?;
+                }
+                5 => {
+                    self.read_string().await?;
+                }
+                _ => Err(RDBParsingError::InvalidRDBFile(
+                    "invalid module value".into(),
+                ))?,
+            }
+        }
+    }
+
+    // 00000000  52 45 44 49 53 30 30 31  31 fa 09 72 65 64 69 73  |REDIS0011..redis|
+    // 00000010  2d 76 65 72 05 37 2e 32  2e 35 fa 0a 72 65 64 69  |-ver.7.2.5..redi|
+    // 00000020  73 2d 62 69 74 73 c0 40  fa 05 63 74 69 6d 65 c2  |s-bits.@..ctime.|
+    // 00000030  6b 6b 58 66 fa 08 75 73  65 64 2d 6d 65 6d c2 90  |kkXf..used-mem..|
+    // 00000040  f8 0d 00 fa 08 61 6f 66  2d 62 61 73 65 c0 00 fe  |.....aof-base...|
+    // 00000050  00 fb 02 01 fc 65 8c 66  c9 8f 01 00 00 00 06 68  |.....e.f.......h|
+    // 00000060  65 6c 6c 6f 32 05 77 6f  72 6c 64 00 05 68 65 6c  |ello2.world..hel|
+    // 00000070  6c 6f 05 77 6f 72 6c 64  ff 1e bf a8 14 bc 85 51  |lo.world.......Q|
+    // 00000080  96                                                |.|
+    // 00000081
+
+    async fn read_string(&mut self) -> Result<Bytes, RDBParsingError> {
+        let len = self.read_length_encoding().await?;
+
+        match len {
+            LengthEncoding::Length(n) => Ok(self.read_n(n as usize).await?.into()),
+            LengthEncoding::Format(format) => match format {
+                0..=2 => Ok(self.read_int_format(format).await?.to_string().into()),
+                // LZF compressed string
+                3 => {
+                    let compressed = self.read_length().await?;
+                    let len = self.read_length().await?;
+                    let data = self.read_n(compressed as usize).await?;
+
+                    Ok(lzf::decompress(&data, len as usize)?.into())
+                }
+                _ => Err(RDBParsingError::InvalidOpcode),
+            },
+        }
+    }
+
+    /// Reads a length, then that many strings times `per_element`.
+    async fn read_strings(&mut self, per_element: u64) -> Result<Vec<Bytes>, RDBParsingError> {
+        let len = self
+            .read_length()
+            .await?
+            .checked_mul(per_element)
+            .ok_or_else(|| RDBParsingError::InvalidRDBFile("length overflow".to_string()))?;
+        let mut strings = vec![];
+
+        for _ in 0..len {
+            strings.push(self.read_string().await?);
+        }
+
+        Ok(strings)
+    }
+
+    /// Reads a score of the first sorted set encoding, written as a string.
+    async fn read_string_double(&mut self) -> Result<f64, RDBParsingError> {
+        match self.read_n(1).await?[0] {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_double(&self.read_n(len as usize).await?),
+        }
+    }
+
+    async fn read_expiry(&mut self, opcode: u8) -> Result<u64, RDBParsingError> {
+        match opcode {
+            0xFC => {
+                let buf = self.read_n(8).await?;
+                Ok(u64::from_le_bytes(buf.try_into().unwrap()))
+            }
+            0xFD => {
+                let buf = self.read_n(4).await?;
+                Ok(u32::from_le_bytes(buf.try_into().unwrap()) as u64 * 1000)
+            }
+            _ => Err(RDBParsingError::InvalidOpcode),
+        }
+    }
+
+    async fn read_int_format(&mut self, format: u8) -> Result<i64, RDBParsingError> {
+        match format {
+            0 => Ok(i8::from_le_bytes(self.read_n(1).await?.try_into().unwrap()) as i64),
+            1 => Ok(i16::from_le_bytes(self.read_n(2).await?.try_into().unwrap()) as i64),
+            2 => Ok(i32::from_le_bytes(self.read_n(4).await?.try_into().unwrap()) as i64),
+            _ => Err(RDBParsingError::InvalidOpcode),
+        }
+    }
+
+    async fn read_n(&mut self, n: usize) -> Result<Vec<u8>, RDBParsingError> {
+        // don't trust a corrupted length with the allocation
+        let remaining = self.cursor.get_ref().len() as u64 - self.cursor.position();
+        if n as u64 > remaining {
+            return Err(RDBParsingError::InvalidRDBFile("unexpected EOF".into()));
+        }
+
+        let mut buf = vec![0; n];
+        self.cursor
+            .read_exact(&mut buf)
+            .await
+            .map_err(|e| RDBParsingError::InvalidRDBFile(e.to_string()))?;
+
+        Ok(buf)
+    }
+
+    async fn read_length(&mut self) -> Result<u64, RDBParsingError> {
+        match self.read_length_encoding().await? {
+            LengthEncoding::Length(len) => Ok(len),
+            LengthEncoding::Format(_) => {
+                Err(RDBParsingError::InvalidRDBFile("expected a length".into()))
+            }
+        }
+    }
+
+    async fn read_length_encoding(&mut self) -> Result<LengthEncoding, RDBParsingError> {
+        let buf = self.read_n(1).await?[0];
+
+        let top_bits = (buf & 0b11000000) >> 6;
+        let rest_bits = buf & 0b00111111;
+
+        match top_bits {
+            0b00 => {
+                let len = rest_bits as u64;
+                Ok(LengthEncoding::Length(len))
+            }
+            0b01 => {
+                let byte = self.read_n(1).await?[0];
+                let len = ((rest_bits as u64) << 8) + byte as u64;
+                Ok(LengthEncoding::Length(len))
+            }
+            // 32 or 64 bit big endian length
+            0b10 => match buf {
+                0x80 => {
+                    let buf = self.read_n(4).await?;
+                    Ok(LengthEncoding::Length(
+                        u32::from_be_bytes(buf.try_into().unwrap()) as u64,
+                    ))
+                }
+                0x81 => {
+                    let buf = self.read_n(8).await?;
+                    Ok(LengthEncoding::Length(u64::from_be_bytes(
+                        buf.try_into().unwrap(),
+                    )))
+                }
+                _ => Err(RDBParsingError::InvalidOpcode),
+            },
+            0b11 => Ok(LengthEncoding::Format(rest_bits)),
+            _ => Err(RDBParsingError::InvalidOpcode),
+        }
+    }
+}
+
+/// Returns the name of the encoding of a value type, as `OBJECT ENCODING` would
+/// have reported it when the file was saved.
+fn encoding(value_type: u8) -> &'static str {
+    match value_type {
+        0 => "raw",
+        1 => "linkedlist",
+        2 | 4 => "hashtable",
+        3 | 5 => "skiplist",
+        7 => "module",
+        9 => "zipmap",
+        10 | 12 | 13 => "ziplist",
+        11 => "intset",
+        14 | 18 => "quicklist",
+        15 | 19 | 21 => "stream",
+        16 | 17 | 20 => "listpack",
+        _ => "unknown",
+    }
+}
+
+#[derive(Debug)]
+enum LengthEncoding {
+    Length(u64),
+    Format(u8),
+}
+
+fn parse_double(buf: &[u8]) -> Result<f64, RDBParsingError> {
+    std::str::from_utf8(buf)
+        .ok()
+        .and_then(|score| score.parse().ok())
+        .ok_or_else(|| RDBParsingError::InvalidRDBFile("invalid score".into()))
+}
+
+/// Groups the members and scores of a compact sorted set.
+fn scores(elements: Vec<Bytes>) -> Result<Vec<(Bytes, f64)>, RDBParsingError> {
+    encodings::pairs(elements)?
+        .into_iter()
+        .map(|(member, score)| Ok((member, parse_double(&score)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(mut rdb: Vec<u8>) -> Vec<u8> {
+        rdb.push(0xFF);
+        rdb.extend(crc64(&rdb).to_le_bytes());
+        rdb
+    }
+
+    async fn entries(rdb: Vec<u8>) -> Result<Vec<RdbEntry>, RDBParsingError> {
+        let mut parser = RDBParser::new(Cursor::new(rdb));
+        parser.read_header().await?;
+
+        let mut entries = vec![];
+        while let Some(entry) = parser.next_entry().await? {
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    #[tokio::test]
+    async fn test_entry_encoding_and_size() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0xFE, 0x02, 0xFC]);
+        rdb.extend(1_000u64.to_le_bytes());
+        rdb.extend([0x00, 0x01, b'a', 0x03, b'x', b'y', b'z']);
+        // intset of a single 16 bit integer
+        rdb.extend([11, 0x01, b's', 0x0A, 2, 0, 0, 0, 1, 0, 0, 0, 7, 0]);
+
+        let entries = entries(checksum(rdb)).await.unwrap();
+        let described: Vec<_> = entries
+            .iter()
+            .map(|e| (e.db, e.encoding, e.size, e.expires_at))
+            .collect();
+
+        assert_eq!(
+            described,
+            [(2, "raw", 7, Some(1_000)), (2, "intset", 14, None)]
+        );
+        assert_eq!(entries[1].value, RdbValue::Set(vec![Bytes::from("7")]));
+    }
+
+    #[tokio::test]
+    async fn test_checksum_and_version() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0x00, 0x01, b'a', 0x01, b'0']);
+        let mut rdb = checksum(rdb);
+
+        assert_eq!(entries(rdb.clone()).await.unwrap().len(), 1);
+
+        *rdb.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            entries(rdb.clone()).await,
+            Err(RDBParsingError::InvalidChecksum { .. })
+        ));
+
+        rdb[5..9].copy_from_slice(b"0013");
+        assert!(matches!(
+            entries(rdb).await,
+            Err(RDBParsingError::UnsupportedVersion(13))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_overflowing_length() {
+        // hash with 2^63 fields, so twice as many strings
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.extend([0x04, 0x01, b'h', 0x81]);
+        rdb.extend((1u64 << 63).to_be_bytes());
+
+        assert!(matches!(
+            entries(rdb).await,
+            Err(RDBParsingError::InvalidRDBFile(_))
+        ));
+    }
+}
@@ -0,0 +1,314 @@
+//! Writer of RDB files, the counterpart of [`RDBParser`](super::RDBParser).
+//! Values are written with their plain encodings, except for streams which
+//! only have a listpack one.
+
+use super::crc64::crc64;
+use super::{RdbEntry, RdbValue};
+
+/// Version of the written files, loadable since Redis 7.0
+const WRITE_VERSION: u32 = 11;
+
+pub struct RDBWriter {
+    buf: Vec<u8>,
+    /// Database of the last selector
+    db: Option<usize>,
+}
+
+impl Default for RDBWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RDBWriter {
+    pub fn new() -> Self {
+        Self {
+            buf: format!("REDIS{:04}", WRITE_VERSION).into_bytes(),
+            db: None,
+        }
+    }
+
+    pub fn write_entry(&mut self, entry: &RdbEntry) {
+        if self.db != Some(entry.db) {
+            self.buf.push(0xFE);
+            self.write_length(entry.db as u64);
+            self.db = Some(entry.db);
+        }
+
+        if let Some(expires_at) = entry.expires_at {
+            self.buf.push(0xFC);
+            self.buf.extend(expires_at.to_le_bytes());
+        }
+
+        match &entry.value {
+            RdbValue::String(data) => {
+                self.buf.push(0);
+                self.write_string(&entry.key);
+                self.write_string(data);
+            }
+            RdbValue::List(elements) | RdbValue::Set(elements) => {
+                let value_type = match entry.value {
+                    RdbValue::List(_) => 1,
+                    _ => 2,
+                };
+
+                self.buf.push(value_type);
+                self.write_string(&entry.key);
+                self.write_length(elements.len() as u64);
+
+                for element in elements {
+                    self.write_string(element);
+                }
+            }
+            // scores as binary doubles
+            RdbValue::SortedSet(members) => {
+                self.buf.push(5);
+                self.write_string(&entry.key);
+                self.write_length(members.len() as u64);
+
+                for (member, score) in members {
+                    self.write_string(member);
+                    self.buf.extend(score.to_le_bytes());
+                }
+            }
+            RdbValue::Hash(pairs) => {
+                self.buf.push(4);
+                self.write_string(&entry.key);
+                self.write_length(pairs.len() as u64);
+
+                for (field, value) in pairs {
+                    self.write_string(field);
+                    self.write_string(value);
+                }
+            }
+            RdbValue::Stream { entries, last_id } => {
+                self.buf.push(15);
+                self.write_string(&entry.key);
+
+                // a single node holding every entry, relative to the first one
+                match entries.first() {
+                    Some(&(master, _)) => {
+                        self.write_length(1);
+
+                        let mut id = master.0.to_be_bytes().to_vec();
+                        id.extend(master.1.to_be_bytes());
+                        self.write_string(&id);
+                        self.write_string(&stream_node(master, entries));
+                    }
+                    None => self.write_length(0),
+                }
+
+                self.write_length(entries.len() as u64);
+                self.write_length(last_id.0);
+                self.write_length(last_id.1);
+                // consumer groups
+                self.write_length(0);
+            }
+        }
+    }
+
+    /// Ends the file with its checksum and returns it.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.push(0xFF);
+        let checksum = crc64(&self.buf);
+        self.buf.extend(checksum.to_le_bytes());
+
+        self.buf
+    }
+
+    fn write_length(&mut self, len: u64) {
+        match len {
+            0..=0x3F => self.buf.push(len as u8),
+            0x40..=0x3FFF => self.buf.extend([0x40 | (len >> 8) as u8, len as u8]),
+            0x4000..=0xFFFF_FFFF => {
+                self.buf.push(0x80);
+                self.buf.extend((len as u32).to_be_bytes());
+            }
+            _ => {
+                self.buf.push(0x81);
+                self.buf.extend(len.to_be_bytes());
+            }
+        }
+    }
+
+    fn write_string(&mut self, data: &[u8]) {
+        self.write_length(data.len() as u64);
+        self.buf.extend(data);
+    }
+}
+
+/// Encodes the entries of a stream as a listpack node, the way
+/// [`stream_node`](super::encodings::stream_node) decodes it.
+fn stream_node(master: (u64, u64), entries: &super::StreamEntries) -> Vec<u8> {
+    let mut listpack = Listpack::default();
+
+    // entries, deleted entries and fields shared with the master entry, none
+    // so that every entry has its own
+    listpack.push_int(entries.len() as i64);
+    listpack.push_int(0);
+    listpack.push_int(0);
+    listpack.push_int(0);
+
+    for ((millis, seq), fields) in entries {
+        // flags and the id relative to the master entry
+        listpack.push_int(0);
+        listpack.push_int(millis.wrapping_sub(master.0) as i64);
+        listpack.push_int(seq.wrapping_sub(master.1) as i64);
+        listpack.push_int(fields.len() as i64);
+
+        for (field, value) in fields {
+            listpack.push(field);
+            listpack.push(value);
+        }
+
+        // number of elements of the entry, to iterate backwards
+        listpack.push_int(2 * fields.len() as i64 + 4);
+    }
+
+    listpack.finish()
+}
+
+/// Listpack being written, the compact encoding of collections since RDB
+/// version 10.
+#[derive(Default)]
+struct Listpack {
+    entries: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    /// Appends a string, as an integer when it is one as Redis does.
+    fn push(&mut self, element: &[u8]) {
+        let int = std::str::from_utf8(element)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|n| n.to_string().as_bytes() == element);
+
+        if let Some(n) = int {
+            return self.push_int(n);
+        }
+
+        let mut entry = match element.len() {
+            len @ 0..=0x3F => vec![0x80 | len as u8],
+            len @ 0x40..=0xFFF => vec![0xE0 | (len >> 8) as u8, len as u8],
+            len => {
+                let mut entry = vec![0xF0];
+                entry.extend((len as u32).to_le_bytes());
+                entry
+            }
+        };
+
+        entry.extend(element);
+        self.push_entry(entry);
+    }
+
+    fn push_int(&mut self, n: i64) {
+        let entry = match n {
+            0..=127 => vec![n as u8],
+            -4096..=4095 => vec![0xC0 | (n >> 8) as u8 & 0x1F, n as u8],
+            _ => {
+                let (encoding, width) = match n {
+                    -0x8000..=0x7FFF => (0xF1, 2),
+                    -0x80_0000..=0x7F_FFFF => (0xF2, 3),
+                    -0x8000_0000..=0x7FFF_FFFF => (0xF3, 4),
+                    _ => (0xF4, 8),
+                };
+
+                let mut entry = vec![encoding];
+                entry.extend(&n.to_le_bytes()[..width]);
+                entry
+            }
+        };
+
+        self.push_entry(entry);
+    }
+
+    /// Appends an encoded entry followed by its length, to iterate backwards.
+    fn push_entry(&mut self, entry: Vec<u8>) {
+        let len = entry.len();
+        self.entries.extend(entry);
+
+        // 7 bits per byte, most significant first, with the high bit set on all
+        // but the first byte
+        let width = match len {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+
+        for i in (0..width).rev() {
+            let byte = (len >> (7 * i)) as u8 & 0x7F;
+            self.entries
+                .push(if i == width - 1 { byte } else { byte | 0x80 });
+        }
+
+        self.len += 1;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        // total bytes and number of elements, which saturates at u16::MAX
+        let mut buf = ((self.entries.len() + 7) as u32).to_le_bytes().to_vec();
+        buf.extend((self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        buf.extend(self.entries);
+        buf.push(0xFF);
+
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+
+    use super::super::RDBParser;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_written_file_is_parsed_back() {
+        let long = Bytes::from(vec![b'x'; 20_000]);
+        let fields = |value: &'static str| vec![(Bytes::from("f"), Bytes::from(value))];
+
+        let entries = [
+            RdbValue::String(long.clone()),
+            RdbValue::List(vec![long, Bytes::from("-5000"), Bytes::from("")]),
+            // the sequence of the second entry is lower than the master one
+            RdbValue::Stream {
+                entries: vec![((1, 7), fields("300")), ((2, 0), fields("a"))],
+                last_id: (2, 0),
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| RdbEntry {
+            db: i,
+            key: Bytes::from(format!("key{}", i)),
+            value,
+            expires_at: Some(i as u64 * 1000),
+            encoding: "",
+            size: 0,
+        })
+        .collect::<Vec<_>>();
+
+        let mut writer = RDBWriter::new();
+        for entry in &entries {
+            writer.write_entry(entry);
+        }
+
+        let mut parser = RDBParser::new(Cursor::new(writer.finish()));
+        assert_eq!(parser.read_header().await.unwrap(), WRITE_VERSION);
+
+        for entry in entries {
+            let parsed = parser.next_entry().await.unwrap().unwrap();
+            assert_eq!(
+                (parsed.db, parsed.key, parsed.value, parsed.expires_at),
+                (entry.db, entry.key, entry.value, entry.expires_at)
+            );
+        }
+
+        assert!(parser.next_entry().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,812 @@
+use std::str::FromStr;
+
+use bytes::{Buf, Bytes, BytesMut};
+
+use super::{ProtocolLimits, RespParseError, RespValue, CRLF};
+
+/// Maximum number of elements of an aggregate
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
+/// Maximum nesting of aggregates, requests are flat arrays and replies are shallow
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// Type prefixes of RESP2 and RESP3 values, requests starting with any other byte
+/// are inline commands
+const RESP_PREFIXES: &[u8] = b"+-:$*_#,(=%|~>";
+
+/// Maximum length of an inline command, longer lines are rejected instead of
+/// being buffered until a newline shows up
+pub(crate) const INLINE_MAX_SIZE: usize = 64 * 1024;
+
+/// Bulk strings shorter than this are copied out of the read buffer, longer ones
+/// share it. A small value kept in the keyspace would otherwise keep the whole
+/// buffer it was read into alive.
+const ZERO_COPY_MIN_LEN: usize = 4 * 1024;
+
+/// Incremental RESP decoder. Decoded elements of an incomplete aggregate are kept
+/// between calls, so a partially received request is never parsed again from the
+/// start, and large bulk strings are split off the read buffer without copying.
+#[derive(Debug)]
+pub(crate) struct Decoder {
+    limits: ProtocolLimits,
+    /// Aggregates whose elements are still being read, the innermost is last
+    stack: Vec<Aggregate>,
+    /// Bytes consumed by the value being decoded so far
+    consumed: usize,
+}
+
+#[derive(Debug)]
+struct Aggregate {
+    kind: AggregateKind,
+    /// Number of elements that are still to be read
+    remaining: usize,
+    elements: Vec<RespValue>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AggregateKind {
+    Array,
+    Set,
+    Push,
+    /// Keys and values are read as consecutive elements
+    Map,
+    /// Keys and values followed by the value the attributes belong to
+    Attribute,
+}
+
+/// An item at the start of the buffer, either a whole value or the header of
+/// an aggregate
+enum Item {
+    Value(RespValue),
+    /// Bulk string data at `start..start + len` of the item
+    Bulk {
+        start: usize,
+        len: usize,
+    },
+    Aggregate(AggregateKind, usize),
+}
+
+enum Parsed {
+    /// The item is incomplete, at least `usize` bytes are needed if known
+    Incomplete(Option<usize>),
+    /// The item and the number of bytes it spans
+    Item(Item, usize),
+}
+
+impl Decoder {
+    pub(crate) fn new(limits: ProtocolLimits) -> Self {
+        Self {
+            limits,
+            stack: vec![],
+            consumed: 0,
+        }
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: ProtocolLimits) {
+        self.limits = limits;
+    }
+
+    /// Bytes of the value being decoded that were already taken from the buffer.
+    pub(crate) fn buffered(&self) -> usize {
+        self.consumed
+    }
+
+    /// Decodes the next value from `buf`, returning the value and the number of bytes
+    /// it spans. Complete items are taken from `buf` even if the value is incomplete,
+    /// in which case `RespParseError::Incomplete` is returned and decoding continues
+    /// with the next call once more bytes were read into `buf`.
+    ///
+    /// Requests of telnet and netcat are inline commands such as `SET key "a b"`, they
+    /// are returned as an array of bulk strings like regular requests.
+    pub(crate) fn decode(
+        &mut self,
+        buf: &mut BytesMut,
+    ) -> Result<(RespValue, usize), RespParseError> {
+        loop {
+            let mut value = match buf.first() {
+                Some(prefix) if self.stack.is_empty() && !RESP_PREFIXES.contains(prefix) => {
+                    let (args, len) = decode_inline(buf)?;
+
+                    buf.advance(len);
+                    self.consumed += len;
+
+                    // empty lines are skipped
+                    if args.is_empty() {
+                        continue;
+                    }
+
+                    RespValue::Array(args.into_iter().map(RespValue::BulkString).collect())
+                }
+                _ => match parse_item(buf, &self.limits)? {
+                    Parsed::Incomplete(needed) => {
+                        // read a large bulk string into a single allocation
+                        if let Some(needed) = needed {
+                            buf.reserve(needed.saturating_sub(buf.len()));
+                        }
+
+                        return Err(RespParseError::Incomplete);
+                    }
+                    Parsed::Item(item, len) => {
+                        self.consumed += len;
+
+                        match item {
+                            Item::Value(value) => {
+                                buf.advance(len);
+                                value
+                            }
+                            Item::Bulk { start, len: data } if data < ZERO_COPY_MIN_LEN => {
+                                let value = Bytes::copy_from_slice(&buf[start..start + data]);
+                                buf.advance(len);
+                                RespValue::BulkString(value)
+                            }
+                            Item::Bulk { start, len: data } => {
+                                let item = buf.split_to(len).freeze();
+                                RespValue::BulkString(item.slice(start..start + data))
+                            }
+                            Item::Aggregate(kind, elements) => {
+                                buf.advance(len);
+
+                                if self.stack.len() >= MAX_NESTING_DEPTH {
+                                    return Err(RespParseError::NestingTooDeep);
+                                }
+
+                                self.stack.push(Aggregate {
+                                    kind,
+                                    remaining: elements,
+                                    // the length is not trusted, the elements may not follow
+                                    elements: Vec::with_capacity(elements.min(1024)),
+                                });
+
+                                continue;
+                            }
+                        }
+                    }
+                },
+            };
+
+            // add the value to the aggregates it completes
+            loop {
+                let Some(aggregate) = self.stack.last_mut() else {
+                    return Ok((value, std::mem::take(&mut self.consumed)));
+                };
+
+                aggregate.elements.push(value);
+                aggregate.remaining -= 1;
+
+                if aggregate.remaining > 0 {
+                    break;
+                }
+
+                value = self.stack.pop().expect("aggregate exists").finish();
+            }
+        }
+    }
+}
+
+impl Aggregate {
+    fn finish(self) -> RespValue {
+        match self.kind {
+            AggregateKind::Array => RespValue::Array(self.elements),
+            AggregateKind::Set => RespValue::Set(self.elements),
+            AggregateKind::Push => RespValue::Push(self.elements),
+            AggregateKind::Map => RespValue::Map(pairs(self.elements)),
+            AggregateKind::Attribute => {
+                let mut elements = self.elements;
+                let value = elements.pop().expect("attribute has a value");
+
+                RespValue::Attribute(pairs(elements), Box::new(value))
+            }
+        }
+    }
+}
+
+fn pairs(elements: Vec<RespValue>) -> Vec<(RespValue, RespValue)> {
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::with_capacity(elements.len() / 2);
+
+    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+        pairs.push((key, value));
+    }
+
+    pairs
+}
+
+/// Returns the line starting at `start` and the position after its CRLF.
+fn line(data: &[u8], start: usize) -> Option<(&[u8], usize)> {
+    let end = data[start..].windows(2).position(|w| w == CRLF)? + start;
+
+    Some((&data[start..end], end + 2))
+}
+
+fn parse_str(line: &[u8]) -> Result<String, RespParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| RespParseError::InvalidValue)?;
+    Ok(line.to_string())
+}
+
+fn parse_number<T: FromStr>(line: &[u8]) -> Result<T, RespParseError> {
+    let line = std::str::from_utf8(line).map_err(|_| RespParseError::InvalidValue)?;
+    line.parse().map_err(|_| RespParseError::InvalidValue)
+}
+
+/// Parses the length of a bulk string, `-1` for a null bulk string.
+fn parse_bulk_len(line: &[u8], limits: &ProtocolLimits) -> Result<i64, RespParseError> {
+    let len: i64 = parse_number(line).map_err(|_| RespParseError::InvalidBulkLength)?;
+
+    if len < -1 || len > limits.max_bulk_len as i64 {
+        return Err(RespParseError::InvalidBulkLength);
+    }
+
+    Ok(len)
+}
+
+/// Parses the number of elements of an aggregate, `-1` for a null array.
+fn parse_multibulk_len(line: &[u8]) -> Result<i64, RespParseError> {
+    let len: i64 = parse_number(line).map_err(|_| RespParseError::InvalidMultibulkLength)?;
+
+    if len < -1 || len > MAX_MULTIBULK_LEN as i64 {
+        return Err(RespParseError::InvalidMultibulkLength);
+    }
+
+    Ok(len)
+}
+
+/// Parses the item at the start of `data`, a scalar value or the header of an
+/// aggregate.
+fn parse_item(data: &[u8], limits: &ProtocolLimits) -> Result<Parsed, RespParseError> {
+    let Some(&prefix) = data.first() else {
+        return Ok(Parsed::Incomplete(None));
+    };
+
+    let Some((line, end)) = line(data, 1) else {
+        return Ok(Parsed::Incomplete(None));
+    };
+
+    let value = match prefix {
+        // Simple String: `+<data>\r\n`
+        b'+' => RespValue::SimpleString(parse_str(line)?),
+
+        // Simple Error: `-<error>\r\n`
+        b'-' => RespValue::SimpleError(parse_str(line)?),
+
+        // Integer: `:[<+|->]<value>\r\n`
+        b':' => RespValue::Integer(parse_number(line)?),
+
+        // Bulk String: `$<length>\r\n<data>\r\n`
+        b'$' => {
+            // handle bulk string null: `$-1\r\n`
+            let len = match parse_bulk_len(line, limits)? {
+                -1 => return Ok(Parsed::Item(Item::Value(RespValue::Null), end)),
+                len => len as usize,
+            };
+
+            if data.len() < end + len {
+                return Ok(Parsed::Incomplete(Some(end + len + 2)));
+            }
+
+            // the RDB file sent to replicas is not followed by CRLF
+            if is_rdb(&data[end..end + len]) {
+                return Ok(Parsed::Item(Item::Bulk { start: end, len }, end + len));
+            }
+
+            if data.len() < end + len + 2 {
+                return Ok(Parsed::Incomplete(Some(end + len + 2)));
+            }
+
+            if &data[end + len..end + len + 2] != CRLF {
+                return Err(RespParseError::InvalidBulkLength);
+            }
+
+            return Ok(Parsed::Item(Item::Bulk { start: end, len }, end + len + 2));
+        }
+
+        // Array: *<number-of-elements>\r\n<element-1>...<element-n>
+        b'*' => match parse_multibulk_len(line)? {
+            // handle null array: `*-1\r\n`
+            -1 => RespValue::NullArray,
+            0 => RespValue::Array(vec![]),
+            len => return Ok(aggregate(AggregateKind::Array, len as usize, end)),
+        },
+
+        // Null: `_\r\n`
+        b'_' => RespValue::Null,
+
+        // Boolean: `#<t|f>\r\n`
+        b'#' => match line {
+            b"t" => RespValue::Boolean(true),
+            b"f" => RespValue::Boolean(false),
+            _ => return Err(RespParseError::InvalidValue),
+        },
+
+        // Double: `,[<+|->]<integral>[.<fractional>][<E|e>[sign]<exponent>]\r\n`
+        b',' => RespValue::Double(match line {
+            b"inf" => f64::INFINITY,
+            b"-inf" => f64::NEG_INFINITY,
+            b"nan" => f64::NAN,
+            line => parse_number(line)?,
+        }),
+
+        // Big number: `([+|-]<number>\r\n`
+        b'(' => {
+            let digits = line.strip_prefix(b"+").or(line.strip_prefix(b"-"));
+            let digits = digits.unwrap_or(line);
+
+            if digits.is_empty() || !digits.iter().all(|b| b.is_ascii_digit()) {
+                return Err(RespParseError::InvalidValue);
+            }
+
+            RespValue::BigNumber(parse_str(line)?)
+        }
+
+        // Verbatim string: `=<length>\r\n<encoding>:<data>\r\n`
+        b'=' => {
+            let len = match parse_bulk_len(line, limits)? {
+                -1 => return Err(RespParseError::InvalidBulkLength),
+                len => len as usize,
+            };
+
+            if data.len() < end + len + 2 {
+                return Ok(Parsed::Incomplete(Some(end + len + 2)));
+            }
+
+            let verbatim = &data[end..end + len];
+
+            if len < 4 || verbatim[3] != b':' || &data[end + len..end + len + 2] != CRLF {
+                return Err(RespParseError::InvalidValue);
+            }
+
+            let value =
+                RespValue::VerbatimString(parse_str(&verbatim[..3])?, verbatim[4..].to_vec());
+
+            return Ok(Parsed::Item(Item::Value(value), end + len + 2));
+        }
+
+        // Map: `%<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>`
+        b'%' => match parse_aggregate_len(line)? {
+            0 => RespValue::Map(vec![]),
+            len => return Ok(aggregate(AggregateKind::Map, len * 2, end)),
+        },
+
+        // Attribute: `|<number-of-attributes>\r\n<key-1><value-1>...` followed by the value
+        b'|' => {
+            let len = parse_aggregate_len(line)?;
+            return Ok(aggregate(AggregateKind::Attribute, len * 2 + 1, end));
+        }
+
+        // Set: `~<number-of-elements>\r\n<element-1>...<element-n>`
+        b'~' => match parse_aggregate_len(line)? {
+            0 => RespValue::Set(vec![]),
+            len => return Ok(aggregate(AggregateKind::Set, len, end)),
+        },
+
+        // Push: `><number-of-elements>\r\n<element-1>...<element-n>`
+        b'>' => match parse_aggregate_len(line)? {
+            0 => RespValue::Push(vec![]),
+            len => return Ok(aggregate(AggregateKind::Push, len, end)),
+        },
+
+        _ => return Err(RespParseError::InvalidTypePrefix),
+    };
+
+    Ok(Parsed::Item(Item::Value(value), end))
+}
+
+fn aggregate(kind: AggregateKind, elements: usize, end: usize) -> Parsed {
+    Parsed::Item(Item::Aggregate(kind, elements), end)
+}
+
+/// Parses the number of elements of a RESP3 aggregate, which can't be null.
+fn parse_aggregate_len(line: &[u8]) -> Result<usize, RespParseError> {
+    match parse_multibulk_len(line)? {
+        -1 => Err(RespParseError::InvalidMultibulkLength),
+        len => Ok(len as usize),
+    }
+}
+
+fn is_rdb(data: &[u8]) -> bool {
+    // the smallest RDB file is the `REDIS<version>` header followed by EOF and the
+    // checksum, the file ends with the EOF opcode and 8 bytes of checksum
+    // TODO: check checksum
+    data.len() >= 18 && data.starts_with(b"REDIS") && data[data.len() - 9] == 0xFF
+}
+
+/// Inline command: `<arg-1> <arg-2> ... <arg-n>\r\n`, the `\r` is optional. Returns the
+/// arguments and the length of the line.
+fn decode_inline(data: &[u8]) -> Result<(Vec<Bytes>, usize), RespParseError> {
+    let Some(newline) = data.iter().position(|&b| b == b'\n') else {
+        if data.len() > INLINE_MAX_SIZE {
+            return Err(RespParseError::InlineTooBig);
+        }
+
+        return Err(RespParseError::Incomplete);
+    };
+
+    if newline > INLINE_MAX_SIZE {
+        return Err(RespParseError::InlineTooBig);
+    }
+
+    let line = data[..newline]
+        .strip_suffix(b"\r")
+        .unwrap_or(&data[..newline]);
+
+    let args = split_inline_args(line)?;
+
+    Ok((args.into_iter().map(Bytes::from).collect(), newline + 1))
+}
+
+/// Splits an inline command into arguments separated by whitespace. Arguments may
+/// be double quoted with C-like escapes such as `\n` and `\x41`, or single quoted
+/// where only `\'` is escaped.
+fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RespParseError> {
+    let mut args = vec![];
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = vec![];
+
+        while i < line.len() && !line[i].is_ascii_whitespace() {
+            match line[i] {
+                quote @ (b'"' | b'\'') => {
+                    i += 1;
+
+                    loop {
+                        let Some(&b) = line.get(i) else {
+                            return Err(RespParseError::UnbalancedQuotes);
+                        };
+
+                        match b {
+                            b'\\' if quote == b'"' && i + 1 < line.len() => {
+                                let escaped = line[i + 1];
+                                i += 2;
+
+                                let hex = line
+                                    .get(i..i + 2)
+                                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+                                match (escaped, hex) {
+                                    (b'x', Some(byte)) => {
+                                        arg.push(byte);
+                                        i += 2;
+                                    }
+                                    (b'n', _) => arg.push(b'\n'),
+                                    (b'r', _) => arg.push(b'\r'),
+                                    (b't', _) => arg.push(b'\t'),
+                                    (b'b', _) => arg.push(0x08),
+                                    (b'a', _) => arg.push(0x07),
+                                    (other, _) => arg.push(other),
+                                }
+                            }
+                            b'\\' if quote == b'\'' && line.get(i + 1) == Some(&b'\'') => {
+                                arg.push(b'\'');
+                                i += 2;
+                            }
+                            b if b == quote => {
+                                i += 1;
+
+                                // the closing quote must end the argument
+                                if line.get(i).is_some_and(|b| !b.is_ascii_whitespace()) {
+                                    return Err(RespParseError::UnbalancedQuotes);
+                                }
+
+                                break;
+                            }
+                            b => {
+                                arg.push(b);
+                                i += 1;
+                            }
+                        }
+                    }
+                }
+                b => {
+                    arg.push(b);
+                    i += 1;
+                }
+            }
+        }
+
+        args.push(arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::resp::{encode_resp, Protocol};
+
+    fn decode(buf: &mut BytesMut) -> Result<RespValue, RespParseError> {
+        let mut decoder = Decoder::new(ProtocolLimits::default());
+        decoder.decode(buf).map(|(value, _)| value)
+    }
+
+    fn bulk(s: &[u8]) -> RespValue {
+        RespValue::BulkString(Bytes::copy_from_slice(s))
+    }
+
+    #[test]
+    fn test_parse_inline_request() {
+        let buf = &mut BytesMut::from(
+            &b"SET key \"a \\\"b\\x41\\n\" 'it\\'s'\r\n\r\n  PING\n*1\r\n$4\r\nPING\r\n"[..],
+        );
+
+        assert_eq!(
+            decode(buf).unwrap(),
+            RespValue::Array(vec![
+                bulk(b"SET"),
+                bulk(b"key"),
+                bulk(b"a \"bA\n"),
+                bulk(b"it's")
+            ])
+        );
+        assert_eq!(decode(buf).unwrap(), RespValue::Array(vec![bulk(b"PING")]));
+        assert_eq!(decode(buf).unwrap(), RespValue::Array(vec![bulk(b"PING")]));
+
+        for request in [&b"SET key \"value\r\n"[..], b"SET key 'a'b\r\n"] {
+            assert!(matches!(
+                decode(&mut BytesMut::from(request)),
+                Err(RespParseError::UnbalancedQuotes)
+            ));
+        }
+
+        assert!(matches!(
+            decode(&mut BytesMut::from(&b"GET key"[..])),
+            Err(RespParseError::Incomplete)
+        ));
+
+        let oversized = vec![b'a'; INLINE_MAX_SIZE + 1];
+        assert!(matches!(
+            decode(&mut BytesMut::from(&oversized[..])),
+            Err(RespParseError::InlineTooBig)
+        ));
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = ProtocolLimits {
+            max_bulk_len: 4,
+            ..Default::default()
+        };
+
+        let parse = |buf: &[u8]| Decoder::new(limits).decode(&mut BytesMut::from(buf));
+
+        assert!(parse(b"$4\r\nabcd\r\n").is_ok());
+        assert!(matches!(
+            parse(b"$5\r\nabcde\r\n"),
+            Err(RespParseError::InvalidBulkLength)
+        ));
+        assert!(matches!(
+            parse(b"$-2\r\n"),
+            Err(RespParseError::InvalidBulkLength)
+        ));
+        assert!(matches!(
+            parse(b"*1048577\r\n"),
+            Err(RespParseError::InvalidMultibulkLength)
+        ));
+        assert!(matches!(
+            parse(b"*x\r\n"),
+            Err(RespParseError::InvalidMultibulkLength)
+        ));
+        assert!(matches!(
+            parse(b"%-1\r\n"),
+            Err(RespParseError::InvalidMultibulkLength)
+        ));
+        assert!(matches!(
+            parse(&b"*1\r\n".repeat(100)),
+            Err(RespParseError::NestingTooDeep)
+        ));
+
+        // a declared length is not allocated before the data arrives
+        assert!(matches!(
+            parse(b"*1000000\r\n"),
+            Err(RespParseError::Incomplete)
+        ));
+
+        // the data must be followed by CRLF, otherwise the length is wrong
+        assert!(matches!(
+            parse(b"$2\r\nabcd\r\n"),
+            Err(RespParseError::InvalidBulkLength)
+        ));
+
+        // null bulk strings are consumed
+        let buf = &mut BytesMut::from(&b"$-1\r\n:1\r\n"[..]);
+        assert_eq!(decode(buf).unwrap(), RespValue::Null);
+        assert_eq!(decode(buf).unwrap(), RespValue::Integer(1));
+    }
+
+    #[test]
+    fn test_resume_partial_request() {
+        let value = vec![b'v'; 2 * ZERO_COPY_MIN_LEN];
+
+        let mut request = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n".to_vec();
+        request.extend(format!("${}\r\n", value.len()).as_bytes());
+        request.extend(&value);
+        request.extend(b"\r\n:1\r\n");
+
+        let mut decoder = Decoder::new(ProtocolLimits::default());
+        let mut buf = BytesMut::new();
+
+        // the complete elements are taken from the buffer and not parsed again
+        buf.extend_from_slice(&request[..30]);
+        assert!(matches!(
+            decoder.decode(&mut buf),
+            Err(RespParseError::Incomplete)
+        ));
+        assert_eq!(decoder.buffered(), 22);
+        assert_eq!(buf.len(), 8);
+
+        // the buffer is reserved for the whole bulk string
+        assert!(buf.capacity() >= value.len());
+
+        buf.extend_from_slice(&request[30..]);
+
+        let (request, len) = decoder.decode(&mut buf).unwrap();
+        assert_eq!(len, 22 + 7 + value.len() + 2);
+        assert_eq!(decoder.buffered(), 0);
+        assert_eq!(
+            request,
+            RespValue::Array(vec![bulk(b"SET"), bulk(b"key"), bulk(&value)])
+        );
+
+        assert_eq!(
+            decoder.decode(&mut buf).unwrap(),
+            (RespValue::Integer(1), 4)
+        );
+    }
+
+    #[test]
+    fn test_large_bulk_string_is_zero_copy() {
+        let value = vec![b'v'; 2 * ZERO_COPY_MIN_LEN];
+
+        let mut buf = BytesMut::from(format!("${}\r\n", value.len()).as_bytes());
+        buf.extend_from_slice(&value);
+        buf.extend_from_slice(b"\r\n");
+
+        let start = buf.as_ptr() as usize;
+
+        match decode(&mut buf).unwrap() {
+            RespValue::BulkString(bytes) => {
+                assert_eq!(bytes, value);
+                assert_eq!(bytes.as_ptr() as usize, start + 7);
+            }
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    fn random_value(rng: &mut StdRng, depth: usize) -> RespValue {
+        let random_bytes = |rng: &mut StdRng| -> Vec<u8> {
+            let len = rng.gen_range(0..20);
+            (0..len).map(|_| rng.gen()).collect()
+        };
+
+        let random_line = |rng: &mut StdRng| -> String {
+            let len = rng.gen_range(0..20);
+            (0..len).map(|_| rng.gen_range(' '..='~')).collect()
+        };
+
+        let kind = if depth > 3 {
+            rng.gen_range(0..9)
+        } else {
+            rng.gen_range(0..14)
+        };
+
+        match kind {
+            0 => RespValue::SimpleString(random_line(rng)),
+            1 => RespValue::SimpleError(random_line(rng)),
+            2 => RespValue::Integer(rng.gen()),
+            3 => RespValue::BulkString(random_bytes(rng).into()),
+            4 => RespValue::Null,
+            5 => RespValue::Double(rng.gen::<f64>() * rng.gen_range(-1e6..1e6)),
+            6 => RespValue::Boolean(rng.gen()),
+            7 => RespValue::BigNumber(format!("-{}{}", rng.gen::<u64>(), rng.gen::<u64>())),
+            8 => RespValue::VerbatimString("txt".to_string(), random_bytes(rng)),
+            9..=11 => {
+                let elements = (0..rng.gen_range(0..5))
+                    .map(|_| random_value(rng, depth + 1))
+                    .collect();
+
+                match kind {
+                    9 => RespValue::Array(elements),
+                    10 => RespValue::Set(elements),
+                    _ => RespValue::Push(elements),
+                }
+            }
+            _ => {
+                let pairs = (0..rng.gen_range(0..5))
+                    .map(|_| (random_value(rng, depth + 1), random_value(rng, depth + 1)))
+                    .collect();
+
+                match kind {
+                    12 => RespValue::Map(pairs),
+                    _ => RespValue::Attribute(pairs, Box::new(random_value(rng, depth + 1))),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+
+        for _ in 0..2000 {
+            let value = random_value(&mut rng, 0);
+            let encoded = encode_resp(&value, Protocol::Resp3);
+
+            assert_eq!(
+                Decoder::new(ProtocolLimits::default())
+                    .decode(&mut BytesMut::from(&encoded[..]))
+                    .unwrap(),
+                (value.clone(), encoded.len())
+            );
+
+            // every strict prefix is incomplete, never an error or a panic
+            for len in 0..encoded.len() {
+                let result = decode(&mut BytesMut::from(&encoded[..len]));
+                assert!(
+                    matches!(result, Err(RespParseError::Incomplete)),
+                    "{:?} of {:?}",
+                    result,
+                    &encoded[..len]
+                );
+            }
+
+            // the value is the same when it arrives in random chunks
+            let mut decoder = Decoder::new(ProtocolLimits::default());
+            let mut buf = BytesMut::new();
+            let mut chunks = encoded.as_slice();
+
+            let decoded = loop {
+                let len = rng.gen_range(1..=chunks.len().max(1)).min(chunks.len());
+                buf.extend_from_slice(&chunks[..len]);
+                chunks = &chunks[len..];
+
+                match decoder.decode(&mut buf) {
+                    Err(RespParseError::Incomplete) if !chunks.is_empty() => continue,
+                    result => break result.unwrap(),
+                }
+            };
+
+            assert_eq!(decoded, (value, encoded.len()));
+        }
+    }
+
+    #[test]
+    fn test_fuzz_garbage_never_panics() {
+        let mut rng = StdRng::seed_from_u64(0xbad);
+
+        let tokens: [&[u8]; 20] = [
+            b"*", b"$", b"%", b"~", b">", b"|", b"=", b"(", b",", b"#", b"_", b"+", b"-", b":",
+            b"\r\n", b"-1", b"0", b"3", b"REDIS", b"\"",
+        ];
+
+        for _ in 0..20000 {
+            // mix valid tokens with random bytes to reach deeper into the decoder
+            let mut input = vec![];
+
+            for _ in 0..rng.gen_range(0..30) {
+                if rng.gen_bool(0.8) {
+                    input.extend(tokens[rng.gen_range(0..tokens.len())]);
+                } else {
+                    input.push(rng.gen());
+                }
+            }
+
+            let mut decoder = Decoder::new(ProtocolLimits::default());
+            let mut buf = BytesMut::from(&input[..]);
+
+            // decode until the input is exhausted or rejected
+            while let Ok((_, len)) = decoder.decode(&mut buf) {
+                assert!(len > 0 && len <= input.len());
+            }
+        }
+    }
+}
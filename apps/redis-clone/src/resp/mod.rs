@@ -0,0 +1,628 @@
+use std::fmt::Display;
+
+use anyhow::Error;
+use bytes::Bytes;
+
+pub(crate) use self::decoder::Decoder;
+
+mod decoder;
+
+const CRLF: &[u8] = b"\r\n";
+
+/*
+https://redis.io/docs/reference/protocol-spec/
+ */
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RespValue {
+    SimpleString(String),
+    SimpleError(String),
+    Integer(i64),
+    BulkString(Bytes),
+    /// Null bulk string in RESP2, `_` in RESP3
+    Null,
+    Array(Vec<RespValue>),
+    /// Null array in RESP2, `_` in RESP3
+    NullArray,
+
+    // RESP3 types, downgraded to RESP2 types for RESP2 connections
+    Map(Vec<(RespValue, RespValue)>),
+    Set(Vec<RespValue>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    /// Format, such as `txt` or `mkd`, and the data
+    VerbatimString(String, Vec<u8>),
+    /// Out-of-band attributes of the value that follows them
+    Attribute(Vec<(RespValue, RespValue)>, Box<RespValue>),
+    Push(Vec<RespValue>),
+}
+
+/// Protocol version of a connection, negotiated with `HELLO`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+/// Represents a Redis Protocol (RESP) value.
+///
+/// This struct provides methods for serializing a `RespValue` to a byte vector, values
+/// are parsed by the [`Decoder`].
+impl RespValue {
+    /// Serializes the `RespValue` to a byte vector. RESP3 types are replaced by
+    /// their RESP2 counterparts when `protocol` is RESP2.
+    ///
+    /// # Returns
+    ///
+    /// Returns a byte vector containing the serialized `RespValue`.
+    pub(crate) fn to_buf(&self, protocol: Protocol) -> Vec<u8> {
+        encode_resp(self, protocol)
+    }
+
+    pub fn as_integer(&self) -> Result<i64, Error> {
+        match self {
+            RespValue::Integer(i) => Ok(*i),
+            RespValue::BulkString(s) => {
+                let val_as_str = std::str::from_utf8(s)?;
+                Ok(val_as_str.parse()?)
+            }
+            _ => Err(anyhow::anyhow!("Invalid value")),
+        }
+    }
+
+    pub fn as_bulk(&self) -> Result<RespValue, Error> {
+        match self {
+            RespValue::SimpleString(string) => Ok(RespValue::BulkString(Bytes::copy_from_slice(
+                string.as_bytes(),
+            ))),
+            _ => Err(anyhow::anyhow!("Invalid value")),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        // TODO: optimize this to avoid encoding the value
+        let encoded = encode_resp(self, Protocol::Resp2);
+        encoded.len()
+    }
+}
+
+// for positional matchers
+impl From<&RespValue> for RespValue {
+    fn from(value: &RespValue) -> Self {
+        value.clone()
+    }
+}
+
+impl From<&str> for RespValue {
+    fn from(s: &str) -> Self {
+        if s.is_empty() {
+            return RespValue::Null;
+        };
+
+        // if string contains any whitespace or newline, treat it as a bulk string
+        if s.contains(|c: char| c.is_whitespace() || c == '\r' || c == '\n') {
+            return RespValue::BulkString(Bytes::copy_from_slice(s.as_bytes()));
+        }
+
+        RespValue::SimpleString(s.to_string())
+    }
+}
+
+impl TryFrom<RespValue> for Bytes {
+    type Error = RespParseError;
+
+    fn try_from(val: RespValue) -> Result<Self, Self::Error> {
+        match val {
+            RespValue::BulkString(b) => Ok(b),
+            _ => Err(RespParseError::InvalidValue),
+        }
+    }
+}
+
+impl TryFrom<&RespValue> for String {
+    type Error = RespParseError;
+
+    fn try_from(val: &RespValue) -> Result<Self, Self::Error> {
+        match val {
+            RespValue::SimpleString(s) => Ok(s.clone()),
+            RespValue::SimpleError(e) => Ok(e.clone()),
+            RespValue::Integer(i) => Ok(i.to_string()),
+            RespValue::BulkString(b) => Ok(String::from_utf8_lossy(b).to_string()),
+            _ => Err(RespParseError::InvalidValue),
+        }
+    }
+}
+
+impl TryFrom<RespValue> for String {
+    type Error = RespParseError;
+
+    fn try_from(val: RespValue) -> Result<Self, Self::Error> {
+        match val {
+            RespValue::SimpleString(s) => Ok(s),
+            RespValue::SimpleError(e) => Ok(e),
+            RespValue::Integer(i) => Ok(i.to_string()),
+            RespValue::BulkString(b) => Ok(String::from_utf8_lossy(&b).to_string()),
+            _ => Err(RespParseError::InvalidValue),
+        }
+    }
+}
+
+impl TryFrom<RespValue> for i64 {
+    type Error = RespParseError;
+
+    fn try_from(val: RespValue) -> Result<Self, Self::Error> {
+        match val {
+            RespValue::Integer(i) => Ok(i),
+            RespValue::BulkString(b) => {
+                let val_as_str =
+                    std::str::from_utf8(&b).map_err(|_| RespParseError::InvalidValue)?;
+                val_as_str.parse().map_err(|_| RespParseError::InvalidValue)
+            }
+            _ => Err(RespParseError::InvalidValue),
+        }
+    }
+}
+
+impl TryFrom<RespValue> for u64 {
+    type Error = RespParseError;
+
+    fn try_from(val: RespValue) -> Result<Self, Self::Error> {
+        match val {
+            RespValue::Integer(i) => u64::try_from(i).map_err(|_| RespParseError::InvalidValue),
+            RespValue::BulkString(b) => {
+                let val_as_str =
+                    std::str::from_utf8(&b).map_err(|_| RespParseError::InvalidValue)?;
+                val_as_str.parse().map_err(|_| RespParseError::InvalidValue)
+            }
+            _ => Err(RespParseError::InvalidValue),
+        }
+    }
+}
+
+impl Display for RespValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let buff = encode_resp(self, Protocol::Resp2)
+            .iter()
+            .map(|b| *b as char)
+            .collect::<String>();
+
+        write!(f, "{}", buff)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RespParseError {
+    #[error("Invalid type prefix byte was encountered")]
+    InvalidTypePrefix,
+    #[error("No corresponding newline was found for value")]
+    MissingNewline,
+    #[error("Buffer is incomplete")]
+    Incomplete,
+    #[error("Invalid value was found")]
+    InvalidValue,
+    #[error("too big inline request")]
+    InlineTooBig,
+    #[error("unbalanced quotes in request")]
+    UnbalancedQuotes,
+    #[error("invalid bulk length")]
+    InvalidBulkLength,
+    #[error("invalid multibulk length")]
+    InvalidMultibulkLength,
+    #[error("too many nested aggregates")]
+    NestingTooDeep,
+}
+
+/// Limits of the values accepted from a peer, so that a malicious or broken client
+/// can't make the server allocate unbounded memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProtocolLimits {
+    /// Maximum length of a bulk string, `proto-max-bulk-len`
+    pub(crate) max_bulk_len: usize,
+    /// Maximum number of bytes buffered for incomplete requests,
+    /// `client-query-buffer-limit`
+    pub(crate) query_buffer_limit: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            query_buffer_limit: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+fn encode_resp(val: &RespValue, protocol: Protocol) -> Vec<u8> {
+    let mut buf = vec![];
+    encode_into(&mut buf, val, protocol);
+    buf
+}
+
+fn encode_into(buf: &mut Vec<u8>, val: &RespValue, protocol: Protocol) {
+    let resp3 = protocol == Protocol::Resp3;
+
+    match val {
+        RespValue::Null | RespValue::NullArray if resp3 => buf.extend(b"_\r\n"),
+        RespValue::Null => buf.extend(b"$-1\r\n"),
+        RespValue::NullArray => buf.extend(b"*-1\r\n"),
+        RespValue::SimpleString(s) => buf.extend(format!("+{s}\r\n").as_bytes()),
+        RespValue::SimpleError(e) => buf.extend(format!("-{e}\r\n").as_bytes()),
+        RespValue::Integer(i) => buf.extend(format!(":{i}\r\n").as_bytes()),
+        RespValue::BulkString(s) => encode_blob(buf, b'$', s),
+        RespValue::Array(a) => encode_aggregate(buf, b'*', a, protocol),
+        RespValue::Set(a) if resp3 => encode_aggregate(buf, b'~', a, protocol),
+        RespValue::Push(a) if resp3 => encode_aggregate(buf, b'>', a, protocol),
+        RespValue::Set(a) | RespValue::Push(a) => encode_aggregate(buf, b'*', a, protocol),
+        RespValue::Map(pairs) if resp3 => encode_pairs(buf, b'%', pairs, protocol),
+        RespValue::Map(pairs) => {
+            // RESP2 clients get the keys and values as a flat array
+            buf.extend(format!("*{}\r\n", pairs.len() * 2).as_bytes());
+
+            for (key, value) in pairs {
+                encode_into(buf, key, protocol);
+                encode_into(buf, value, protocol);
+            }
+        }
+        RespValue::Double(d) => {
+            let double = if d.is_nan() {
+                "nan".to_string()
+            } else if d.is_infinite() {
+                if *d > 0.0 { "inf" } else { "-inf" }.to_string()
+            } else {
+                d.to_string()
+            };
+
+            if resp3 {
+                buf.extend(format!(",{double}\r\n").as_bytes());
+            } else {
+                encode_blob(buf, b'$', double.as_bytes());
+            }
+        }
+        RespValue::Boolean(b) if resp3 => buf.extend(if *b { b"#t\r\n" } else { b"#f\r\n" }),
+        RespValue::Boolean(b) => buf.extend(format!(":{}\r\n", *b as i64).as_bytes()),
+        RespValue::BigNumber(n) if resp3 => buf.extend(format!("({n}\r\n").as_bytes()),
+        RespValue::BigNumber(n) => encode_blob(buf, b'$', n.as_bytes()),
+        RespValue::VerbatimString(format, data) if resp3 => {
+            let mut verbatim = Vec::with_capacity(format.len() + 1 + data.len());
+            verbatim.extend(format.as_bytes());
+            verbatim.push(b':');
+            verbatim.extend(data);
+
+            encode_blob(buf, b'=', &verbatim);
+        }
+        RespValue::VerbatimString(_, data) => encode_blob(buf, b'$', data),
+        RespValue::Attribute(attributes, value) => {
+            // RESP2 has no attributes, only the value is sent
+            if resp3 {
+                encode_pairs(buf, b'|', attributes, protocol);
+            }
+
+            encode_into(buf, value, protocol);
+        }
+    }
+}
+
+fn encode_blob(buf: &mut Vec<u8>, prefix: u8, data: &[u8]) {
+    buf.push(prefix);
+    buf.extend(format!("{}\r\n", data.len()).as_bytes());
+    buf.extend(data);
+    buf.extend(CRLF);
+}
+
+fn encode_aggregate(buf: &mut Vec<u8>, prefix: u8, elements: &[RespValue], protocol: Protocol) {
+    buf.push(prefix);
+    buf.extend(format!("{}\r\n", elements.len()).as_bytes());
+
+    for element in elements {
+        encode_into(buf, element, protocol);
+    }
+}
+
+fn encode_pairs(
+    buf: &mut Vec<u8>,
+    prefix: u8,
+    pairs: &[(RespValue, RespValue)],
+    protocol: Protocol,
+) {
+    buf.push(prefix);
+    buf.extend(format!("{}\r\n", pairs.len()).as_bytes());
+
+    for (key, value) in pairs {
+        encode_into(buf, key, protocol);
+        encode_into(buf, value, protocol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::{Buf, BytesMut};
+
+    use super::*;
+
+    /// Decodes a value from the cursor and advances it past the value.
+    fn decode(buf: &mut Cursor<&[u8]>) -> Result<RespValue, RespParseError> {
+        let mut bytes = BytesMut::from(buf.chunk());
+        let (value, len) = Decoder::new(ProtocolLimits::default()).decode(&mut bytes)?;
+
+        buf.advance(len);
+
+        Ok(value)
+    }
+
+    #[test]
+    fn test_conversions_of_other_types_fail() {
+        let array = RespValue::Array(vec![]);
+
+        assert!(Bytes::try_from(RespValue::Integer(1)).is_err());
+        assert!(String::try_from(array.clone()).is_err());
+        assert!(String::try_from(&array).is_err());
+        assert!(i64::try_from(RespValue::Null).is_err());
+        assert!(u64::try_from(RespValue::Integer(-1)).is_err());
+
+        // an argument that isn't a bulk string is rejected instead of panicking
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"*2\r\n$3\r\nDEL\r\n:1\r\n");
+        let request = decode(buf).unwrap();
+        assert!(crate::commands::Command::try_from(request).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_simple_string() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"+Hello, World!\r\n");
+        let expected = RespValue::SimpleString("Hello, World!".to_string());
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_message_simple_error() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"-Error occurred\r\n");
+        let expected = RespValue::SimpleError("Error occurred".to_string());
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_message_integer() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b":42\r\n");
+        let expected = RespValue::Integer(42);
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_message_bulk_string() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"$5\r\nHello\r\n");
+        let expected = RespValue::BulkString(Bytes::from_static(b"Hello"));
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_message_null() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"$-1\r\n");
+        let expected = RespValue::Null;
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_message_null_array() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"*-1\r\n");
+        assert_eq!(decode(buf).unwrap(), RespValue::NullArray);
+    }
+
+    #[test]
+    fn test_parse_message_array() {
+        let buf: &[u8] = b"*3\r\n+Hello\r\n:42\r\n$5\r\nWorld\r\n";
+        let buf = &mut Cursor::new(buf);
+
+        let expected = RespValue::Array(vec![
+            RespValue::SimpleString("Hello".to_string()),
+            RespValue::Integer(42),
+            RespValue::BulkString(Bytes::from_static(b"World")),
+        ]);
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_simple_string() {
+        let val = RespValue::SimpleString("Hello, World!".to_string());
+        let expected = b"+Hello, World!\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_simple_error() {
+        let val = RespValue::SimpleError("Error occurred".to_string());
+        let expected = b"-Error occurred\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_integer() {
+        let val = RespValue::Integer(42);
+        let expected = b":42\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_bulk_string() {
+        let val = RespValue::BulkString(Bytes::from_static(b"Hello"));
+        let expected = b"$5\r\nHello\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_null() {
+        let val = RespValue::Null;
+        let expected = b"$-1\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_serialize_resp_value_to_buf_array() {
+        let val = RespValue::Array(vec![
+            RespValue::SimpleString("Hello".to_string()),
+            RespValue::Integer(42),
+            RespValue::BulkString(Bytes::from_static(b"World")),
+        ]);
+        let expected = b"*3\r\n+Hello\r\n:42\r\n$5\r\nWorld\r\n".to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+    }
+
+    #[test]
+    fn test_parse_resp3_types() {
+        let buf: &[u8] = b"%2\r\n+first\r\n,1.5\r\n$6\r\nsecond\r\n~2\r\n#t\r\n_\r\n\
+            >2\r\n(-12345678901234567890\r\n=8\r\ntxt:text\r\n\
+            |1\r\n+ttl\r\n:3600\r\n,-inf\r\n";
+        let buf = &mut Cursor::new(buf);
+
+        let expected = RespValue::Map(vec![
+            (
+                RespValue::SimpleString("first".to_string()),
+                RespValue::Double(1.5),
+            ),
+            (
+                RespValue::BulkString(Bytes::from_static(b"second")),
+                RespValue::Set(vec![RespValue::Boolean(true), RespValue::Null]),
+            ),
+        ]);
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        let expected = RespValue::Push(vec![
+            RespValue::BigNumber("-12345678901234567890".to_string()),
+            RespValue::VerbatimString("txt".to_string(), b"text".to_vec()),
+        ]);
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        let expected = RespValue::Attribute(
+            vec![(
+                RespValue::SimpleString("ttl".to_string()),
+                RespValue::Integer(3600),
+            )],
+            Box::new(RespValue::Double(f64::NEG_INFINITY)),
+        );
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        assert!(decode(&mut Cursor::new(b"#x\r\n")).is_err());
+        assert!(decode(&mut Cursor::new(b"(12a\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_serialize_resp3_types() {
+        let val = RespValue::Array(vec![
+            RespValue::Map(vec![(
+                RespValue::BulkString(Bytes::from_static(b"key")),
+                RespValue::Double(0.5),
+            )]),
+            RespValue::Set(vec![RespValue::Boolean(false)]),
+            RespValue::Null,
+            RespValue::NullArray,
+            RespValue::BigNumber("123".to_string()),
+            RespValue::VerbatimString("txt".to_string(), b"text".to_vec()),
+        ]);
+
+        let expected = b"*6\r\n%1\r\n$3\r\nkey\r\n,0.5\r\n~1\r\n#f\r\n_\r\n_\r\n\
+            (123\r\n=8\r\ntxt:text\r\n"
+            .to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp3), expected);
+
+        // RESP2 clients get the closest RESP2 types
+        let expected = b"*6\r\n*2\r\n$3\r\nkey\r\n$3\r\n0.5\r\n*1\r\n:0\r\n$-1\r\n*-1\r\n\
+            $3\r\n123\r\n$4\r\ntext\r\n"
+            .to_vec();
+        assert_eq!(encode_resp(&val, Protocol::Resp2), expected);
+
+        let val = RespValue::Push(vec![RespValue::Double(f64::INFINITY)]);
+        assert_eq!(
+            encode_resp(&val, Protocol::Resp3),
+            b">1\r\n,inf\r\n".to_vec()
+        );
+        assert_eq!(
+            encode_resp(&val, Protocol::Resp2),
+            b"*1\r\n$3\r\ninf\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_parse_message_invalid_type_prefix() {
+        // a line with an unknown prefix is an inline command, but not inside arrays
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"*1\r\n!Hello, World!\r\n");
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_missing_newline() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"+Hello, World!");
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_invalid_value() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b":Hello, World!\r\n");
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_invalid_value_bulk_string() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"$5\r\nHello");
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_invalid_value_bulk_string_null() {
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(b"$-2\r\n");
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_parse_message_invalid_value_array() {
+        let buf: &[u8] = b"*3\r\n+Hello\r\n:42\r\n$5\r\nWorld";
+        let buf = &mut Cursor::new(buf);
+
+        assert!(decode(buf).is_err());
+    }
+
+    #[test]
+    fn test_multiple_parse_message() {
+        let buf: &[u8] = b"+Hello, World!\r\n-Error occurred\r\n:42\r\n$5\r\nHello\r\n";
+        let buf = &mut Cursor::new(buf);
+
+        let expected = RespValue::SimpleString("Hello, World!".to_string());
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        let expected = RespValue::SimpleError("Error occurred".to_string());
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        let expected = RespValue::Integer(42);
+        assert_eq!(decode(buf).unwrap(), expected);
+
+        let expected = RespValue::BulkString(Bytes::from_static(b"Hello"));
+        assert_eq!(decode(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_rdb_file() {
+        let hex_string = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
+        let empty_file_payload = (0..hex_string.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex_string[i..i + 2], 16).expect("hex_string is invalid"))
+            .collect::<Vec<_>>();
+
+        let len = empty_file_payload.len();
+
+        let payload_len = format!("${}\r\n", len).as_bytes().to_vec();
+
+        let data = [payload_len, empty_file_payload].concat();
+
+        let buf: &mut Cursor<&[u8]> = &mut Cursor::new(&data);
+
+        let resp = decode(buf);
+
+        assert!(resp.is_ok());
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the unit tests.
+
+use bytes::Bytes;
+use clap::Parser;
+
+use crate::commands::Command;
+use crate::conf::Config;
+use crate::db::Db;
+use crate::resp::RespValue;
+use crate::Cli;
+
+/// Database of a server started with the default configuration.
+pub(crate) fn db() -> Db {
+    db_with(&[])
+}
+
+/// Database of a server started with the command line arguments `args`.
+pub(crate) fn db_with(args: &[&str]) -> Db {
+    let args = ["redis-clone"].iter().chain(args);
+    Db::new(Config::from(Cli::parse_from(args)))
+}
+
+/// Request sent by a client for the command `args`.
+pub(crate) fn request(args: &[&str]) -> RespValue {
+    RespValue::Array(
+        args.iter()
+            .map(|arg| RespValue::BulkString(Bytes::copy_from_slice(arg.as_bytes())))
+            .collect(),
+    )
+}
+
+pub(crate) fn command(args: &[&str]) -> Command {
+    Command::try_from(request(args)).unwrap()
+}
@@ -0,0 +1,573 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::commands::{Command, CommandTrait};
+use crate::db::Db;
+use crate::resp::RespValue;
+
+/// Executes a command outside of a transaction and propagates it to the
+/// replicas if it is a write.
+pub(crate) async fn execute_command(
+    command: &Command,
+    request: &RespValue,
+    db: &Db,
+) -> Option<RespValue> {
+    let _guard = db.lock_command().await;
+
+    if command.grows_memory() {
+        if let Err(e) = db.free_memory().await {
+            return Some(RespValue::SimpleError(e.to_string()));
+        }
+    }
+
+    let resp = command.execute(db).await;
+
+    if let Some(propagation) = command.propagation(request, resp.as_ref()) {
+        db.propagate(propagation);
+    }
+
+    resp
+}
+
+/// Switches the connection to database `index` for `SELECT`.
+fn select(db: &mut Db, index: usize) -> RespValue {
+    match db.select(index) {
+        Ok(selected) => {
+            *db = selected;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Err(e) => RespValue::SimpleError(e.to_string()),
+    }
+}
+
+/// Per-connection state of `MULTI`/`EXEC` transactions and `WATCH`ed keys
+#[derive(Default)]
+pub(crate) struct Transaction {
+    /// Commands queued since `MULTI` along with the requests they were parsed
+    /// from, `None` outside of a transaction
+    queue: Option<Vec<(Command, RespValue)>>,
+    /// Set when a command failed to be queued, `EXEC` then discards the transaction
+    failed: bool,
+    /// Watched keys along with the database they were watched in
+    watched: Vec<(Db, Bytes)>,
+    /// Set by the `Db` once one of the watched keys is modified
+    dirty: Arc<AtomicBool>,
+}
+
+impl Transaction {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn in_multi(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Marks the transaction as failed if a command could not be queued.
+    pub(crate) fn flag_failed(&mut self) {
+        if self.in_multi() {
+            self.failed = true;
+        }
+    }
+
+    /// Returns whether [`Transaction::execute`] runs `command` right away with
+    /// [`execute_command`], rather than queuing it or handling it itself.
+    pub(crate) fn executes_directly(&self, command: &Command) -> bool {
+        !self.in_multi()
+            && !matches!(
+                command,
+                Command::Multi
+                    | Command::Exec
+                    | Command::Discard
+                    | Command::Select(_)
+                    | Command::Watch(_)
+                    | Command::Unwatch
+            )
+    }
+
+    /// Returns the keys that [`Transaction::execute`] may use to run `command`,
+    /// `None` if it may use any key of the keyspace.
+    pub(crate) fn keys<'a>(&'a self, command: &'a Command) -> Option<Vec<&'a Bytes>> {
+        let watched = self.watched_keys().collect();
+
+        match command {
+            Command::Exec => {
+                let mut keys: Vec<_> = watched;
+
+                for (command, _) in self.queue.iter().flatten() {
+                    keys.extend(command.keys()?);
+                }
+
+                Some(keys)
+            }
+            Command::Discard | Command::Unwatch => Some(watched),
+            // queued commands only run with `EXEC`
+            _ if self.in_multi() => Some(vec![]),
+            command => command.keys(),
+        }
+    }
+
+    /// Returns the keys watched by the client.
+    pub(crate) fn watched_keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.watched.iter().map(|(_, key)| key)
+    }
+
+    /// Executes a command from the client on its selected database `db`. Inside
+    /// a transaction commands are queued until `EXEC` or `DISCARD`, other
+    /// commands are executed and propagated right away.
+    pub(crate) async fn execute(
+        &mut self,
+        command: Command,
+        request: &RespValue,
+        db: &mut Db,
+    ) -> Option<RespValue> {
+        match command {
+            Command::Multi if self.in_multi() => Some(RespValue::SimpleError(
+                "ERR MULTI calls can not be nested".to_string(),
+            )),
+            Command::Multi => {
+                self.queue = Some(vec![]);
+                Some(RespValue::SimpleString("OK".to_string()))
+            }
+            Command::Exec => Some(self.exec(db).await),
+            Command::Discard => Some(self.discard().await),
+            Command::Select(command) if !self.in_multi() => Some(select(db, command.index)),
+            Command::Watch(_) if self.in_multi() => {
+                self.failed = true;
+                Some(RespValue::SimpleError(
+                    "ERR WATCH inside MULTI is not allowed".to_string(),
+                ))
+            }
+            Command::Watch(command) => {
+                for key in command.keys {
+                    let watched = self.watched.iter().any(|(watched_db, watched)| {
+                        watched_db.index() == db.index() && *watched == key
+                    });
+
+                    if !watched {
+                        db.watch(&key, &self.dirty).await;
+                        self.watched.push((db.clone(), key));
+                    }
+                }
+
+                Some(RespValue::SimpleString("OK".to_string()))
+            }
+            // inside a transaction `UNWATCH` is queued, so that the watched keys
+            // are still checked by `EXEC`
+            Command::Unwatch if !self.in_multi() => {
+                self.unwatch().await;
+                Some(RespValue::SimpleString("OK".to_string()))
+            }
+            command => match &mut self.queue {
+                Some(queue) => {
+                    queue.push((command, request.clone()));
+                    Some(RespValue::SimpleString("QUEUED".to_string()))
+                }
+                None => execute_command(&command, request, db).await,
+            },
+        }
+    }
+
+    async fn exec(&mut self, db: &mut Db) -> RespValue {
+        let Some(queue) = self.queue.take() else {
+            return RespValue::SimpleError("ERR EXEC without MULTI".to_string());
+        };
+
+        if std::mem::take(&mut self.failed) {
+            self.unwatch().await;
+
+            return RespValue::SimpleError(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            );
+        }
+
+        let guard = db.lock_exec().await;
+
+        for (db, key) in &self.watched {
+            db.expire_watched(std::slice::from_ref(key)).await;
+        }
+
+        let dirty = self.dirty.load(Ordering::SeqCst);
+
+        self.unwatch().await;
+
+        if dirty {
+            return RespValue::NullArray;
+        }
+
+        if queue.iter().any(|(command, _)| command.grows_memory()) {
+            if let Err(e) = db.free_memory().await {
+                return RespValue::SimpleError(e.to_string());
+            }
+        }
+
+        let mut replies = Vec::with_capacity(queue.len());
+        let mut propagations = vec![];
+
+        // a `SELECT` in the transaction applies to the commands after it
+        let mut selected = db.clone();
+
+        for (command, request) in queue.iter() {
+            let reply = match command {
+                Command::Select(command) => select(&mut selected, command.index),
+                // the keys were already unwatched before running the commands
+                Command::Unwatch => RespValue::SimpleString("OK".to_string()),
+                command => command.execute(&selected).await.unwrap_or(RespValue::Null),
+            };
+
+            if let Some(propagation) = command.propagation(request, Some(&reply)) {
+                propagations.push((selected.clone(), propagation));
+            }
+
+            replies.push(reply);
+        }
+
+        // replicas apply the writes of the transaction atomically as well
+        if !propagations.is_empty() {
+            db.propagate(RespValue::Array(vec![RespValue::BulkString(
+                Bytes::from_static(b"MULTI"),
+            )]));
+
+            for (db, propagation) in propagations {
+                db.propagate(propagation);
+            }
+
+            selected.propagate(RespValue::Array(vec![RespValue::BulkString(
+                Bytes::from_static(b"EXEC"),
+            )]));
+        }
+
+        drop(guard);
+        *db = selected;
+
+        RespValue::Array(replies)
+    }
+
+    async fn discard(&mut self) -> RespValue {
+        if self.queue.take().is_none() {
+            return RespValue::SimpleError("ERR DISCARD without MULTI".to_string());
+        }
+
+        self.failed = false;
+        self.unwatch().await;
+
+        RespValue::SimpleString("OK".to_string())
+    }
+
+    /// Forgets all the watched keys, also called once the connection is closed.
+    pub(crate) async fn unwatch(&mut self) {
+        if self.watched.is_empty() {
+            return;
+        }
+
+        for (db, key) in self.watched.drain(..) {
+            db.unwatch(std::slice::from_ref(&key), &self.dirty).await;
+        }
+
+        self.dirty.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_utils::{db, request};
+
+    async fn send(transaction: &mut Transaction, db: &mut Db, args: &[&str]) -> Option<RespValue> {
+        let request = request(args);
+        let command = Command::try_from(request.clone()).unwrap();
+
+        transaction.execute(command, &request, db).await
+    }
+
+    fn ok() -> Option<RespValue> {
+        Some(RespValue::SimpleString("OK".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_exec_runs_queued_commands() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+        let mut replication = db.subscribe_replication();
+
+        assert_eq!(send(&mut transaction, &mut db, &["MULTI"]).await, ok());
+        assert_eq!(
+            send(&mut transaction, &mut db, &["SET", "key", "value"]).await,
+            Some(RespValue::SimpleString("QUEUED".to_string()))
+        );
+        send(&mut transaction, &mut db, &["GET", "key"]).await;
+        assert_eq!(db.get(b"key").await.unwrap(), None);
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::Array(vec![
+                RespValue::SimpleString("OK".to_string()),
+                RespValue::BulkString(Bytes::from_static(b"value")),
+            ]))
+        );
+
+        assert_eq!(replication.recv().await.unwrap(), request(&["SELECT", "0"]));
+        assert_eq!(replication.recv().await.unwrap(), request(&["MULTI"]));
+        assert_eq!(
+            replication.recv().await.unwrap(),
+            request(&["SET", "key", "value"])
+        );
+        assert_eq!(replication.recv().await.unwrap(), request(&["EXEC"]));
+    }
+
+    #[tokio::test]
+    async fn test_select_applies_to_later_commands() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+        let mut replication = db.subscribe_replication();
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["SELECT", "1"]).await,
+            ok()
+        );
+        assert!(matches!(
+            send(&mut transaction, &mut db, &["SELECT", "16"]).await,
+            Some(RespValue::SimpleError(_))
+        ));
+
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        send(&mut transaction, &mut db, &["SET", "key", "one"]).await;
+        send(&mut transaction, &mut db, &["SELECT", "2"]).await;
+        send(&mut transaction, &mut db, &["SET", "key", "two"]).await;
+        assert_eq!(db.index(), 1);
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::Array(vec![ok().unwrap(); 3]))
+        );
+
+        assert_eq!(db.index(), 2);
+        assert_eq!(db.get(b"key").await.unwrap(), Some("two".into()));
+        assert_eq!(
+            db.select(1).unwrap().get(b"key").await.unwrap(),
+            Some("one".into())
+        );
+        assert_eq!(db.select(0).unwrap().get(b"key").await.unwrap(), None);
+
+        for args in [
+            &["SELECT", "1"][..],
+            &["MULTI"],
+            &["SET", "key", "one"],
+            &["SELECT", "2"],
+            &["SET", "key", "two"],
+            &["EXEC"],
+        ] {
+            assert_eq!(replication.recv().await.unwrap(), request(args));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modified_watched_key_aborts_exec() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["WATCH", "key"]).await,
+            ok()
+        );
+
+        db.set("key".into(), "other".into(), None).await;
+
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        send(&mut transaction, &mut db, &["SET", "key", "value"]).await;
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::NullArray)
+        );
+        assert_eq!(db.get(b"key").await.unwrap(), Some("other".into()));
+
+        // keys are no longer watched after EXEC
+        send(&mut transaction, &mut db, &["WATCH", "key"]).await;
+        send(&mut transaction, &mut db, &["UNWATCH"]).await;
+        db.set("key".into(), "other".into(), None).await;
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        send(&mut transaction, &mut db, &["SET", "key", "value"]).await;
+        send(&mut transaction, &mut db, &["EXEC"]).await;
+
+        assert_eq!(db.get(b"key").await.unwrap(), Some("value".into()));
+    }
+
+    #[tokio::test]
+    async fn test_unwatch_inside_multi_is_queued() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+
+        send(&mut transaction, &mut db, &["WATCH", "key"]).await;
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        assert_eq!(
+            send(&mut transaction, &mut db, &["UNWATCH"]).await,
+            Some(RespValue::SimpleString("QUEUED".to_string()))
+        );
+        send(&mut transaction, &mut db, &["SET", "key", "value"]).await;
+
+        db.set("key".into(), "other".into(), None).await;
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::NullArray)
+        );
+        assert_eq!(db.get(b"key").await.unwrap(), Some("other".into()));
+    }
+
+    #[tokio::test]
+    async fn test_flush_and_expiry_dirty_watched_keys() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+
+        db.set(
+            "key".into(),
+            "value".into(),
+            Some(crate::utils::unix_millis() + 10),
+        )
+        .await;
+        send(&mut transaction, &mut db, &["WATCH", "key"]).await;
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::NullArray)
+        );
+
+        db.set("key".into(), "value".into(), None).await;
+        send(&mut transaction, &mut db, &["WATCH", "key"]).await;
+        db.flush(false, false).await;
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+
+        assert_eq!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::NullArray)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queueing_error_aborts_exec() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        send(&mut transaction, &mut db, &["SET", "key", "value"]).await;
+        transaction.flag_failed();
+
+        assert!(matches!(
+            send(&mut transaction, &mut db, &["EXEC"]).await,
+            Some(RespValue::SimpleError(e)) if e.starts_with("EXECABORT")
+        ));
+        assert!(!transaction.in_multi());
+        assert_eq!(db.get(b"key").await.unwrap(), None);
+        assert!(matches!(
+            send(&mut transaction, &mut db, &["DISCARD"]).await,
+            Some(RespValue::SimpleError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_queued_relative_expiry_starts_at_exec() {
+        let mut db = db();
+        let mut transaction = Transaction::new();
+        let mut replication = db.subscribe_replication();
+
+        send(&mut transaction, &mut db, &["MULTI"]).await;
+        send(&mut transaction, &mut db, &["SET", "a", "v", "PX", "1000"]).await;
+        send(&mut transaction, &mut db, &["PEXPIRE", "a", "2000"]).await;
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let exec = crate::utils::unix_millis();
+        send(&mut transaction, &mut db, &["EXEC"]).await;
+
+        let expires_at = db.expires_at(b"a").await.unwrap().unwrap();
+        assert!(expires_at >= exec + 2000);
+
+        // replicas get the time the command resolved
+        replication.recv().await.unwrap();
+        replication.recv().await.unwrap();
+        replication.recv().await.unwrap();
+        assert_eq!(
+            replication.recv().await.unwrap(),
+            request(&["PEXPIREAT", "a", &expires_at.to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_expiry_is_not_propagated() {
+        let db = db();
+        let mut replication = db.subscribe_replication();
+
+        let run = |args: &'static [&'static str]| {
+            let db = db.clone();
+            async move {
+                let request = request(args);
+                let command = Command::try_from(request.clone()).unwrap();
+                execute_command(&command, &request, &db).await
+            }
+        };
+
+        run(&["SET", "key", "value"]).await;
+        assert_eq!(run(&["PERSIST", "key"]).await, Some(RespValue::Integer(0)));
+        assert_eq!(
+            run(&["EXPIRE", "key", "100", "XX"]).await,
+            Some(RespValue::Integer(0))
+        );
+        run(&["EXPIRE", "key", "100", "NX"]).await;
+
+        assert_eq!(replication.recv().await.unwrap(), request(&["SELECT", "0"]));
+        assert!(matches!(
+            replication.recv().await.unwrap(),
+            RespValue::Array(args) if args[0] == RespValue::BulkString("SET".into())
+        ));
+        assert!(matches!(
+            replication.recv().await.unwrap(),
+            RespValue::Array(args) if args[0] == RespValue::BulkString("PEXPIREAT".into())
+        ));
+        assert!(replication.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keyspace_commands_changing_nothing_are_not_propagated() {
+        let db = db();
+        let mut replication = db.subscribe_replication();
+
+        for args in [
+            &["FLUSHALL"][..],
+            &["DEL", "missing"],
+            &["UNLINK", "missing"],
+            &["RENAME", "missing", "b"],
+            &["COPY", "missing", "b"],
+            &["MOVE", "missing", "1"],
+            &["SET", "a", "1"],
+            &["RENAME", "a", "a"],
+            &["SET", "b", "2"],
+            &["RENAMENX", "a", "b"],
+            &["COPY", "a", "b"],
+            &["DEL", "a", "missing"],
+        ] {
+            let request = request(args);
+            let command = Command::try_from(request.clone()).unwrap();
+            execute_command(&command, &request, &db).await;
+        }
+
+        let mut propagated = vec![];
+        while let Ok(command) = replication.try_recv() {
+            propagated.push(command);
+        }
+
+        assert_eq!(
+            propagated,
+            [
+                request(&["SELECT", "0"]),
+                request(&["SET", "a", "1"]),
+                request(&["SET", "b", "2"]),
+                request(&["DEL", "a", "missing"]),
+            ]
+        );
+    }
+}
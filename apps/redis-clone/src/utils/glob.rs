@@ -0,0 +1,221 @@
+//! Redis compatible glob-style pattern matching, as used by `KEYS`, `SCAN` and
+//! pattern subscriptions.
+//!
+//! Supported patterns:
+//! - `?` matches exactly one character
+//! - `*` matches any number of characters, including none
+//! - `[abc]` matches one of the characters, `[^a]` any character but `a` and
+//!   `[a-z]` a range of characters
+//! - `\` escapes the following character
+
+/// Limits the recursion depth of `*` so that malicious patterns can't blow the stack
+const MAX_NESTING: usize = 1000;
+
+/// Returns whether `string` matches the glob-style `pattern`.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+
+    match_impl(pattern, string, nocase, &mut skip_longer_matches, 0)
+}
+
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+fn match_impl(
+    mut pattern: &[u8],
+    mut string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    while !pattern.is_empty() && !string.is_empty() {
+        match pattern[0] {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+
+                if pattern.len() == 1 {
+                    return true;
+                }
+
+                while !string.is_empty() {
+                    if match_impl(
+                        &pattern[1..],
+                        string,
+                        nocase,
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+
+                    // a nested `*` already failed to match the rest of the string,
+                    // trying longer matches for this `*` can't succeed either
+                    if *skip_longer_matches {
+                        return false;
+                    }
+
+                    string = &string[1..];
+                }
+
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => {
+                string = &string[1..];
+            }
+            b'[' => {
+                pattern = &pattern[1..];
+
+                let not = pattern.first() == Some(&b'^');
+                if not {
+                    pattern = &pattern[1..];
+                }
+
+                let mut matched = false;
+
+                // an unterminated class is treated as if it was closed at the end
+                while !pattern.is_empty() && pattern[0] != b']' {
+                    if pattern[0] == b'\\' && pattern.len() >= 2 {
+                        pattern = &pattern[1..];
+                        matched |= pattern[0] == string[0];
+                    } else if pattern.len() >= 3 && pattern[1] == b'-' {
+                        let (mut start, mut end, mut c) = (pattern[0], pattern[2], string[0]);
+
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+
+                        if nocase {
+                            start = start.to_ascii_lowercase();
+                            end = end.to_ascii_lowercase();
+                            c = c.to_ascii_lowercase();
+                        }
+
+                        pattern = &pattern[2..];
+                        matched |= c >= start && c <= end;
+                    } else {
+                        matched |= eq(pattern[0], string[0], nocase);
+                    }
+
+                    pattern = &pattern[1..];
+                }
+
+                if matched == not {
+                    return false;
+                }
+
+                string = &string[1..];
+
+                if pattern.is_empty() {
+                    continue;
+                }
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+
+                if !eq(pattern[0], string[0], nocase) {
+                    return false;
+                }
+
+                string = &string[1..];
+            }
+            c => {
+                if !eq(c, string[0], nocase) {
+                    return false;
+                }
+
+                string = &string[1..];
+            }
+        }
+
+        pattern = &pattern[1..];
+    }
+
+    if string.is_empty() {
+        while pattern.first() == Some(&b'*') {
+            pattern = &pattern[1..];
+        }
+    }
+
+    pattern.is_empty() && string.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_glob_match_literal() {
+        assert!(matches("hello", "hello"));
+        assert!(!matches("hello", "hell"));
+        assert!(!matches("hell", "hello"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn test_glob_match_wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h*llo", "hllo"));
+        assert!(matches("h*llo", "heeeello"));
+        assert!(matches("user:*:name", "user:42:name"));
+        assert!(!matches("user:*:name", "user:42:email"));
+        assert!(matches("a**b***", "ab"));
+        assert!(!matches("a*b*c", "abab"));
+    }
+
+    #[test]
+    fn test_glob_match_classes() {
+        assert!(matches("h[ae]llo", "hello"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[b-a]llo", "hallo"));
+        assert!(!matches("h[a-b]llo", "hcllo"));
+        assert!(matches("h[\\]]llo", "h]llo"));
+        assert!(matches("h[ab", "ha"));
+    }
+
+    #[test]
+    fn test_glob_match_escapes() {
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+        assert!(matches("h\\?", "h?"));
+        assert!(matches("trailing\\", "trailing\\"));
+    }
+
+    #[test]
+    fn test_glob_match_nocase() {
+        assert!(glob_match(b"HeLLo", b"hello", true));
+        assert!(glob_match(b"h[A-Z]llo", b"hello", true));
+        assert!(!glob_match(b"HeLLo", b"hello", false));
+    }
+
+    #[test]
+    fn test_glob_match_pathological_pattern() {
+        let pattern = "a*".repeat(50) + "b";
+        let string = "a".repeat(100);
+
+        assert!(!matches(&pattern, &string));
+    }
+}
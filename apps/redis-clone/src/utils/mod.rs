@@ -1,3 +1,7 @@
+pub mod glob;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use rand::{thread_rng, Rng};
 
 pub fn random_string(length: usize) -> String {
@@ -8,6 +12,14 @@ pub fn random_string(length: usize) -> String {
         .collect()
 }
 
+/// Returns the current UNIX time in milliseconds.
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;